@@ -0,0 +1,100 @@
+// bridge.rs
+use crate::discover::DeviceKind;
+use clap::{Args, ValueEnum};
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use udp_core::{Bridge, BridgeRoute, Endpoint, TcpConfig, TcpTransport, UdpServer};
+
+#[derive(Args, Debug)]
+pub struct BridgeArgs {
+    /// 前端 UDP 监听端口
+    #[arg(short, long)]
+    port: u16,
+
+    /// 下游串口设备，如 /dev/ttyS1
+    #[arg(long, conflicts_with = "tcp", required_unless_present = "tcp")]
+    serial: Option<PathBuf>,
+
+    /// 串口波特率
+    #[arg(long, default_value_t = 115200)]
+    baud: u32,
+
+    /// 串口校验位
+    #[arg(long, value_enum, default_value_t = ParityArg::None)]
+    parity: ParityArg,
+
+    /// 串口停止位
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=2))]
+    stop_bits: u8,
+
+    /// 下游 TCP 地址
+    #[arg(long)]
+    tcp: Option<SocketAddr>,
+
+    /// 只转发指定类型设备的请求
+    #[arg(short, long)]
+    device_type: Option<DeviceKind>,
+
+    /// 只转发指定设备序号的请求
+    #[arg(short = 'i', long)]
+    device_index: Option<u16>,
+
+    /// 等待下游响应的时间（毫秒），超时后丢弃请求方映射
+    #[arg(long, default_value_t = 5000)]
+    response_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ParityArg {
+    None,
+    Even,
+    Odd,
+}
+
+#[cfg(target_os = "linux")]
+fn serial_endpoint(args: &BridgeArgs, path: &PathBuf) -> io::Result<Endpoint> {
+    use udp_core::{Parity, SerialConfig, SerialTransport, StopBits};
+
+    let parity = match args.parity {
+        ParityArg::None => Parity::None,
+        ParityArg::Even => Parity::Even,
+        ParityArg::Odd => Parity::Odd,
+    };
+    let stop_bits = if args.stop_bits == 2 { StopBits::Two } else { StopBits::One };
+    let config = SerialConfig::new(args.baud).with_parity(parity).with_stop_bits(stop_bits);
+    let transport = SerialTransport::open(path, &config)?;
+    Endpoint::new(path.display().to_string(), transport, path.clone())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn serial_endpoint(_args: &BridgeArgs, _path: &PathBuf) -> io::Result<Endpoint> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "串口下游仅支持 Linux"))
+}
+
+pub fn run(args: &BridgeArgs) -> io::Result<()> {
+    let endpoint = match (&args.serial, args.tcp) {
+        (Some(path), _) => serial_endpoint(args, path)?,
+        (None, Some(addr)) => Endpoint::new(addr.to_string(), TcpTransport::new(TcpConfig::default()), addr)?,
+        (None, None) => unreachable!("clap requires --serial or --tcp"),
+    };
+
+    let mut route = BridgeRoute::any();
+    if let Some(device_type) = args.device_type {
+        route = route.device_type(device_type.into());
+    }
+    if let Some(device_index) = args.device_index {
+        route = route.device_index(device_index);
+    }
+
+    let server = UdpServer::bind(args.port)?;
+    let handle = Bridge::new(server)
+        .with_response_timeout(Duration::from_millis(args.response_timeout_ms))
+        .route(route, &endpoint)
+        .start()?;
+    tracing::info!(port = args.port, endpoint = endpoint.name(), "bridge started");
+
+    handle.join().expect("网关线程异常退出");
+    Ok(())
+}
@@ -0,0 +1,108 @@
+// discover.rs
+use clap::{Args, ValueEnum};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use udp_core::discovery::DEFAULT_DISCOVERY_PORT;
+use udp_core::{DiscoveredDevice, MulticastInterface, Scanner, UdpClient};
+use udp_protocol::DeviceType;
+
+#[derive(Args, Debug)]
+pub struct DiscoverArgs {
+    /// 设备监听发现请求的端口
+    #[arg(short, long, default_value_t = DEFAULT_DISCOVERY_PORT)]
+    port: u16,
+
+    /// 发送目标（单播、广播或组播地址），默认使用受限广播 255.255.255.255
+    #[arg(short, long)]
+    target: Option<SocketAddr>,
+
+    /// 按 Layer2 组过滤（16 位十六进制），同时改为发送到该组映射的组播地址
+    #[arg(short, long, value_parser = parse_group)]
+    group: Option<[u8; 8]>,
+
+    /// 只发现指定类型的设备
+    #[arg(short, long)]
+    device_type: Option<DeviceKind>,
+
+    /// 组播使用的网卡地址
+    #[arg(short, long)]
+    interface: Option<Ipv4Addr>,
+
+    /// 等待响应的时间（毫秒）
+    #[arg(long, default_value_t = 1000)]
+    timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum DeviceKind {
+    Fpga,
+    Mcu,
+    Network,
+    Optical,
+}
+
+impl From<DeviceKind> for DeviceType {
+    fn from(kind: DeviceKind) -> Self {
+        match kind {
+            DeviceKind::Fpga => DeviceType::FPGA,
+            DeviceKind::Mcu => DeviceType::MCU,
+            DeviceKind::Network => DeviceType::NetworkPort,
+            DeviceKind::Optical => DeviceType::OpticalPort,
+        }
+    }
+}
+
+fn parse_group(s: &str) -> Result<[u8; 8], String> {
+    let bytes = hex::decode(s).map_err(|e| e.to_string())?;
+    bytes.try_into().map_err(|_| "组必须是 8 字节（16 位十六进制）".to_string())
+}
+
+pub fn run(args: &DiscoverArgs) -> io::Result<()> {
+    let client = UdpClient::new()?;
+    if let Some(interface) = args.interface {
+        client.set_multicast_interface(MulticastInterface::V4(interface))?;
+    }
+
+    let mut scanner = Scanner::new(client).with_timeout(Duration::from_millis(args.timeout_ms));
+    if let Some(device_type) = args.device_type {
+        scanner = scanner.with_device_type(device_type.into());
+    }
+    if let Some(group) = args.group {
+        scanner = scanner.with_group(group);
+    }
+
+    let devices = match (args.target, args.group) {
+        (Some(target), _) => scanner.scan(target)?,
+        (None, Some(_)) => scanner.scan_group(args.port)?,
+        (None, None) => scanner.scan_broadcast(args.port)?,
+    };
+    tracing::info!(devices = devices.len(), "discovery finished");
+    print_table(&devices);
+    Ok(())
+}
+
+fn print_table(devices: &[DiscoveredDevice]) {
+    if devices.is_empty() {
+        println!("No devices found");
+        return;
+    }
+
+    println!(
+        "{:<22} {:<12} {:>6} {:<16} {:<10} CAPABILITIES",
+        "ADDRESS", "TYPE", "INDEX", "GROUP", "FIRMWARE"
+    );
+    for device in devices {
+        let info = &device.info;
+        println!(
+            "{:<22} {:<12} {:>6} {:<16} {:<10} {}",
+            device.addr.to_string(),
+            format!("{:?}", info.device_type),
+            info.device_index,
+            hex::encode(info.group),
+            info.firmware.to_string(),
+            info.capabilities
+        );
+    }
+    println!("{} device(s) found", devices.len());
+}
@@ -1,3 +1,46 @@
-fn main() {
-    println!("Hello, world!");
+// main.rs
+use clap::{Parser, Subcommand};
+use udp_core::LogFormat;
+
+mod bridge;
+mod discover;
+mod progress;
+mod recv;
+mod send;
+
+/// 命令行参数解析
+#[derive(Parser, Debug)]
+#[command(name = "transfer-rs")]
+#[command(about = "设备通信工具", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// 日志输出格式（text 或 json），级别由 RUST_LOG 控制
+    #[arg(long, global = true, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 广播或组播发现请求，打印回复的设备列表
+    Discover(discover::DiscoverArgs),
+    /// 在 UDP 端口和串口或 TCP 下游之间转发帧
+    Bridge(bridge::BridgeArgs),
+    /// 可靠地发送文件，完成后由接收方校验 SHA-256
+    Send(send::SendArgs),
+    /// 接收一个文件并校验 SHA-256
+    Recv(recv::RecvArgs),
+}
+
+fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    udp_core::logging::init(cli.log_format);
+
+    match cli.command {
+        Command::Discover(args) => discover::run(&args),
+        Command::Bridge(args) => bridge::run(&args),
+        Command::Send(args) => send::run(&args),
+        Command::Recv(args) => recv::run(&args),
+    }
 }
@@ -0,0 +1,66 @@
+// progress.rs
+// send 和 recv 共用的进度输出
+use std::time::{Duration, Instant};
+use udp_core::{TransferProgress, TransferReport};
+
+// 两次进度输出之间的最短间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// 按固定间隔输出进度，避免每个确认都打印一行
+pub struct ProgressLog {
+    last: Option<Instant>,
+}
+
+impl ProgressLog {
+    pub fn new() -> Self {
+        ProgressLog { last: None }
+    }
+
+    pub fn update(&mut self, progress: &TransferProgress) {
+        let done = progress.bytes == progress.total;
+        if !done && self.last.is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        tracing::info!(
+            percent = format!("{:.1}", progress.fraction() * 100.0),
+            bytes = progress.bytes,
+            total = progress.total,
+            rate = format_rate(progress.throughput()),
+            retransmissions = progress.retransmissions,
+            "progress"
+        );
+    }
+}
+
+// 在标准输出打印传输结果
+pub fn print_report(verb: &str, report: &TransferReport) {
+    tracing::info!(
+        name = report.name,
+        size = report.size,
+        chunks = report.chunks,
+        elapsed_ms = report.elapsed.as_millis() as u64,
+        retransmissions = report.retransmissions,
+        "transfer finished"
+    );
+    println!(
+        "{} {} ({} bytes) in {:.2}s, {}",
+        verb,
+        report.name,
+        report.size,
+        report.elapsed.as_secs_f64(),
+        format_rate(report.throughput())
+    );
+    println!("sha256 {}", hex::encode(report.sha256));
+}
+
+fn format_rate(bytes_per_sec: f64) -> String {
+    const UNITS: [&str; 4] = ["B/s", "KiB/s", "MiB/s", "GiB/s"];
+    let mut rate = bytes_per_sec;
+    let mut unit = 0;
+    while rate >= 1024.0 && unit < UNITS.len() - 1 {
+        rate /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", rate, UNITS[unit])
+}
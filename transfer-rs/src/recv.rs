@@ -0,0 +1,70 @@
+// recv.rs
+use crate::progress::{ProgressLog, print_report};
+use clap::Args;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use udp_core::{FileReceiver, UdpServer};
+
+#[derive(Args, Debug)]
+pub struct RecvArgs {
+    /// 监听端口
+    #[arg(short, long)]
+    port: u16,
+
+    /// 保存文件的目录
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+
+    /// 等待发送方发起传输的时间（秒）
+    #[arg(long, default_value_t = 300)]
+    wait_secs: u64,
+
+    /// 传输开始后多久收不到数据即放弃（毫秒）
+    #[arg(long, default_value_t = 10000)]
+    idle_timeout_ms: u64,
+}
+
+pub fn run(args: &RecvArgs) -> io::Result<()> {
+    let mut progress = ProgressLog::new();
+    let mut receiver = FileReceiver::new(UdpServer::bind(args.port)?)
+        .with_idle_timeout(Duration::from_millis(args.idle_timeout_ms))
+        .on_progress(move |p| progress.update(p));
+    tracing::info!(port = args.port, output = %args.output.display(), "waiting for a file");
+
+    // 先写入临时文件，摘要校验通过后再改名
+    let mut target = None;
+    let result = receiver.receive(Duration::from_secs(args.wait_secs), |offer| {
+        let path = output_path(&args.output, &offer.name)?;
+        tracing::info!(peer = %offer.peer, name = offer.name, size = offer.size, "receiving file");
+        let mut partial = path.clone().into_os_string();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        let file = File::create(&partial)?;
+        target = Some((partial, path));
+        Ok(BufWriter::new(file))
+    });
+
+    match (result, target) {
+        (Ok(report), Some((partial, path))) => {
+            fs::rename(&partial, &path)?;
+            print_report("received", &report);
+            Ok(())
+        }
+        (result, target) => {
+            if let Some((partial, _)) = target {
+                let _ = fs::remove_file(partial);
+            }
+            result.map(|_| ())
+        }
+    }
+}
+
+// 只取发送方文件名的最后一段，防止写到输出目录之外
+fn output_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let file_name = Path::new(name)
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid file name {:?}", name)))?;
+    Ok(dir.join(file_name))
+}
@@ -0,0 +1,42 @@
+// send.rs
+use crate::progress::{ProgressLog, print_report};
+use clap::Args;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use udp_core::transfer::DEFAULT_CHUNK_SIZE;
+use udp_core::{FileSender, ReliableConfig, UdpClient};
+
+#[derive(Args, Debug)]
+pub struct SendArgs {
+    /// 要发送的文件
+    file: PathBuf,
+
+    /// 接收方地址，如 192.168.1.10:58100
+    addr: SocketAddr,
+
+    /// 数据块大小（字节，最大 4096）
+    #[arg(long, default_value_t = DEFAULT_CHUNK_SIZE)]
+    chunk_size: usize,
+
+    /// 未确认数据块的最大个数
+    #[arg(long, default_value_t = ReliableConfig::default().window_size)]
+    window: u16,
+}
+
+pub fn run(args: &SendArgs) -> io::Result<()> {
+    let config = ReliableConfig {
+        window_size: args.window,
+        ..ReliableConfig::default()
+    };
+    let mut progress = ProgressLog::new();
+    let mut sender = FileSender::new(UdpClient::new()?)
+        .with_config(config)
+        .with_chunk_size(args.chunk_size)
+        .on_progress(move |p| progress.update(p));
+
+    tracing::info!(file = %args.file.display(), addr = %args.addr, "sending file");
+    let report = sender.send_file(args.addr, &args.file)?;
+    print_report("sent", &report);
+    Ok(())
+}
@@ -0,0 +1,468 @@
+// batch.rs
+// 批量收发：Linux 上使用 recvmmsg/sendmmsg 一次系统调用处理多个数据报，其他平台逐个收发
+use crate::UdpClient;
+use crate::error::TruncatedDatagram;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// 默认每批数据报个数
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// 批量接收缓冲区，可重复使用
+///
+/// 开启 GRO 时一个缓冲区可能包含多个合并的数据报，这里会按分段大小拆回单个数据报。
+pub struct RecvBatch<A = SocketAddr> {
+    bufs: Vec<Vec<u8>>,
+    entries: Vec<Entry<A>>,
+    // 单个数据报的最大长度，不超过缓冲区大小
+    max_len: usize,
+}
+
+// 一个数据报在缓冲区中的位置
+#[derive(Debug, Clone)]
+struct Entry<A> {
+    addr: A,
+    buf: usize,
+    offset: usize,
+    len: usize,
+    truncated: Option<TruncatedDatagram>,
+}
+
+impl<A: Clone> RecvBatch<A> {
+    // batch_size 个缓冲区，每个 buf_size 字节
+    pub fn new(batch_size: usize, buf_size: usize) -> Self {
+        RecvBatch {
+            bufs: vec![vec![0; buf_size]; batch_size.max(1)],
+            entries: Vec::with_capacity(batch_size.max(1)),
+            max_len: buf_size,
+        }
+    }
+
+    // 单个数据报的最大长度，超过的按截断处理。缓冲区比它大时（如开启 GRO）同样生效，
+    // 合并的数据报拆开后逐个检查
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_len = size.min(self.bufs[0].len());
+        self
+    }
+
+    // 每批最多接收的缓冲区个数
+    pub fn capacity(&self) -> usize {
+        self.bufs.len()
+    }
+
+    // 本批实际收到的数据报个数（GRO 合并的数据报已拆开计数）
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<(A, &[u8])> {
+        self.entries.get(index).map(|entry| self.slice(entry))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (A, &[u8])> {
+        self.entries.iter().map(|entry| self.slice(entry))
+    }
+
+    // 第 index 个数据报是否超过缓冲区被截断，截断的数据报只包含缓冲区能容纳的部分
+    pub fn truncation(&self, index: usize) -> Option<TruncatedDatagram> {
+        self.entries.get(index).and_then(|entry| entry.truncated)
+    }
+
+    fn slice(&self, entry: &Entry<A>) -> (A, &[u8]) {
+        (entry.addr.clone(), &self.bufs[entry.buf][entry.offset..entry.offset + entry.len])
+    }
+
+    // 清空上一批的记录，缓冲区保留复用
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn buf_mut(&mut self, buf: usize) -> &mut [u8] {
+        &mut self.bufs[buf]
+    }
+
+    // 单个数据报的最大长度
+    pub(crate) fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    // 给拿不到实际长度的接收用：缓冲区比最大长度多留一个字节，收到超过最大长度的数据才算截断，
+    // 正好等于最大长度的数据报完整保留。只在第一次使用时扩容
+    pub(crate) fn spare_buf_mut(&mut self, buf: usize) -> &mut [u8] {
+        if self.bufs[buf].len() <= self.max_len {
+            self.bufs[buf].resize(self.max_len + 1, 0);
+        }
+        &mut self.bufs[buf]
+    }
+
+    // 记录第 buf 个缓冲区收到的 len 字节，segment_size 不为0时按分段拆开
+    pub(crate) fn push(&mut self, addr: A, buf: usize, len: usize, segment_size: usize) {
+        if segment_size == 0 || segment_size >= len {
+            self.push_entry(addr, buf, 0, len);
+            return;
+        }
+        for offset in (0..len).step_by(segment_size) {
+            self.push_entry(addr.clone(), buf, offset, segment_size.min(len - offset));
+        }
+    }
+
+    // 超过最大长度的数据报只保留能容纳的部分并标记截断
+    fn push_entry(&mut self, addr: A, buf: usize, offset: usize, len: usize) {
+        let truncated = (len > self.max_len).then_some(TruncatedDatagram {
+            len: Some(len),
+            max: self.max_len,
+        });
+        self.entries.push(Entry {
+            addr,
+            buf,
+            offset,
+            len: len.min(self.max_len),
+            truncated,
+        });
+    }
+
+    // 记录一个被截断的数据报，real_len 为平台报告的实际长度
+    pub(crate) fn push_truncated(&mut self, addr: A, buf: usize, real_len: Option<usize>) {
+        let max = self.max_len;
+        self.entries.push(Entry {
+            addr,
+            buf,
+            offset: 0,
+            len: max,
+            truncated: Some(TruncatedDatagram { len: real_len, max }),
+        });
+    }
+}
+
+// 批量接收，阻塞直到至少收到一个数据报（遵循 socket 的读超时），之后只取已经到达的数据报
+pub fn recv_batch(socket: &UdpSocket, batch: &mut RecvBatch) -> io::Result<usize> {
+    batch.entries.clear();
+    sys::recv_batch(socket, batch)?;
+    Ok(batch.entries.len())
+}
+
+// 接收单个数据报，超过缓冲区长度时返回 TruncatedDatagram 错误而不是静默截断
+pub fn recv_from_checked(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    sys::recv_from_checked(socket, buf)
+}
+
+// 批量发送，返回发送成功的数据报个数
+pub fn send_batch(socket: &UdpSocket, msgs: &[(SocketAddr, &[u8])]) -> io::Result<usize> {
+    let mut sent = 0;
+    while sent < msgs.len() {
+        sent += sys::send_batch(socket, &msgs[sent..])?;
+    }
+    Ok(sent)
+}
+
+impl UdpClient {
+    // 把多条消息批量发送到同一个地址，设置了 pacer 时每条消息都先取得令牌再一次性发出
+    pub fn send_batch(&self, addr: SocketAddr, msgs: &[&[u8]]) -> io::Result<usize> {
+        for msg in msgs {
+            self.pace(msg.len());
+        }
+        let msgs: Vec<(SocketAddr, &[u8])> = msgs.iter().map(|msg| (addr, *msg)).collect();
+        let sent = send_batch(&self.socket, &msgs)?;
+        for (_, msg) in &msgs[..sent] {
+            self.record_sent(msg.len());
+        }
+        Ok(sent)
+    }
+
+    // 批量接收回包，最多等待 timeout
+    pub fn recv_batch(&self, batch: &mut RecvBatch, timeout: Duration) -> io::Result<usize> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        recv_batch(&self.socket, batch)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::{RecvBatch, TruncatedDatagram};
+    use socket2::{SockAddr, SockAddrStorage};
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::{mem, ptr};
+
+    // 每个数据报的控制消息缓冲区，按 u64 对齐
+    const CONTROL_WORDS: usize = 8;
+
+    pub(super) fn recv_batch(socket: &UdpSocket, batch: &mut RecvBatch) -> io::Result<()> {
+        let count = batch.bufs.len();
+        let mut addrs: Vec<SockAddrStorage> = (0..count).map(|_| SockAddrStorage::zeroed()).collect();
+        let mut controls = vec![[0u64; CONTROL_WORDS]; count];
+        let mut iovecs: Vec<libc::iovec> = batch
+            .bufs
+            .iter_mut()
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+        let mut msgs: Vec<libc::mmsghdr> = iovecs
+            .iter_mut()
+            .zip(addrs.iter_mut())
+            .zip(controls.iter_mut())
+            .map(|((iov, addr), control)| {
+                // SAFETY: msghdr 全零是合法的初始值
+                let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+                hdr.msg_name = addr as *mut SockAddrStorage as *mut libc::c_void;
+                hdr.msg_namelen = addr.size_of();
+                hdr.msg_iov = iov;
+                hdr.msg_iovlen = 1;
+                hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+                hdr.msg_controllen = mem::size_of_val(control);
+                libc::mmsghdr { msg_hdr: hdr, msg_len: 0 }
+            })
+            .collect();
+
+        // MSG_WAITFORONE：第一个数据报按 socket 设置阻塞，之后只取已经到达的
+        // MSG_TRUNC：数据报被截断时 msg_len 返回实际长度
+        // SAFETY: msgs 中的指针指向本函数内存活的 addrs/controls/iovecs 和 batch 的缓冲区
+        let received = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                msgs.as_mut_ptr(),
+                count as libc::c_uint,
+                libc::MSG_WAITFORONE | libc::MSG_TRUNC,
+                ptr::null_mut(),
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for (index, (msg, addr)) in msgs.iter().zip(addrs).take(received as usize).enumerate() {
+            // SAFETY: 内核已经写入了 msg_namelen 字节的地址
+            let addr = unsafe { SockAddr::new(addr, msg.msg_hdr.msg_namelen) };
+            let addr = addr
+                .as_socket()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported address family"))?;
+            if msg.msg_hdr.msg_flags & libc::MSG_TRUNC != 0 {
+                batch.push_truncated(addr, index, Some(msg.msg_len as usize));
+                continue;
+            }
+            let segment_size = gro_segment_size(&msg.msg_hdr);
+            batch.push(addr, index, msg.msg_len as usize, segment_size);
+        }
+        Ok(())
+    }
+
+    pub(super) fn recv_from_checked(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut addr = SockAddrStorage::zeroed();
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        // SAFETY: msghdr 全零是合法的初始值，其中的指针都指向本函数内存活的数据
+        let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+        hdr.msg_name = &mut addr as *mut SockAddrStorage as *mut libc::c_void;
+        hdr.msg_namelen = addr.size_of();
+        hdr.msg_iov = &mut iov;
+        hdr.msg_iovlen = 1;
+
+        // SAFETY: 同上
+        let received = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut hdr, libc::MSG_TRUNC) };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if hdr.msg_flags & libc::MSG_TRUNC != 0 {
+            return Err(TruncatedDatagram {
+                len: Some(received as usize),
+                max: buf.len(),
+            }
+            .into());
+        }
+
+        // SAFETY: 内核已经写入了 msg_namelen 字节的地址
+        let addr = unsafe { SockAddr::new(addr, hdr.msg_namelen) };
+        let addr = addr
+            .as_socket()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported address family"))?;
+        Ok((received as usize, addr))
+    }
+
+    // 读取 UDP_GRO 控制消息中的分段大小，没有合并时返回0
+    fn gro_segment_size(hdr: &libc::msghdr) -> usize {
+        // SAFETY: hdr 是 recvmmsg 填写过的消息头，控制消息缓冲区仍然有效
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(hdr);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO {
+                    return ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int) as usize;
+                }
+                cmsg = libc::CMSG_NXTHDR(hdr, cmsg);
+            }
+        }
+        0
+    }
+
+    pub(super) fn send_batch(socket: &UdpSocket, msgs: &[(SocketAddr, &[u8])]) -> io::Result<usize> {
+        let addrs: Vec<SockAddr> = msgs.iter().map(|(addr, _)| SockAddr::from(*addr)).collect();
+        let mut iovecs: Vec<libc::iovec> = msgs
+            .iter()
+            .map(|(_, data)| libc::iovec {
+                iov_base: data.as_ptr() as *mut libc::c_void,
+                iov_len: data.len(),
+            })
+            .collect();
+        let mut hdrs: Vec<libc::mmsghdr> = iovecs
+            .iter_mut()
+            .zip(&addrs)
+            .map(|(iov, addr)| {
+                // SAFETY: msghdr 全零是合法的初始值
+                let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+                hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+                hdr.msg_namelen = addr.len();
+                hdr.msg_iov = iov;
+                hdr.msg_iovlen = 1;
+                libc::mmsghdr { msg_hdr: hdr, msg_len: 0 }
+            })
+            .collect();
+
+        // SAFETY: hdrs 中的指针指向本函数内存活的 addrs/iovecs 和调用方的数据
+        let sent = unsafe { libc::sendmmsg(socket.as_raw_fd(), hdrs.as_mut_ptr(), hdrs.len() as libc::c_uint, 0) };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(sent as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::{RecvBatch, TruncatedDatagram};
+    use std::cell::RefCell;
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+
+    thread_local! {
+        // recv_from_checked 多留一个字节用的接收缓冲区，每个线程复用
+        static SPARE: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // 没有 recvmmsg 的平台每次只接收一个数据报
+    // 无法得到 MSG_TRUNC，缓冲区比最大长度多留一个字节，正好等于最大长度的数据报和 Linux 上一样完整返回
+    pub(super) fn recv_batch(socket: &UdpSocket, batch: &mut RecvBatch) -> io::Result<()> {
+        let (num_bytes, src_addr) = socket.recv_from(batch.spare_buf_mut(0))?;
+        if num_bytes > batch.max_len() {
+            batch.push_truncated(src_addr, 0, None);
+        } else {
+            batch.push(src_addr, 0, num_bytes, 0);
+        }
+        Ok(())
+    }
+
+    pub(super) fn recv_from_checked(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        SPARE.with_borrow_mut(|spare| {
+            spare.resize(buf.len() + 1, 0);
+            let (num_bytes, src_addr) = socket.recv_from(spare)?;
+            if num_bytes > buf.len() {
+                return Err(TruncatedDatagram { len: None, max: buf.len() }.into());
+            }
+            buf[..num_bytes].copy_from_slice(&spare[..num_bytes]);
+            Ok((num_bytes, src_addr))
+        })
+    }
+
+    pub(super) fn send_batch(socket: &UdpSocket, msgs: &[(SocketAddr, &[u8])]) -> io::Result<usize> {
+        for (addr, data) in msgs {
+            socket.send_to(data, addr)?;
+        }
+        Ok(msgs.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_round_trip() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = receiver.local_addr().unwrap();
+
+        let client = UdpClient::new().unwrap();
+        let payloads: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 100 + i as usize]).collect();
+        let msgs: Vec<&[u8]> = payloads.iter().map(|p| p.as_slice()).collect();
+        assert_eq!(client.send_batch(addr, &msgs).unwrap(), 20);
+
+        let mut batch = RecvBatch::new(8, 2048);
+        let mut received = Vec::new();
+        while received.len() < 20 {
+            let count = recv_batch(&receiver, &mut batch).unwrap();
+            assert!(count >= 1 && count <= batch.capacity());
+            for (src_addr, data) in batch.iter() {
+                assert_eq!(src_addr.port(), client.local_addr().unwrap().port());
+                received.push(data.to_vec());
+            }
+        }
+        assert_eq!(received, payloads);
+    }
+
+    #[test]
+    fn test_split_coalesced_buffer() {
+        let mut batch = RecvBatch::new(2, 64);
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        batch.push(addr, 0, 25, 10);
+        batch.push(addr, 1, 8, 10);
+        let lens: Vec<usize> = batch.iter().map(|(_, data)| data.len()).collect();
+        assert_eq!(lens, vec![10, 10, 5, 8]);
+    }
+
+    #[test]
+    fn test_max_datagram_size_checked_per_segment() {
+        // 缓冲区 64 字节，但单个数据报最多 8 字节
+        let mut batch = RecvBatch::new(2, 64).with_max_datagram_size(8);
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        batch.push(addr, 0, 25, 10);
+        batch.push(addr, 1, 6, 0);
+        let lens: Vec<usize> = batch.iter().map(|(_, data)| data.len()).collect();
+        assert_eq!(lens, vec![8, 8, 5, 6]);
+        let truncated = TruncatedDatagram { len: Some(10), max: 8 };
+        assert_eq!(batch.truncation(0), Some(truncated));
+        assert_eq!(batch.truncation(1), Some(truncated));
+        assert_eq!((batch.truncation(2), batch.truncation(3)), (None, None));
+    }
+
+    #[test]
+    fn test_truncation_detected() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let client = UdpClient::new().unwrap();
+
+        client.send_only(addr, &[0xAB; 300]).unwrap();
+        let mut buf = [0u8; 100];
+        let err = recv_from_checked(&receiver, &mut buf).unwrap_err();
+        assert!(crate::is_truncated(&err));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        client.send_only(addr, &[0xCD; 300]).unwrap();
+        client.send_only(addr, &[0xEF; 50]).unwrap();
+        let mut batch = RecvBatch::new(4, 100);
+        let mut truncations = Vec::new();
+        while truncations.len() < 2 {
+            recv_batch(&receiver, &mut batch).unwrap();
+            truncations.extend((0..batch.len()).map(|i| batch.truncation(i)));
+        }
+        assert_eq!(truncations[0], Some(TruncatedDatagram { len: Some(300), max: 100 }));
+        assert_eq!(truncations[1], None);
+    }
+
+    #[test]
+    fn test_recv_batch_timeout() {
+        let client = UdpClient::new().unwrap();
+        let mut batch = RecvBatch::new(4, 64);
+        let err = client.recv_batch(&mut batch, Duration::from_millis(50)).unwrap_err();
+        assert!(crate::retry::is_timeout(&err));
+        assert!(batch.is_empty());
+    }
+}
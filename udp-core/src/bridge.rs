@@ -0,0 +1,591 @@
+// bridge.rs
+// 网关：在前端（通常是 UDP）和下游链路（串口、TCP 等）之间转发校验通过的帧。
+// 请求帧按设备类型和设备序号路由到下游端点，序列号改写为端点自己的序列号；
+// 下游的响应按序列号找回原始请求方，恢复原序列号后从前端发回。
+use crate::metrics::TransportMetrics;
+use crate::retry::is_timeout;
+use crate::transport::DatagramTransport;
+use crate::{ServerHandle, ServerStats, UdpServer};
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use udp_protocol::{DeviceType, Layer1Protocol, Layer2Protocol, ReqRsp, try_decapsulate_data};
+
+// 端点接收线程检查停止标志的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// 默认的等待下游响应时间，超时后丢弃映射
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type FrameSender = Box<dyn Fn(&[u8]) -> io::Result<usize> + Send + Sync>;
+type ResponseSink = Arc<dyn Fn(&[u8]) + Send + Sync>;
+type ReaderSpawner = Box<dyn FnOnce(ResponseSink, Arc<AtomicBool>) -> JoinHandle<()> + Send>;
+
+struct EndpointInner {
+    name: String,
+    send: FrameSender,
+    reader: Mutex<Option<ReaderSpawner>>,
+    next_seq: AtomicU16,
+}
+
+/// 下游端点，克隆后可用于多条路由
+#[derive(Clone)]
+pub struct Endpoint {
+    inner: Arc<EndpointInner>,
+}
+
+impl Endpoint {
+    // 通过 transport 和 target 收发的端点，name 用于日志
+    pub fn new<T: DatagramTransport>(name: impl Into<String>, transport: T, target: T::Addr) -> io::Result<Self> {
+        let reader = transport.try_clone()?;
+        reader.set_read_timeout(Some(POLL_INTERVAL))?;
+        let spawn: ReaderSpawner = Box::new(move |sink, running| {
+            thread::spawn(move || {
+                let mut buf = vec![0; crate::MAX_DATAGRAM_SIZE];
+                while running.load(Ordering::Relaxed) {
+                    match reader.recv_from_checked(&mut buf) {
+                        Ok((num_bytes, _)) => sink(&buf[..num_bytes]),
+                        Err(e) if is_timeout(&e) => {}
+                        // 避免链路异常时空转
+                        Err(_) => thread::sleep(POLL_INTERVAL),
+                    }
+                }
+            })
+        });
+        Ok(Endpoint {
+            inner: Arc::new(EndpointInner {
+                name: name.into(),
+                send: Box::new(move |frame| transport.send_to(frame, &target)),
+                reader: Mutex::new(Some(spawn)),
+                next_seq: AtomicU16::new(rand::random()),
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+}
+
+/// 网关路由条件，None 表示匹配任意值
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BridgeRoute {
+    pub device_type: Option<DeviceType>,
+    pub device_index: Option<u16>,
+}
+
+impl BridgeRoute {
+    // 匹配所有设备
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    pub fn device_type(mut self, device_type: DeviceType) -> Self {
+        self.device_type = Some(device_type);
+        self
+    }
+
+    pub fn device_index(mut self, device_index: u16) -> Self {
+        self.device_index = Some(device_index);
+        self
+    }
+
+    fn matches(&self, layer2: &Layer2Protocol) -> bool {
+        self.device_type.is_none_or(|t| t == layer2.device_type)
+            && self.device_index.is_none_or(|i| i == layer2.device_index)
+    }
+
+    fn specificity(&self) -> u8 {
+        self.device_type.is_some() as u8 + self.device_index.is_some() as u8
+    }
+}
+
+/// 网关计数器
+#[derive(Debug, Default)]
+pub struct BridgeStats {
+    forwarded: AtomicU64,
+    responses: AtomicU64,
+    invalid: AtomicU64,
+    unrouted: AtomicU64,
+    unmatched: AtomicU64,
+    expired: AtomicU64,
+    send_errors: AtomicU64,
+}
+
+impl BridgeStats {
+    // 转发到下游的请求
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded.load(Ordering::Relaxed)
+    }
+
+    // 发回请求方的响应
+    pub fn responses(&self) -> u64 {
+        self.responses.load(Ordering::Relaxed)
+    }
+
+    // 两侧收到的无法解析的帧
+    pub fn invalid(&self) -> u64 {
+        self.invalid.load(Ordering::Relaxed)
+    }
+
+    // 没有匹配路由的请求，以及前端收到的响应帧
+    pub fn unrouted(&self) -> u64 {
+        self.unrouted.load(Ordering::Relaxed)
+    }
+
+    // 下游发来的找不到对应请求的帧，以及下游发来的非响应帧
+    pub fn unmatched(&self) -> u64 {
+        self.unmatched.load(Ordering::Relaxed)
+    }
+
+    // 超时未收到响应而丢弃的请求映射
+    pub fn expired(&self) -> u64 {
+        self.expired.load(Ordering::Relaxed)
+    }
+
+    pub fn send_errors(&self) -> u64 {
+        self.send_errors.load(Ordering::Relaxed)
+    }
+}
+
+// 等待响应的请求：原始请求方和原始序列号
+struct PendingRequest<A> {
+    requester: A,
+    seq: u16,
+    sent_at: Instant,
+}
+
+// 每个端点一张以端点序列号为键的映射表
+type PendingTable<A> = Mutex<HashMap<u16, PendingRequest<A>>>;
+
+/// 网关
+pub struct Bridge<T: DatagramTransport = UdpSocket> {
+    front: UdpServer<T>,
+    workers: usize,
+    response_timeout: Duration,
+    routes: Vec<(BridgeRoute, Endpoint)>,
+}
+
+impl<T: DatagramTransport> Bridge<T> {
+    pub fn new(front: UdpServer<T>) -> Self {
+        Bridge {
+            front,
+            workers: 1,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn with_response_timeout(mut self, timeout: Duration) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    // 添加路由；多个路由匹配时选条件最多的，条件相同时选先添加的
+    pub fn route(mut self, route: BridgeRoute, endpoint: &Endpoint) -> Self {
+        self.routes.push((route, endpoint.clone()));
+        self
+    }
+
+    pub fn start(self) -> io::Result<BridgeHandle<T::Addr>> {
+        let mut routes = self.routes;
+        routes.sort_by_key(|(route, _)| std::cmp::Reverse(route.specificity()));
+
+        // 去重后的端点，同一个端点只启动一个接收线程、只有一张映射表
+        let mut endpoints: Vec<Endpoint> = Vec::new();
+        for (_, endpoint) in &routes {
+            if !endpoints.iter().any(|e| Arc::ptr_eq(&e.inner, &endpoint.inner)) {
+                endpoints.push(endpoint.clone());
+            }
+        }
+        let routes: Vec<(BridgeRoute, usize)> = routes
+            .iter()
+            .map(|(route, endpoint)| {
+                let index = endpoints.iter().position(|e| Arc::ptr_eq(&e.inner, &endpoint.inner)).unwrap();
+                (*route, index)
+            })
+            .collect();
+        let pending: Arc<Vec<PendingTable<T::Addr>>> =
+            Arc::new(endpoints.iter().map(|_| Mutex::new(HashMap::new())).collect());
+
+        let stats = Arc::new(BridgeStats::default());
+        let running = Arc::new(AtomicBool::new(true));
+        let front = self.front.socket.try_clone()?;
+        let response_timeout = self.response_timeout;
+        let auto_dscp = self.front.auto_dscp;
+        let metrics = self.front.metrics.clone();
+
+        let mut readers = Vec::with_capacity(endpoints.len());
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            let Some(spawn) = endpoint.inner.reader.lock().unwrap().take() else {
+                running.store(false, Ordering::Relaxed);
+                return Err(io::Error::other(format!("Endpoint {} is already used by another bridge", endpoint.name())));
+            };
+            let front = front.try_clone()?;
+            let pending = pending.clone();
+            let stats = stats.clone();
+            let metrics = metrics.clone();
+            // 单个响应处理出错时只计为无效帧，不让端点接收线程退出
+            let sink: ResponseSink = Arc::new(move |data| {
+                let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                    respond(&front, auto_dscp, metrics.as_ref(), &pending[index], &stats, data)
+                }));
+                if handled.is_err() {
+                    tracing::warn!(len = data.len(), "endpoint response handling panicked");
+                    stats.invalid.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+            readers.push(spawn(sink, running.clone()));
+        }
+
+        let request_stats = stats.clone();
+        let started = self.front.start_pool(self.workers, move |src_addr, data, _reply| {
+            let (mut layer1, layer2) = match try_decapsulate_data(data) {
+                Ok((layer1, layer2, _)) => (layer1, layer2),
+                Err(e) => {
+                    tracing::debug!(peer = ?src_addr, error = e.name(), "dropping undecodable request");
+                    request_stats.invalid.fetch_add(1, Ordering::Relaxed);
+                    if let Some(metrics) = &metrics {
+                        metrics.decode_failure(e.name());
+                    }
+                    return;
+                }
+            };
+            let span = tracing::debug_span!(
+                "bridge_request",
+                peer = ?src_addr,
+                seq = layer1.frame_seq_number,
+                device_type = ?layer2.device_type,
+                device_index = layer2.device_index,
+            );
+            let _enter = span.enter();
+            let route = (layer2.req_rsp == ReqRsp::Request)
+                .then(|| routes.iter().find(|(route, _)| route.matches(&layer2)))
+                .flatten();
+            let Some(&(_, index)) = route else {
+                tracing::debug!("no route for request");
+                request_stats.unrouted.fetch_add(1, Ordering::Relaxed);
+                return;
+            };
+
+            // 和 FrameServer 一样不看 is_need_reply，每个转发的请求都记录请求方，下游的响应才能找回去
+            let endpoint = &endpoints[index].inner;
+            let seq = endpoint.next_seq.fetch_add(1, Ordering::Relaxed);
+            {
+                let mut table = pending[index].lock().unwrap();
+                let before = table.len();
+                table.retain(|_, request| request.sent_at.elapsed() < response_timeout);
+                request_stats.expired.fetch_add((before - table.len()) as u64, Ordering::Relaxed);
+                table.insert(
+                    seq,
+                    PendingRequest {
+                        requester: src_addr,
+                        seq: layer1.frame_seq_number,
+                        sent_at: Instant::now(),
+                    },
+                );
+            }
+
+            layer1.frame_seq_number = seq;
+            match (endpoint.send)(&layer1.serialize()) {
+                Ok(_) => {
+                    tracing::debug!(endpoint = %endpoint.name, endpoint_seq = seq, "forwarded request");
+                    request_stats.forwarded.fetch_add(1, Ordering::Relaxed)
+                }
+                Err(e) => {
+                    tracing::warn!(endpoint = %endpoint.name, error = %e, "forward to endpoint failed");
+                    pending[index].lock().unwrap().remove(&seq);
+                    request_stats.send_errors.fetch_add(1, Ordering::Relaxed)
+                }
+            };
+        });
+        // 前端启动失败时停止已启动的端点接收线程
+        let inner = started.inspect_err(|_| running.store(false, Ordering::Relaxed))?;
+
+        Ok(BridgeHandle {
+            inner,
+            stats,
+            running,
+            readers,
+        })
+    }
+}
+
+// 下游响应：按端点序列号找回请求方，恢复原序列号后发回
+fn respond<T: DatagramTransport>(
+    front: &T,
+    auto_dscp: bool,
+    metrics: Option<&TransportMetrics>,
+    pending: &PendingTable<T::Addr>,
+    stats: &BridgeStats,
+    data: &[u8],
+) {
+    let decoded = Layer1Protocol::deserialize(data)
+        .and_then(|layer1| Layer2Protocol::deserialize(&layer1.payload).map(|layer2| (layer1, layer2)));
+    let (mut layer1, layer2) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            tracing::debug!(len = data.len(), error = e.name(), "dropping undecodable endpoint response");
+            stats.invalid.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = metrics {
+                metrics.decode_failure(e.name());
+            }
+            return;
+        }
+    };
+    // 下游主动发来的请求帧即使序列号相同也不能占用请求方的映射
+    if layer2.req_rsp != ReqRsp::Response {
+        tracing::debug!(endpoint_seq = layer1.frame_seq_number, "ignoring non-response frame from endpoint");
+        stats.unmatched.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let Some(request) = pending.lock().unwrap().remove(&layer1.frame_seq_number) else {
+        tracing::debug!(endpoint_seq = layer1.frame_seq_number, "no pending request for endpoint response");
+        stats.unmatched.fetch_add(1, Ordering::Relaxed);
+        return;
+    };
+    layer1.frame_seq_number = request.seq;
+    let frame = layer1.serialize();
+    let result = if auto_dscp {
+        front.send_prioritized(&frame, &request.requester)
+    } else {
+        front.send_to(&frame, &request.requester)
+    };
+    match result {
+        Ok(sent) => {
+            if let Some(metrics) = metrics {
+                metrics.sent(sent);
+            }
+            stats.responses.fetch_add(1, Ordering::Relaxed)
+        }
+        Err(e) => {
+            tracing::warn!(peer = ?request.requester, error = %e, "response to requester failed");
+            stats.send_errors.fetch_add(1, Ordering::Relaxed)
+        }
+    };
+}
+
+/// 网关运行句柄
+pub struct BridgeHandle<A = SocketAddr> {
+    inner: ServerHandle<A>,
+    stats: Arc<BridgeStats>,
+    running: Arc<AtomicBool>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl<A: Clone> BridgeHandle<A> {
+    // 前端监听的地址
+    pub fn local_addr(&self) -> A {
+        self.inner.local_addr()
+    }
+
+    pub fn stats(&self) -> Arc<BridgeStats> {
+        self.stats.clone()
+    }
+
+    pub fn server_stats(&self) -> Arc<ServerStats> {
+        self.inner.stats()
+    }
+
+    // 停止前端和所有端点的接收线程
+    pub fn shutdown(self) -> thread::Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        let result = self.inner.shutdown();
+        for reader in self.readers {
+            reader.join()?;
+        }
+        result
+    }
+
+    // 等待前端接收线程结束（通常不会主动结束）
+    pub fn join(self) -> thread::Result<()> {
+        self.inner.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceClient, FrameServer, Route, TcpServer, TcpTransport, UdpClient};
+    use std::net::Ipv4Addr;
+    use udp_protocol::{ProtocolBody, RegisterProtocol, RequestBodyType, TlvProtocol};
+
+    fn front_addr(handle: &BridgeHandle) -> SocketAddr {
+        (Ipv4Addr::LOCALHOST, handle.local_addr().port()).into()
+    }
+
+    // TCP 下游：按命令号回显，记录收到的序列号
+    fn tcp_device(seen: Arc<Mutex<Vec<u16>>>) -> crate::FrameServerHandle {
+        FrameServer::new(TcpServer::listen(0).unwrap())
+            .route(Route::new(RequestBodyType::TlvProtocol), move |req| {
+                seen.lock().unwrap().push(req.layer1.frame_seq_number);
+                let ProtocolBody::Tlv(tlv) = &req.body else { unreachable!() };
+                Some(ProtocolBody::Tlv(TlvProtocol::new(tlv.command_code, 0, tlv.user_data.clone())))
+            })
+            .start()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bridge_to_tcp_rewrites_sequence() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let device = tcp_device(seen.clone());
+        let device_addr: SocketAddr = (Ipv4Addr::LOCALHOST, device.local_addr().port()).into();
+
+        let endpoint = Endpoint::new("tcp", TcpTransport::connect(device_addr, Default::default()).unwrap(), device_addr)
+            .unwrap();
+        let bridge = Bridge::new(UdpServer::bind(0).unwrap())
+            .with_workers(2)
+            .route(BridgeRoute::any().device_type(DeviceType::MCU), &endpoint)
+            .start()
+            .unwrap();
+        let addr = front_addr(&bridge);
+
+        // 两个请求方使用相同的起始序列号，响应仍各自回到原请求方并恢复序列号
+        let clients: Vec<_> = (0..2)
+            .map(|_| DeviceClient::new(UdpClient::new().unwrap(), addr, DeviceType::MCU, 0))
+            .collect();
+        for (i, client) in clients.iter().enumerate() {
+            let data = vec![i as u8; 4];
+            assert_eq!(client.command(0x30, &data).unwrap(), data);
+        }
+
+        // 未路由的设备类型
+        let fpga = DeviceClient::new(UdpClient::new().unwrap(), addr, DeviceType::FPGA, 0)
+            .with_retry_policy(crate::RetryPolicy::new(1, Duration::from_millis(100)));
+        assert!(fpga.command(0x30, b"").is_err());
+
+        let stats = bridge.stats();
+        bridge.shutdown().unwrap();
+        device.shutdown().unwrap();
+        assert_eq!((stats.forwarded(), stats.responses(), stats.unrouted()), (2, 2, 1));
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[1], seen[0].wrapping_add(1));
+    }
+
+    #[test]
+    fn test_bridge_counts_invalid_endpoint_response() {
+        let device = FrameServer::new(UdpServer::bind(0).unwrap())
+            .route(Route::new(RequestBodyType::TlvProtocol), |req| {
+                let ProtocolBody::Tlv(tlv) = &req.body else { unreachable!() };
+                Some(ProtocolBody::Tlv(TlvProtocol::new(tlv.command_code, 0, tlv.user_data.clone())))
+            })
+            .start()
+            .unwrap();
+        let device_addr: SocketAddr = (Ipv4Addr::LOCALHOST, device.local_addr().port()).into();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let endpoint_addr = socket.local_addr().unwrap();
+        let endpoint = Endpoint::new("udp", socket, device_addr).unwrap();
+        let bridge = Bridge::new(UdpServer::bind(0).unwrap())
+            .route(BridgeRoute::any(), &endpoint)
+            .start()
+            .unwrap();
+        let addr = front_addr(&bridge);
+
+        // 下游发来长度字段为 0xFFFF 的坏帧，端点接收线程应计数后继续处理正常响应
+        let bad = [0x55, 0xBB, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00];
+        let stray = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        stray.send_to(&bad, endpoint_addr).unwrap();
+        stray.send_to(&[0x55], endpoint_addr).unwrap();
+
+        let client = DeviceClient::new(UdpClient::new().unwrap(), addr, DeviceType::MCU, 0);
+        assert_eq!(client.command(0x30, b"ok").unwrap(), b"ok");
+
+        let stats = bridge.stats();
+        bridge.shutdown().unwrap();
+        device.shutdown().unwrap();
+        assert_eq!((stats.invalid(), stats.responses()), (2, 1));
+    }
+
+    #[test]
+    fn test_bridge_routes_encapsulated_request() {
+        use udp_protocol::{CheckType, FrameType, Priority, encapsulate_data};
+
+        let tlv = |req_rsp| {
+            encapsulate_data(
+                FrameType::Type0,
+                Priority::Medium,
+                CheckType::CheckSum,
+                req_rsp,
+                DeviceType::MCU,
+                0,
+                RequestBodyType::TlvProtocol,
+                [0; 8],
+                0x30,
+                0,
+                b"ping".to_vec(),
+            )
+        };
+        let device = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        device.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let device_addr = device.local_addr().unwrap();
+        let endpoint = Endpoint::new("udp", UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap(), device_addr).unwrap();
+        let bridge = Bridge::new(UdpServer::bind(0).unwrap())
+            .route(BridgeRoute::any().device_type(DeviceType::MCU), &endpoint)
+            .start()
+            .unwrap();
+
+        // encapsulate_data 生成的请求不要求回复，仍然要记录请求方
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        client.send_to(&tlv(ReqRsp::Request), front_addr(&bridge)).unwrap();
+        let mut buf = [0u8; 256];
+        let (len, endpoint_addr) = device.recv_from(&mut buf).unwrap();
+        let forwarded = Layer1Protocol::deserialize(&buf[..len]).unwrap();
+
+        // 下游发来序列号相同的请求帧不能占用映射，之后的响应仍能回到请求方
+        device.send_to(&buf[..len], endpoint_addr).unwrap();
+        let mut response = Layer1Protocol::deserialize(&tlv(ReqRsp::Response)).unwrap();
+        response.frame_seq_number = forwarded.frame_seq_number;
+        device.send_to(&response.serialize(), endpoint_addr).unwrap();
+
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        let reply = Layer1Protocol::deserialize(&buf[..len]).unwrap();
+        assert_eq!(reply.frame_seq_number, 1);
+        assert_eq!(Layer2Protocol::deserialize(&reply.payload).unwrap().req_rsp, ReqRsp::Response);
+
+        let stats = bridge.stats();
+        bridge.shutdown().unwrap();
+        assert_eq!((stats.forwarded(), stats.responses(), stats.unmatched()), (1, 1, 1));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_bridge_to_serial() {
+        use crate::{SerialConfig, pty_pair};
+
+        let (master, slave) = pty_pair(&SerialConfig::new(115200)).unwrap();
+        // 从设备端模拟 UART 上的 MCU
+        let mcu = FrameServer::new(UdpServer::from_socket(slave))
+            .route(Route::new(RequestBodyType::RegisterProtocol).device_index(3), |req| {
+                let ProtocolBody::Register(reg) = &req.body else { unreachable!() };
+                Some(ProtocolBody::Register(RegisterProtocol::new(reg.register_address, 0, vec![0x33; reg.data_length as usize])))
+            })
+            .start()
+            .unwrap();
+
+        let target = master.path().to_path_buf();
+        let endpoint = Endpoint::new("uart", master, target).unwrap();
+        let bridge = Bridge::new(UdpServer::bind(0).unwrap())
+            .route(BridgeRoute::any(), &endpoint)
+            .start()
+            .unwrap();
+        let addr = front_addr(&bridge);
+
+        let client = DeviceClient::new(UdpClient::new().unwrap(), addr, DeviceType::MCU, 3);
+        assert_eq!(client.read_register(0x10, 2).unwrap(), [0x33, 0x33]);
+        assert_eq!(client.read_u32(0x20).unwrap(), 0x33333333);
+
+        bridge.shutdown().unwrap();
+        mcu.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,354 @@
+// device.rs
+// 面向单个设备的请求/响应客户端：封装寄存器读写和 TLV 命令，
+// 按帧序列号匹配响应，检查错误码，并按重传策略处理超时。
+use crate::error::{DeviceError, protocol_error};
+use crate::retry::is_timeout;
+use crate::transport::DatagramTransport;
+use crate::{RetryPolicy, UdpClient};
+use std::io;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Instant;
+use udp_protocol::{
+    CheckType, DeviceType, FrameType, Layer1Protocol, Layer2Protocol, Priority, ProtocolBody, RegisterProtocol,
+    ReqRsp, TlvProtocol, try_decapsulate_data,
+};
+
+/// 设备客户端
+pub struct DeviceClient<T: DatagramTransport = UdpSocket> {
+    client: UdpClient<T>,
+    target: T::Addr,
+    device_type: DeviceType,
+    device_index: u16,
+    group: [u8; 8],
+    priority: Priority,
+    policy: RetryPolicy,
+    next_seq: AtomicU16,
+}
+
+impl<T: DatagramTransport> DeviceClient<T> {
+    pub fn new(client: UdpClient<T>, target: T::Addr, device_type: DeviceType, device_index: u16) -> Self {
+        DeviceClient {
+            client,
+            target,
+            device_type,
+            device_index,
+            group: [0; 8],
+            priority: Priority::Medium,
+            policy: RetryPolicy::default(),
+            next_seq: AtomicU16::new(rand::random()),
+        }
+    }
+
+    pub fn with_group(mut self, group: [u8; 8]) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    // 设置超时和重传策略
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn target(&self) -> T::Addr {
+        self.target.clone()
+    }
+
+    pub fn client(&self) -> &UdpClient<T> {
+        &self.client
+    }
+
+    // 读取从 addr 开始的 len 字节寄存器；请求的数据为空、data_length 为读取长度
+    pub fn read_register(&self, addr: u32, len: u16) -> io::Result<Vec<u8>> {
+        let request = RegisterProtocol {
+            register_address: addr,
+            error_code: 0,
+            data_length: len,
+            data: Vec::new(),
+        };
+        let ProtocolBody::Register(response) = self.request(ProtocolBody::Register(request))? else {
+            return Err(unexpected_body());
+        };
+        if response.register_address != addr || response.data.len() != len as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Register response mismatch: expected {} bytes at {:#x}, got {} bytes at {:#x}",
+                    len,
+                    addr,
+                    response.data.len(),
+                    response.register_address
+                ),
+            ));
+        }
+        Ok(response.data)
+    }
+
+    // 读取一个 32 位寄存器（小端）
+    pub fn read_u32(&self, addr: u32) -> io::Result<u32> {
+        let data = self.read_register(addr, 4)?;
+        Ok(u32::from_le_bytes(data.try_into().unwrap()))
+    }
+
+    // 写入从 addr 开始的寄存器
+    pub fn write_register(&self, addr: u32, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Register write data is empty"));
+        }
+        let request = RegisterProtocol::new(addr, 0, data.to_vec());
+        let ProtocolBody::Register(response) = self.request(ProtocolBody::Register(request))? else {
+            return Err(unexpected_body());
+        };
+        if response.register_address != addr {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Register response address {:#x} does not match {:#x}", response.register_address, addr),
+            ));
+        }
+        Ok(())
+    }
+
+    // 写入一个 32 位寄存器（小端）
+    pub fn write_u32(&self, addr: u32, value: u32) -> io::Result<()> {
+        self.write_register(addr, &value.to_le_bytes())
+    }
+
+    // 执行 TLV 命令，返回响应数据
+    pub fn command(&self, code: u32, data: &[u8]) -> io::Result<Vec<u8>> {
+        let request = TlvProtocol::new(code, 0, data.to_vec());
+        let ProtocolBody::Tlv(response) = self.request(ProtocolBody::Tlv(request))? else {
+            return Err(unexpected_body());
+        };
+        if response.command_code != code {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Command response code {:#x} does not match {:#x}", response.command_code, code),
+            ));
+        }
+        Ok(response.user_data)
+    }
+
+    // 发送请求并等待序列号相同的响应，错误码非零时返回 DeviceError
+    pub fn request(&self, body: ProtocolBody) -> io::Result<ProtocolBody> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let span = tracing::debug_span!(
+            "device_request",
+            peer = ?self.target,
+            seq,
+            device_type = ?self.device_type,
+            device_index = self.device_index,
+        );
+        let _enter = span.enter();
+        let frame = self.encode(seq, body);
+        let response = self
+            .exchange(seq, &frame)
+            .inspect_err(|e| tracing::warn!(error = %e, "device request failed"))?;
+
+        let error_code = match &response {
+            ProtocolBody::Register(reg) => reg.error_code,
+            ProtocolBody::Tlv(tlv) => tlv.error_code,
+        };
+        if error_code != 0 {
+            tracing::debug!(error_code, "device returned error code");
+            return Err(DeviceError { error_code }.into());
+        }
+        Ok(response)
+    }
+
+    fn encode(&self, seq: u16, body: ProtocolBody) -> Vec<u8> {
+        let layer2 = Layer2Protocol {
+            req_rsp: ReqRsp::Request,
+            is_need_reply: true,
+            code: false,
+            flag: false,
+            request_body_type: body.request_body_type(),
+            device_type: self.device_type,
+            device_index: self.device_index,
+            group: self.group,
+            payload: body.serialize(),
+        };
+        Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: self.priority,
+            check_type: CheckType::CheckSum,
+            frame_type: FrameType::Type0,
+            frame_seq_number: seq,
+            frame_length: 0, // 序列化时计算填充
+            payload: layer2.serialize(),
+            checksum: 0, // 序列化时计算填充
+        }
+        .serialize()
+    }
+
+    // 按重传策略发送同一帧，丢弃序列号不匹配的迟到响应和无法解析的数据
+    fn exchange(&self, seq: u16, frame: &[u8]) -> io::Result<ProtocolBody> {
+        let start = Instant::now();
+        let mut buf = vec![0; self.client.max_datagram_size()];
+        let mut last_error = None;
+
+        for attempt in 1..=self.policy.max_attempts.max(1) {
+            let mut attempt_deadline = Instant::now() + self.policy.timeout_for_attempt(attempt);
+            if let Some(deadline) = self.policy.deadline {
+                attempt_deadline = attempt_deadline.min(start + deadline);
+            }
+            if attempt_deadline <= Instant::now() {
+                break;
+            }
+            if attempt > 1 {
+                tracing::debug!(attempt, "retransmitting request");
+            }
+            let sent_at = self.client.send_timed(self.target.clone(), frame)?;
+
+            loop {
+                let remaining = attempt_deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                self.client.socket.set_read_timeout(Some(remaining))?;
+                let (num_bytes, src_addr) = match self.client.socket.recv_from_checked(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) if is_timeout(&e) => break,
+                    Err(e) => {
+                        self.client.record_error(&e);
+                        return Err(e);
+                    }
+                };
+                if src_addr != self.target {
+                    tracing::trace!(src = ?src_addr, "ignoring datagram from another address");
+                    continue;
+                }
+                match try_decapsulate_data(&buf[..num_bytes]) {
+                    Ok((layer1, layer2, body))
+                        if layer1.frame_seq_number == seq && layer2.req_rsp == ReqRsp::Response =>
+                    {
+                        // Karn 算法：重传后的回包不计入 RTT
+                        self.client.record_reply(num_bytes, (attempt == 1).then(|| sent_at.elapsed()));
+                        return Ok(body);
+                    }
+                    Ok((layer1, ..)) => {
+                        tracing::debug!(response_seq = layer1.frame_seq_number, "ignoring stale response");
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::debug!(error = e.name(), "ignoring undecodable response");
+                        if let Some(metrics) = &self.client.metrics {
+                            metrics.decode_failure(e.name());
+                        }
+                        last_error = Some(protocol_error(e));
+                    }
+                }
+            }
+        }
+
+        // 只收到过无法解析的数据时返回解析错误，便于定位问题
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("No response from {:?} after {:?}", self.target, start.elapsed()),
+            )
+        }))
+    }
+}
+
+fn unexpected_body() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Response body type does not match request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FrameServer, FrameServerHandle, Route, UdpServer, device_error_code};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use udp_protocol::RequestBodyType;
+
+    // 模拟设备：寄存器读写、回显命令，命令 0xEE 返回错误码，第一次读 0x200 时不回复
+    fn start_device() -> FrameServerHandle {
+        let registers = Arc::new(Mutex::new(HashMap::<u32, u8>::new()));
+        let dropped = Arc::new(AtomicU32::new(0));
+        FrameServer::new(UdpServer::bind(0).unwrap())
+            .route(
+                Route::new(RequestBodyType::RegisterProtocol).device_type(DeviceType::FPGA).device_index(1),
+                move |req| {
+                    let ProtocolBody::Register(reg) = &req.body else { unreachable!() };
+                    let mut registers = registers.lock().unwrap();
+                    let data = if reg.data.is_empty() {
+                        if reg.register_address == 0x200 && dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                            return None;
+                        }
+                        (0..reg.data_length as u32)
+                            .map(|i| *registers.get(&(reg.register_address + i)).unwrap_or(&0))
+                            .collect()
+                    } else {
+                        for (i, byte) in reg.data.iter().enumerate() {
+                            registers.insert(reg.register_address + i as u32, *byte);
+                        }
+                        Vec::new()
+                    };
+                    Some(ProtocolBody::Register(RegisterProtocol::new(reg.register_address, 0, data)))
+                },
+            )
+            .route(Route::new(RequestBodyType::TlvProtocol), |req| {
+                let ProtocolBody::Tlv(tlv) = &req.body else { unreachable!() };
+                let error_code = if tlv.command_code == 0xEE { 0x0102 } else { 0 };
+                Some(ProtocolBody::Tlv(TlvProtocol::new(tlv.command_code, error_code, tlv.user_data.clone())))
+            })
+            .start()
+            .unwrap()
+    }
+
+    fn device(handle: &FrameServerHandle, device_index: u16) -> DeviceClient {
+        let target = SocketAddr::from(([127, 0, 0, 1], handle.local_addr().port()));
+        DeviceClient::new(UdpClient::new().unwrap(), target, DeviceType::FPGA, device_index)
+            .with_retry_policy(RetryPolicy::new(3, Duration::from_millis(100)).with_jitter(0.0))
+    }
+
+    #[test]
+    fn test_register_read_write() {
+        let handle = start_device();
+        let device = device(&handle, 1);
+
+        device.write_u32(0x100, 0xCAFEBABE).unwrap();
+        assert_eq!(device.read_u32(0x100).unwrap(), 0xCAFEBABE);
+        assert_eq!(device.read_register(0x102, 2).unwrap(), [0xFE, 0xCA]);
+
+        // 第一次请求没有回复，重传后成功
+        assert_eq!(device.read_register(0x200, 1).unwrap(), [0]);
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_command_and_error_code() {
+        let handle = start_device();
+        let device = device(&handle, 1);
+
+        assert_eq!(device.command(0x01, b"echo").unwrap(), b"echo");
+        let err = device.command(0xEE, b"").unwrap_err();
+        assert_eq!(device_error_code(&err), Some(0x0102));
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_unrouted_device_times_out() {
+        let handle = start_device();
+        // 没有设备序号 9 的寄存器路由
+        let device = device(&handle, 9);
+        let err = device.read_register(0x100, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        handle.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+// discovery.rs
+// 设备发现：扫描器广播或组播 DiscoveryRequest 并在超时前收集响应，
+// 设备端用 start_discovery 或在自己的处理函数里调用 discovery_reply 回复。
+use crate::multicast::group_multicast_addr;
+use crate::{ServerHandle, UdpClient, UdpServer};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use udp_protocol::discovery::{DiscoveryRequest, DiscoveryResponse};
+use udp_protocol::types::DeviceType;
+
+/// 设备默认监听发现请求的端口
+pub const DEFAULT_DISCOVERY_PORT: u16 = 58051;
+/// 默认的扫描等待时间
+pub const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 扫描到的设备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    /// 设备回包的来源地址
+    pub addr: SocketAddr,
+    pub info: DiscoveryResponse,
+}
+
+/// 设备扫描器
+pub struct Scanner {
+    client: UdpClient,
+    timeout: Duration,
+    device_type: Option<DeviceType>,
+    group: [u8; 8],
+}
+
+impl Scanner {
+    pub fn new(client: UdpClient) -> Self {
+        Scanner {
+            client,
+            timeout: DEFAULT_SCAN_TIMEOUT,
+            device_type: None,
+            group: [0; 8],
+        }
+    }
+
+    // 设置收集响应的时间
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // 只发现指定类型的设备
+    pub fn with_device_type(mut self, device_type: DeviceType) -> Self {
+        self.device_type = Some(device_type);
+        self
+    }
+
+    // 只发现指定组的设备，scan_group 按该组映射的组播地址发送
+    pub fn with_group(mut self, group: [u8; 8]) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub fn client(&self) -> &UdpClient {
+        &self.client
+    }
+
+    // 向 addr 发送发现请求，addr 可以是单播、广播或组播地址；结果按地址排序，同一地址同一设备只保留一条
+    pub fn scan(&self, addr: SocketAddr) -> io::Result<Vec<DiscoveredDevice>> {
+        let request = DiscoveryRequest {
+            nonce: rand::random(),
+            device_type: self.device_type,
+            group: self.group,
+        };
+        self.client.socket.set_broadcast(true)?;
+        let replies = self.client.send_and_collect(addr, &request.encode(1), self.timeout, None)?;
+
+        let mut devices = BTreeMap::new();
+        for (src_addr, data) in replies {
+            // 忽略无法解析的数据和其他扫描的响应
+            let Ok(info) = DiscoveryResponse::decode(&data) else {
+                continue;
+            };
+            if info.nonce == request.nonce {
+                devices
+                    .entry((src_addr, info.device_index))
+                    .or_insert(DiscoveredDevice { addr: src_addr, info });
+            }
+        }
+        Ok(devices.into_values().collect())
+    }
+
+    // 通过受限广播扫描 port 上的设备
+    pub fn scan_broadcast(&self, port: u16) -> io::Result<Vec<DiscoveredDevice>> {
+        self.scan(SocketAddr::from((Ipv4Addr::BROADCAST, port)))
+    }
+
+    // 通过当前组映射的组播地址扫描 port 上的设备
+    pub fn scan_group(&self, port: u16) -> io::Result<Vec<DiscoveredDevice>> {
+        let addr = group_multicast_addr(&self.client.socket, &self.group)?;
+        self.scan(SocketAddr::new(addr, port))
+    }
+}
+
+// 如果 data 是本设备应该回复的发现请求，返回响应帧
+pub fn discovery_reply(info: &DiscoveryResponse, data: &[u8]) -> Option<Vec<u8>> {
+    let request = DiscoveryRequest::decode(data).ok()?;
+    info.reply_to(&request, 1)
+}
+
+impl UdpServer {
+    // 启动只回复发现请求的服务器，用于设备模拟和测试
+    pub fn start_discovery(self, info: DiscoveryResponse) -> io::Result<ServerHandle> {
+        self.start_pool(1, move |_src_addr, data, reply| {
+            if let Some(response) = discovery_reply(&info, data) {
+                let _ = reply.send(&response);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MulticastInterface;
+    use crate::multicast::bind_shared;
+    use udp_protocol::discovery::{Capabilities, FirmwareVersion};
+
+    fn device(device_type: DeviceType, device_index: u16, group: [u8; 8]) -> DiscoveryResponse {
+        DiscoveryResponse {
+            nonce: 0,
+            device_type,
+            device_index,
+            group,
+            firmware: FirmwareVersion { major: 1, minor: 0, patch: device_index },
+            capabilities: Capabilities::REGISTER,
+        }
+    }
+
+    #[test]
+    fn test_scan_unicast_responders() {
+        let handles: Vec<ServerHandle> = [DeviceType::FPGA, DeviceType::MCU]
+            .into_iter()
+            .enumerate()
+            .map(|(index, device_type)| {
+                let info = device(device_type, index as u16, [0; 8]);
+                UdpServer::bind(0).unwrap().start_discovery(info).unwrap()
+            })
+            .collect();
+
+        let scanner = Scanner::new(UdpClient::new().unwrap()).with_timeout(Duration::from_millis(200));
+        for handle in &handles {
+            let addr = SocketAddr::from(([127, 0, 0, 1], handle.local_addr().port()));
+            let devices = scanner.scan(addr).unwrap();
+            assert_eq!(devices.len(), 1);
+            assert_eq!(devices[0].addr, addr);
+        }
+
+        // 按设备类型过滤
+        let scanner = scanner.with_device_type(DeviceType::MCU);
+        let fpga = SocketAddr::from(([127, 0, 0, 1], handles[0].local_addr().port()));
+        assert!(scanner.scan(fpga).unwrap().is_empty());
+
+        for handle in handles {
+            handle.shutdown().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scan_group_collects_all_devices() {
+        let group = [0x37, 0, 0, 0, 0, 0, 0, 0];
+        let loopback = MulticastInterface::V4(Ipv4Addr::LOCALHOST);
+        let first = bind_shared(0).unwrap();
+        let port = first.local_addr().unwrap().port();
+        let mut sockets = vec![first];
+        sockets.extend((1..4).map(|_| bind_shared(port).unwrap()));
+
+        // 前三个设备在组内，最后一个属于其他组
+        let handles: Vec<ServerHandle> = sockets
+            .into_iter()
+            .enumerate()
+            .map(|(index, socket)| {
+                let server = UdpServer::from_socket(socket);
+                server.join_group(&group, loopback).unwrap();
+                let own_group = if index < 3 { group } else { [0x99; 8] };
+                server.start_discovery(device(DeviceType::NetworkPort, index as u16, own_group)).unwrap()
+            })
+            .collect();
+
+        let client = UdpClient::new().unwrap();
+        client.set_multicast_interface(loopback).unwrap();
+        let scanner = Scanner::new(client)
+            .with_group(group)
+            .with_timeout(Duration::from_millis(300));
+        let devices = scanner.scan_group(port).unwrap();
+
+        let mut indexes: Vec<u16> = devices.iter().map(|d| d.info.device_index).collect();
+        indexes.sort();
+        assert_eq!(indexes, [0, 1, 2]);
+        assert!(devices.iter().all(|d| d.info.firmware.patch == d.info.device_index));
+
+        for handle in handles {
+            handle.shutdown().unwrap();
+        }
+    }
+}
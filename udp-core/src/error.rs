@@ -0,0 +1,69 @@
+// error.rs
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// 数据报超过接收缓冲区被截断
+///
+/// 以 `io::ErrorKind::InvalidData` 返回，可以用 [`is_truncated`] 区分于其他错误。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncatedDatagram {
+    /// 数据报的实际长度，平台无法提供时为 None
+    pub len: Option<usize>,
+    /// 当前允许的最大数据报长度
+    pub max: usize,
+}
+
+impl fmt::Display for TruncatedDatagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.len {
+            Some(len) => write!(f, "Datagram truncated: {} bytes exceeds maximum of {}", len, self.max),
+            None => write!(f, "Datagram truncated: exceeds maximum of {} bytes", self.max),
+        }
+    }
+}
+
+impl Error for TruncatedDatagram {}
+
+impl From<TruncatedDatagram> for io::Error {
+    fn from(e: TruncatedDatagram) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+// 判断错误是否为数据报截断
+pub fn is_truncated(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<TruncatedDatagram>())
+}
+
+/// 设备在响应中返回了非零错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceError {
+    pub error_code: u16,
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device returned error code {:#06x}", self.error_code)
+    }
+}
+
+impl Error for DeviceError {}
+
+impl From<DeviceError> for io::Error {
+    fn from(e: DeviceError) -> Self {
+        io::Error::other(e)
+    }
+}
+
+// 取出设备返回的错误码，其他错误返回 None
+pub fn device_error_code(e: &io::Error) -> Option<u16> {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<DeviceError>())
+        .map(|inner| inner.error_code)
+}
+
+// 协议解析错误统一以 InvalidData 返回
+pub(crate) fn protocol_error(e: udp_protocol::types::ProtocolError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
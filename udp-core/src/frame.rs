@@ -0,0 +1,327 @@
+// frame.rs
+// 协议感知的服务器：解析每个数据报，按请求体类型、设备类型和设备序号路由到处理函数，
+// 处理函数返回的消息体按请求帧自动封装并回复给来源地址。
+use crate::transport::DatagramTransport;
+use crate::{ServerHandle, ServerStats, UdpServer};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use udp_protocol::{
+    DeviceType, Layer1Protocol, Layer2Protocol, ProtocolBody, ReqRsp, RequestBodyType, encapsulate_reply,
+    try_decapsulate_data,
+};
+
+/// 解析后的请求帧
+#[derive(Debug, Clone)]
+pub struct FrameRequest<A = SocketAddr> {
+    pub src_addr: A,
+    pub layer1: Layer1Protocol,
+    pub layer2: Layer2Protocol,
+    pub body: ProtocolBody,
+}
+
+/// 帧处理函数，返回 None 表示不回复
+pub type FrameHandler<A = SocketAddr> = Box<dyn Fn(&FrameRequest<A>) -> Option<ProtocolBody> + Send + Sync>;
+
+/// 路由条件，设备类型和设备序号为 None 时匹配任意值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    pub body_type: RequestBodyType,
+    pub device_type: Option<DeviceType>,
+    pub device_index: Option<u16>,
+}
+
+impl Route {
+    pub fn new(body_type: RequestBodyType) -> Self {
+        Route {
+            body_type,
+            device_type: None,
+            device_index: None,
+        }
+    }
+
+    pub fn device_type(mut self, device_type: DeviceType) -> Self {
+        self.device_type = Some(device_type);
+        self
+    }
+
+    pub fn device_index(mut self, device_index: u16) -> Self {
+        self.device_index = Some(device_index);
+        self
+    }
+
+    fn matches(&self, layer2: &Layer2Protocol) -> bool {
+        self.body_type == layer2.request_body_type
+            && self.device_type.is_none_or(|t| t == layer2.device_type)
+            && self.device_index.is_none_or(|i| i == layer2.device_index)
+    }
+
+    // 条件越多越具体，匹配时优先
+    fn specificity(&self) -> u8 {
+        self.device_type.is_some() as u8 + self.device_index.is_some() as u8
+    }
+}
+
+/// 帧服务器计数器
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    handled: AtomicU64,
+    replied: AtomicU64,
+    unrouted: AtomicU64,
+    reply_errors: AtomicU64,
+    decode_errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl FrameStats {
+    // 交给处理函数的请求帧
+    pub fn handled(&self) -> u64 {
+        self.handled.load(Ordering::Relaxed)
+    }
+
+    // 成功发送的回复
+    pub fn replied(&self) -> u64 {
+        self.replied.load(Ordering::Relaxed)
+    }
+
+    // 解析成功但没有匹配路由的帧（包括响应帧）
+    pub fn unrouted(&self) -> u64 {
+        self.unrouted.load(Ordering::Relaxed)
+    }
+
+    pub fn reply_errors(&self) -> u64 {
+        self.reply_errors.load(Ordering::Relaxed)
+    }
+
+    // 按错误种类统计的解析失败次数
+    pub fn decode_errors(&self) -> BTreeMap<&'static str, u64> {
+        self.decode_errors.lock().unwrap().clone()
+    }
+
+    pub fn decode_error_total(&self) -> u64 {
+        self.decode_errors.lock().unwrap().values().sum()
+    }
+
+    fn add_decode_error(&self, kind: &'static str) {
+        *self.decode_errors.lock().unwrap().entry(kind).or_default() += 1;
+    }
+}
+
+/// 协议感知的服务器
+pub struct FrameServer<T: DatagramTransport = UdpSocket> {
+    server: UdpServer<T>,
+    workers: usize,
+    routes: Vec<(Route, FrameHandler<T::Addr>)>,
+}
+
+impl<T: DatagramTransport> FrameServer<T> {
+    pub fn new(server: UdpServer<T>) -> Self {
+        FrameServer {
+            server,
+            workers: 1,
+            routes: Vec::new(),
+        }
+    }
+
+    // 处理线程数，同一来源地址的帧总是由同一个线程按顺序处理
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // 注册处理函数；多个路由匹配时选条件最多的，条件相同时选先注册的
+    pub fn route<H>(mut self, route: Route, handler: H) -> Self
+    where
+        H: Fn(&FrameRequest<T::Addr>) -> Option<ProtocolBody> + Send + Sync + 'static,
+    {
+        self.routes.push((route, Box::new(handler)));
+        self
+    }
+
+    pub fn start(self) -> io::Result<FrameServerHandle<T::Addr>> {
+        let mut routes = self.routes;
+        // 稳定排序，保持相同具体程度的注册顺序
+        routes.sort_by_key(|(route, _)| std::cmp::Reverse(route.specificity()));
+        let stats = Arc::new(FrameStats::default());
+
+        let frame_stats = stats.clone();
+        let metrics = self.server.metrics.clone();
+        let inner = self.server.start_pool(self.workers, move |src_addr, data, reply| {
+            let (layer1, layer2, body) = match try_decapsulate_data(data) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    tracing::debug!(peer = ?src_addr, error = e.name(), "dropping undecodable frame");
+                    frame_stats.add_decode_error(e.name());
+                    if let Some(metrics) = &metrics {
+                        metrics.decode_failure(e.name());
+                    }
+                    return;
+                }
+            };
+            let span = tracing::debug_span!(
+                "frame",
+                peer = ?src_addr,
+                seq = layer1.frame_seq_number,
+                device_type = ?layer2.device_type,
+                device_index = layer2.device_index,
+            );
+            let _enter = span.enter();
+            let handler = (layer2.req_rsp == ReqRsp::Request)
+                .then(|| routes.iter().find(|(route, _)| route.matches(&layer2)))
+                .flatten();
+            let Some((_, handler)) = handler else {
+                tracing::debug!(body_type = ?layer2.request_body_type, "no route for frame");
+                frame_stats.unrouted.fetch_add(1, Ordering::Relaxed);
+                return;
+            };
+
+            let request = FrameRequest { src_addr, layer1, layer2, body };
+            frame_stats.handled.fetch_add(1, Ordering::Relaxed);
+            if let Some(response) = handler(&request) {
+                match reply.send(&encapsulate_reply(&request.layer1, &request.layer2, &response)) {
+                    Ok(_) => frame_stats.replied.fetch_add(1, Ordering::Relaxed),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "reply failed");
+                        frame_stats.reply_errors.fetch_add(1, Ordering::Relaxed)
+                    }
+                };
+            }
+        })?;
+
+        Ok(FrameServerHandle { inner, stats })
+    }
+}
+
+/// 帧服务器运行句柄
+pub struct FrameServerHandle<A = SocketAddr> {
+    inner: ServerHandle<A>,
+    stats: Arc<FrameStats>,
+}
+
+impl<A: Clone> FrameServerHandle<A> {
+    pub fn local_addr(&self) -> A {
+        self.inner.local_addr()
+    }
+
+    pub fn frame_stats(&self) -> Arc<FrameStats> {
+        self.stats.clone()
+    }
+
+    pub fn server_stats(&self) -> Arc<ServerStats> {
+        self.inner.stats()
+    }
+
+    pub fn shutdown(self) -> std::thread::Result<()> {
+        self.inner.shutdown()
+    }
+
+    pub fn join(self) -> std::thread::Result<()> {
+        self.inner.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UdpClient;
+    use std::time::Duration;
+    use udp_protocol::{CheckType, FrameType, Priority, RegisterProtocol, encapsulate_data};
+
+    fn request(body_type: RequestBodyType, device_type: DeviceType, device_index: u16, code: u32) -> Vec<u8> {
+        encapsulate_data(
+            FrameType::Type0,
+            Priority::Medium,
+            CheckType::CheckSum,
+            ReqRsp::Request,
+            device_type,
+            device_index,
+            body_type,
+            [0; 8],
+            code,
+            0,
+            vec![0x11, 0x22],
+        )
+    }
+
+    fn start() -> FrameServerHandle {
+        FrameServer::new(UdpServer::bind(0).unwrap())
+            .with_workers(2)
+            .route(Route::new(RequestBodyType::RegisterProtocol), |req| {
+                let ProtocolBody::Register(reg) = &req.body else { unreachable!() };
+                Some(ProtocolBody::Register(RegisterProtocol::new(reg.register_address, 0, vec![0xA0])))
+            })
+            .route(
+                Route::new(RequestBodyType::RegisterProtocol).device_type(DeviceType::FPGA).device_index(2),
+                |_req| Some(ProtocolBody::Register(RegisterProtocol::new(0, 0, vec![0xF2]))),
+            )
+            .route(Route::new(RequestBodyType::TlvProtocol).device_type(DeviceType::MCU), |_req| None)
+            .start()
+            .unwrap()
+    }
+
+    fn reply_data(client: &UdpClient, addr: SocketAddr, frame: &[u8]) -> Vec<u8> {
+        let reply = client.send_and_receive(addr, frame, Duration::from_secs(1)).unwrap();
+        match try_decapsulate_data(&reply).unwrap().2 {
+            ProtocolBody::Register(reg) => reg.data,
+            ProtocolBody::Tlv(tlv) => tlv.user_data,
+        }
+    }
+
+    #[test]
+    fn test_routes_by_specificity() {
+        let handle = start();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        let client = UdpClient::new().unwrap();
+
+        let generic = request(RequestBodyType::RegisterProtocol, DeviceType::FPGA, 1, 0x10);
+        assert_eq!(reply_data(&client, addr, &generic), [0xA0]);
+        let specific = request(RequestBodyType::RegisterProtocol, DeviceType::FPGA, 2, 0x10);
+        assert_eq!(reply_data(&client, addr, &specific), [0xF2]);
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_counts_decode_errors_and_unrouted() {
+        let handle = start();
+        let stats = handle.frame_stats();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        let client = UdpClient::new().unwrap();
+
+        // 校验和错误、长度不足、没有路由、处理函数不回复
+        let mut corrupted = request(RequestBodyType::RegisterProtocol, DeviceType::MCU, 1, 0x10);
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+        client.send_only(addr, &corrupted).unwrap();
+        client.send_only(addr, &[0x55, 0xBB]).unwrap();
+        client.send_only(addr, &request(RequestBodyType::TlvProtocol, DeviceType::FPGA, 1, 0x20)).unwrap();
+        client.send_only(addr, &request(RequestBodyType::TlvProtocol, DeviceType::MCU, 1, 0x20)).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        handle.shutdown().unwrap();
+
+        assert_eq!(stats.decode_error_total(), 2);
+        assert_eq!(stats.decode_errors().get("InvalidLength"), Some(&1));
+        assert_eq!(stats.decode_errors().get("ChecksumMismatch"), Some(&1));
+        assert_eq!(stats.unrouted(), 1);
+        assert_eq!(stats.handled(), 1);
+        assert_eq!(stats.replied(), 0);
+    }
+
+    #[test]
+    fn test_oversized_frame_length_counted() {
+        let handle = start();
+        let stats = handle.frame_stats();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        let client = UdpClient::new().unwrap();
+
+        // Frame Length 为 0xFFFF 的 12 字节数据报，之后服务器仍然正常回复
+        let bad = [0x55, 0xBB, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00];
+        client.send_only(addr, &bad).unwrap();
+        let generic = request(RequestBodyType::RegisterProtocol, DeviceType::FPGA, 1, 0x10);
+        assert_eq!(reply_data(&client, addr, &generic), [0xA0]);
+        handle.shutdown().unwrap();
+
+        assert_eq!(stats.decode_errors().get("InvalidLength"), Some(&1));
+    }
+}
@@ -0,0 +1,588 @@
+// heartbeat.rs
+// 心跳监控：按固定间隔给设备列表发送 HeartbeatRequest，超时未回复计为丢失，
+// 连续丢失达到阈值判定离线，收到回复判定在线；窗口内状态切换过多时报告抖动。
+// 事件在监控线程中通过回调发出，当前状态可以随时从句柄读取。
+use crate::retry::is_timeout;
+use crate::transport::DatagramTransport;
+use crate::UdpClient;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use udp_protocol::heartbeat::{HeartbeatRequest, HeartbeatResponse};
+use udp_protocol::types::DeviceType;
+
+/// 默认心跳间隔
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// 默认连续丢失多少次判定离线
+pub const DEFAULT_MISS_THRESHOLD: u32 = 3;
+/// 默认的抖动判定：窗口内在线/离线切换次数
+pub const DEFAULT_FLAP_TRANSITIONS: usize = 4;
+/// 默认的抖动判定窗口
+pub const DEFAULT_FLAP_WINDOW: Duration = Duration::from_secs(60);
+
+// 平滑 RTT 的权重
+const SRTT_ALPHA: f64 = 0.125;
+
+/// 被监控的设备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitoredDevice<A = SocketAddr> {
+    pub addr: A,
+    pub device_type: DeviceType,
+    pub device_index: u16,
+}
+
+/// 设备链路状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// 还没有收到回复，也没有达到离线阈值
+    Unknown,
+    Online,
+    Offline,
+}
+
+/// 单个设备的心跳状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceStatus<A = SocketAddr> {
+    pub device: MonitoredDevice<A>,
+    pub state: LinkState,
+    /// 当前连续丢失的心跳数
+    pub missed: u32,
+    pub sent: u64,
+    pub received: u64,
+    /// 最近一次回复的往返时间
+    pub last_rtt: Option<Duration>,
+    /// 平滑往返时间
+    pub srtt: Option<Duration>,
+    /// 最近一次回复中的设备状态字
+    pub device_status: Option<u32>,
+    /// 最近一次状态变化的时间
+    pub since: Instant,
+    /// 是否处于抖动状态
+    pub flapping: bool,
+}
+
+/// 心跳事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeartbeatEvent<A = SocketAddr> {
+    /// 设备上线（首次回复或离线后恢复），携带这次回复的往返时间
+    Online { device: MonitoredDevice<A>, rtt: Duration },
+    /// 连续 missed 次没有回复
+    Offline { device: MonitoredDevice<A>, missed: u32 },
+    /// 窗口内在线/离线切换了 transitions 次
+    Flapping { device: MonitoredDevice<A>, transitions: usize },
+    /// 窗口内的切换次数回落到阈值以下，抖动结束
+    FlappingEnded { device: MonitoredDevice<A> },
+}
+
+/// 心跳事件回调，在监控线程中调用
+pub type HeartbeatHandler<A = SocketAddr> = Box<dyn FnMut(HeartbeatEvent<A>) + Send>;
+
+/// 心跳监控
+pub struct HeartbeatMonitor<T: DatagramTransport = std::net::UdpSocket> {
+    client: UdpClient<T>,
+    devices: Vec<MonitoredDevice<T::Addr>>,
+    interval: Duration,
+    timeout: Option<Duration>,
+    miss_threshold: u32,
+    flap_transitions: usize,
+    flap_window: Duration,
+    handler: Option<HeartbeatHandler<T::Addr>>,
+}
+
+impl<T: DatagramTransport> HeartbeatMonitor<T> {
+    pub fn new(client: UdpClient<T>) -> Self {
+        HeartbeatMonitor {
+            client,
+            devices: Vec::new(),
+            interval: DEFAULT_HEARTBEAT_INTERVAL,
+            timeout: None,
+            miss_threshold: DEFAULT_MISS_THRESHOLD,
+            flap_transitions: DEFAULT_FLAP_TRANSITIONS,
+            flap_window: DEFAULT_FLAP_WINDOW,
+            handler: None,
+        }
+    }
+
+    // 添加被监控的设备
+    pub fn device(mut self, addr: T::Addr, device_type: DeviceType, device_index: u16) -> Self {
+        self.devices.push(MonitoredDevice {
+            addr,
+            device_type,
+            device_index,
+        });
+        self
+    }
+
+    // 心跳发送间隔
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    // 等待单次回复的时间，默认等于间隔，超过间隔时按间隔计算
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // 连续丢失多少次判定离线
+    pub fn with_miss_threshold(mut self, misses: u32) -> Self {
+        self.miss_threshold = misses.max(1);
+        self
+    }
+
+    // window 内在线/离线切换达到 transitions 次时报告抖动
+    pub fn with_flap_detection(mut self, transitions: usize, window: Duration) -> Self {
+        self.flap_transitions = transitions.max(2);
+        self.flap_window = window;
+        self
+    }
+
+    // 设置事件回调
+    pub fn on_event<H>(mut self, handler: H) -> Self
+    where
+        H: FnMut(HeartbeatEvent<T::Addr>) + Send + 'static,
+    {
+        self.handler = Some(Box::new(handler));
+        self
+    }
+
+    // 启动监控线程
+    pub fn start(self) -> io::Result<HeartbeatHandle<T::Addr>> {
+        let now = Instant::now();
+        let trackers: Vec<Tracker<T::Addr>> = self.devices.iter().map(|device| Tracker::new(device.clone(), now)).collect();
+        let status = Arc::new(Mutex::new(trackers.iter().map(|t| t.status.clone()).collect()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let mut worker = Worker {
+            timeout: self.timeout.unwrap_or(self.interval).min(self.interval),
+            interval: self.interval,
+            miss_threshold: self.miss_threshold,
+            flap_transitions: self.flap_transitions,
+            flap_window: self.flap_window,
+            handler: self.handler,
+            client: self.client,
+            trackers,
+            status: status.clone(),
+            counter: 0,
+        };
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || worker.run(&running))
+        };
+        Ok(HeartbeatHandle { running, status, thread })
+    }
+}
+
+/// 心跳监控运行句柄
+pub struct HeartbeatHandle<A = SocketAddr> {
+    running: Arc<AtomicBool>,
+    status: Arc<Mutex<Vec<DeviceStatus<A>>>>,
+    thread: JoinHandle<()>,
+}
+
+impl<A: Clone> HeartbeatHandle<A> {
+    // 所有设备的当前状态，顺序与添加顺序一致
+    pub fn status(&self) -> Vec<DeviceStatus<A>> {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn shutdown(self) -> thread::Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        self.thread.join()
+    }
+}
+
+struct Tracker<A> {
+    status: DeviceStatus<A>,
+    // 等待回复的心跳：计数和发送时间
+    pending: Option<(u32, Instant)>,
+    transitions: VecDeque<Instant>,
+}
+
+impl<A> Tracker<A> {
+    fn new(device: MonitoredDevice<A>, now: Instant) -> Self {
+        Tracker {
+            status: DeviceStatus {
+                device,
+                state: LinkState::Unknown,
+                missed: 0,
+                sent: 0,
+                received: 0,
+                last_rtt: None,
+                srtt: None,
+                device_status: None,
+                since: now,
+                flapping: false,
+            },
+            pending: None,
+            transitions: VecDeque::new(),
+        }
+    }
+}
+
+struct Worker<T: DatagramTransport> {
+    client: UdpClient<T>,
+    trackers: Vec<Tracker<T::Addr>>,
+    status: Arc<Mutex<Vec<DeviceStatus<T::Addr>>>>,
+    interval: Duration,
+    timeout: Duration,
+    miss_threshold: u32,
+    flap_transitions: usize,
+    flap_window: Duration,
+    handler: Option<HeartbeatHandler<T::Addr>>,
+    counter: u32,
+}
+
+impl<T: DatagramTransport> Worker<T> {
+    fn run(&mut self, running: &AtomicBool) {
+        let mut buf = vec![0; self.client.max_datagram_size()];
+        let mut next_round = Instant::now();
+        while running.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            self.expire_pending(now);
+            for index in 0..self.trackers.len() {
+                self.check_flapping(index, now);
+            }
+            if now >= next_round {
+                self.send_round(now);
+                next_round += self.interval;
+                if next_round < now {
+                    // 处理过慢时跳过错过的轮次
+                    next_round = now + self.interval;
+                }
+            }
+            self.publish();
+
+            let deadline = self
+                .trackers
+                .iter()
+                .filter_map(|tracker| tracker.pending.map(|(_, sent_at)| sent_at + self.timeout))
+                .fold(next_round, Instant::min);
+            let wait = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+            if self.client.socket.set_read_timeout(Some(wait)).is_err() {
+                thread::sleep(wait);
+                continue;
+            }
+            match self.client.socket.recv_from_checked(&mut buf) {
+                Ok((num_bytes, src_addr)) => self.on_reply(&src_addr, &buf[..num_bytes]),
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => {
+                    self.client.record_error(&e);
+                    tracing::warn!(error = %e, "heartbeat receive failed");
+                    thread::sleep(wait);
+                }
+            }
+        }
+    }
+
+    fn send_round(&mut self, now: Instant) {
+        for index in 0..self.trackers.len() {
+            self.counter = self.counter.wrapping_add(1);
+            let device = &self.trackers[index].status.device;
+            let request = HeartbeatRequest::new(device.device_type, device.device_index, self.counter);
+            let addr = device.addr.clone();
+            match self.client.send_timed(addr, &request.encode(self.counter as u16)) {
+                Ok(sent_at) => {
+                    let tracker = &mut self.trackers[index];
+                    tracker.pending = Some((self.counter, sent_at));
+                    tracker.status.sent += 1;
+                }
+                // 发送失败同样计为一次丢失
+                Err(e) => {
+                    tracing::debug!(device = ?self.trackers[index].status.device, error = %e, "heartbeat send failed");
+                    self.miss(index, now);
+                }
+            }
+        }
+    }
+
+    fn expire_pending(&mut self, now: Instant) {
+        for index in 0..self.trackers.len() {
+            if let Some((_, sent_at)) = self.trackers[index].pending
+                && now >= sent_at + self.timeout
+            {
+                self.trackers[index].pending = None;
+                self.miss(index, now);
+            }
+        }
+    }
+
+    fn on_reply(&mut self, src_addr: &T::Addr, data: &[u8]) {
+        let Ok(response) = HeartbeatResponse::decode(data) else {
+            return;
+        };
+        let now = Instant::now();
+        let Some(index) = self.trackers.iter().position(|tracker| {
+            let device = &tracker.status.device;
+            &device.addr == src_addr
+                && device.device_type == response.device_type
+                && device.device_index == response.device_index
+                && tracker.pending.is_some_and(|(counter, _)| counter == response.counter)
+        }) else {
+            // 迟到的回复或其他设备的数据
+            return;
+        };
+
+        let (_, sent_at) = self.trackers[index].pending.take().unwrap();
+        let rtt = now.saturating_duration_since(sent_at);
+        self.client.record_reply(data.len(), Some(rtt));
+        let status = &mut self.trackers[index].status;
+        status.received += 1;
+        status.missed = 0;
+        status.last_rtt = Some(rtt);
+        status.device_status = Some(response.status);
+        status.srtt = Some(match status.srtt {
+            Some(srtt) => srtt.mul_f64(1.0 - SRTT_ALPHA) + rtt.mul_f64(SRTT_ALPHA),
+            None => rtt,
+        });
+        if status.state != LinkState::Online {
+            let device = status.device.clone();
+            tracing::info!(?device, ?rtt, "device online");
+            self.transition(index, LinkState::Online, now);
+            self.emit(HeartbeatEvent::Online { device, rtt });
+        }
+    }
+
+    fn miss(&mut self, index: usize, now: Instant) {
+        let status = &mut self.trackers[index].status;
+        status.missed += 1;
+        if status.missed >= self.miss_threshold && status.state != LinkState::Offline {
+            let device = status.device.clone();
+            let missed = status.missed;
+            tracing::warn!(?device, missed, "device offline");
+            self.transition(index, LinkState::Offline, now);
+            self.emit(HeartbeatEvent::Offline { device, missed });
+        }
+    }
+
+    // 切换状态并检查抖动；从 Unknown 的第一次切换不计入
+    fn transition(&mut self, index: usize, state: LinkState, now: Instant) {
+        let tracker = &mut self.trackers[index];
+        let counted = tracker.status.state != LinkState::Unknown;
+        tracker.status.state = state;
+        tracker.status.since = now;
+        if !counted {
+            return;
+        }
+
+        tracker.transitions.push_back(now);
+        self.check_flapping(index, now);
+    }
+
+    // 丢弃窗口外的切换并重新判断抖动，抖动开始和结束时发出事件
+    fn check_flapping(&mut self, index: usize, now: Instant) {
+        let tracker = &mut self.trackers[index];
+        while tracker
+            .transitions
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) > self.flap_window)
+        {
+            tracker.transitions.pop_front();
+        }
+        let transitions = tracker.transitions.len();
+        let flapping = transitions >= self.flap_transitions;
+        if flapping == tracker.status.flapping {
+            return;
+        }
+        tracker.status.flapping = flapping;
+        let device = tracker.status.device.clone();
+        if flapping {
+            tracing::warn!(?device, transitions, "device flapping");
+            self.emit(HeartbeatEvent::Flapping { device, transitions });
+        } else {
+            tracing::info!(?device, "device stopped flapping");
+            self.emit(HeartbeatEvent::FlappingEnded { device });
+        }
+    }
+
+    // 先发布状态，回调中读到的状态与事件一致
+    fn emit(&mut self, event: HeartbeatEvent<T::Addr>) {
+        self.publish();
+        if let Some(handler) = self.handler.as_mut() {
+            handler(event);
+        }
+    }
+
+    fn publish(&self) {
+        let mut status = self.status.lock().unwrap();
+        for (slot, tracker) in status.iter_mut().zip(&self.trackers) {
+            slot.clone_from(&tracker.status);
+        }
+    }
+}
+
+// 如果 data 是发给本设备的心跳请求，返回带 status 的响应帧
+pub fn heartbeat_reply(device_type: DeviceType, device_index: u16, status: u32, data: &[u8]) -> Option<Vec<u8>> {
+    let request = HeartbeatRequest::decode(data).ok()?;
+    (request.device_type == device_type && request.device_index == device_index)
+        .then(|| request.reply(status, request.counter as u16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryAddr, MemoryNetwork, MemoryTransport, ServerHandle, UdpServer};
+    use std::sync::mpsc;
+
+    // 等待事件的上限，留足余量以免负载高时误判超时；事件到达后立即返回
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // 模拟设备，answering 为 false 时不回复
+    fn start_device(network: &MemoryNetwork, answering: Arc<AtomicBool>) -> ServerHandle<MemoryAddr> {
+        UdpServer::from_socket(network.bind())
+            .start_pool(1, move |_, data, reply| {
+                if !answering.load(Ordering::Relaxed) {
+                    return;
+                }
+                if let Some(response) = heartbeat_reply(DeviceType::FPGA, 1, 0x5A, data) {
+                    reply.send(&response).unwrap();
+                }
+            })
+            .unwrap()
+    }
+
+    // 不启动线程的监控状态机，丢失阈值为 2，由测试按给定时间驱动
+    fn worker(
+        flap_transitions: usize,
+        flap_window: Duration,
+    ) -> (Worker<MemoryTransport>, mpsc::Receiver<HeartbeatEvent<MemoryAddr>>, Instant) {
+        let network = MemoryNetwork::new();
+        let device = MonitoredDevice {
+            addr: network.bind().addr(),
+            device_type: DeviceType::FPGA,
+            device_index: 1,
+        };
+        let start = Instant::now();
+        let tracker = Tracker::new(device, start);
+        let (events_tx, events) = mpsc::channel();
+        let worker = Worker {
+            client: UdpClient::from_socket(network.bind()),
+            status: Arc::new(Mutex::new(vec![tracker.status.clone()])),
+            trackers: vec![tracker],
+            interval: DEFAULT_HEARTBEAT_INTERVAL,
+            timeout: DEFAULT_HEARTBEAT_INTERVAL,
+            miss_threshold: 2,
+            flap_transitions,
+            flap_window,
+            handler: Some(Box::new(move |event| {
+                let _ = events_tx.send(event);
+            })),
+            counter: 0,
+        };
+        (worker, events, start)
+    }
+
+    #[test]
+    fn test_misses_reach_offline_threshold() {
+        let (mut worker, events, start) = worker(DEFAULT_FLAP_TRANSITIONS, DEFAULT_FLAP_WINDOW);
+        let device = worker.trackers[0].status.device;
+
+        worker.miss(0, start + Duration::from_secs(1));
+        assert_eq!(worker.trackers[0].status.state, LinkState::Unknown);
+        assert!(events.try_recv().is_err());
+
+        let offline_at = start + Duration::from_secs(2);
+        worker.miss(0, offline_at);
+        assert_eq!(events.try_recv().unwrap(), HeartbeatEvent::Offline { device, missed: 2 });
+        // 事件发出前已经发布状态；从 Unknown 的第一次切换不计入抖动
+        let status = worker.status.lock().unwrap()[0].clone();
+        assert_eq!((status.state, status.since), (LinkState::Offline, offline_at));
+        assert!(worker.trackers[0].transitions.is_empty());
+
+        // 已经离线时继续丢失只累计次数
+        worker.miss(0, start + Duration::from_secs(3));
+        assert_eq!(worker.trackers[0].status.missed, 3);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_flapping_follows_transition_window() {
+        let (mut worker, events, start) = worker(3, Duration::from_secs(10));
+        let device = worker.trackers[0].status.device;
+        let at = |secs| start + Duration::from_secs(secs);
+
+        worker.transition(0, LinkState::Offline, at(0));
+        worker.transition(0, LinkState::Online, at(1));
+        worker.transition(0, LinkState::Offline, at(2));
+        assert!(events.try_recv().is_err());
+        worker.transition(0, LinkState::Online, at(3));
+        assert_eq!(events.try_recv().unwrap(), HeartbeatEvent::Flapping { device, transitions: 3 });
+        assert!(worker.trackers[0].status.flapping);
+
+        // 正好在窗口边界上的切换仍然计入，超出后抖动结束
+        worker.check_flapping(0, at(11));
+        assert!(events.try_recv().is_err());
+        worker.check_flapping(0, at(11) + Duration::from_millis(1));
+        assert_eq!(events.try_recv().unwrap(), HeartbeatEvent::FlappingEnded { device });
+        assert!(!worker.status.lock().unwrap()[0].flapping);
+        assert_eq!(worker.trackers[0].transitions.len(), 2);
+    }
+
+    #[test]
+    fn test_reply_matches_pending_heartbeat() {
+        let (mut worker, events, start) = worker(DEFAULT_FLAP_TRANSITIONS, DEFAULT_FLAP_WINDOW);
+        let device = worker.trackers[0].status.device;
+        worker.trackers[0].pending = Some((7, start));
+        worker.trackers[0].status.missed = 1;
+
+        // Frame Length 为 0xFFFF 的数据报、计数不符的回复和其他地址发来的回复都被忽略
+        let bad = [0x55, 0xBB, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00];
+        worker.on_reply(&device.addr, &bad);
+        let stale = HeartbeatRequest::new(DeviceType::FPGA, 1, 6).reply(0x5A, 6);
+        worker.on_reply(&device.addr, &stale);
+        let reply = HeartbeatRequest::new(DeviceType::FPGA, 1, 7).reply(0x5A, 7);
+        worker.on_reply(&MemoryAddr(u64::MAX), &reply);
+        assert!(worker.trackers[0].pending.is_some());
+        assert!(events.try_recv().is_err());
+
+        worker.on_reply(&device.addr, &reply);
+        let HeartbeatEvent::Online { device: online, rtt } = events.try_recv().unwrap() else {
+            panic!("expected online event");
+        };
+        assert_eq!(online, device);
+        let status = &worker.trackers[0].status;
+        assert!(worker.trackers[0].pending.is_none());
+        assert_eq!((status.missed, status.received, status.device_status), (0, 1, Some(0x5A)));
+        assert_eq!((status.last_rtt, status.srtt), (Some(rtt), Some(rtt)));
+    }
+
+    // 端到端只检查事件顺序，不依赖具体的时间
+    #[test]
+    fn test_online_offline_end_to_end() {
+        let network = MemoryNetwork::new();
+        let answering = Arc::new(AtomicBool::new(true));
+        let device = start_device(&network, answering.clone());
+        let (events_tx, events) = mpsc::channel();
+        let handle = HeartbeatMonitor::new(UdpClient::from_socket(network.bind()))
+            .device(device.local_addr(), DeviceType::FPGA, 1)
+            .with_interval(Duration::from_millis(20))
+            .with_miss_threshold(2)
+            .on_event(move |event| {
+                let _ = events_tx.send(event);
+            })
+            .start()
+            .unwrap();
+
+        let HeartbeatEvent::Online { device: online, .. } = events.recv_timeout(EVENT_TIMEOUT).unwrap() else {
+            panic!("expected online event");
+        };
+        assert_eq!(online.addr, device.local_addr());
+
+        answering.store(false, Ordering::Relaxed);
+        let offline = events.recv_timeout(EVENT_TIMEOUT).unwrap();
+        assert!(matches!(offline, HeartbeatEvent::Offline { missed: 2, .. }));
+
+        let status = &handle.status()[0];
+        assert_eq!(status.state, LinkState::Offline);
+        assert_eq!(status.device_status, Some(0x5A));
+        assert!(status.last_rtt.is_some() && status.srtt.is_some());
+        assert!(status.received >= 1 && status.sent > status.received);
+        handle.shutdown().unwrap();
+        device.shutdown().unwrap();
+    }
+}
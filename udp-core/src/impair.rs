@@ -0,0 +1,578 @@
+// impair.rs
+// 链路损伤模拟：丢包（含 Gilbert-Elliott 突发丢包）、固定和抖动延迟、乱序、重复、比特错误和带宽限制。
+// ImpairedTransport 包装任意传输，只对发出的数据报施加损伤；两端都包装即可模拟双向损伤。
+// 使用相同的种子和相同的发送序列时，每个数据报的处理结果完全相同。
+use crate::batch::RecvBatch;
+use crate::transport::DatagramTransport;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Gilbert-Elliott 两状态突发丢包模型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GilbertElliott {
+    /// 每个数据报从好状态进入坏状态的概率
+    pub p_good_to_bad: f64,
+    /// 每个数据报从坏状态回到好状态的概率
+    pub p_bad_to_good: f64,
+    /// 好状态下的丢包率
+    pub loss_good: f64,
+    /// 坏状态下的丢包率
+    pub loss_bad: f64,
+}
+
+impl GilbertElliott {
+    // 好状态不丢包、坏状态全部丢包（即 Gilbert 模型），平均突发长度为 1 / p_bad_to_good
+    pub fn new(p_good_to_bad: f64, p_bad_to_good: f64) -> Self {
+        GilbertElliott {
+            p_good_to_bad,
+            p_bad_to_good,
+            loss_good: 0.0,
+            loss_bad: 1.0,
+        }
+    }
+
+    pub fn with_loss(mut self, loss_good: f64, loss_bad: f64) -> Self {
+        self.loss_good = loss_good;
+        self.loss_bad = loss_bad;
+        self
+    }
+}
+
+/// 损伤参数，概率取值 0.0 ~ 1.0
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImpairmentConfig {
+    /// 独立随机丢包率
+    pub loss: f64,
+    /// 突发丢包模型，和独立丢包叠加
+    pub burst_loss: Option<GilbertElliott>,
+    /// 固定延迟
+    pub delay: Duration,
+    /// 延迟抖动，实际延迟在 delay ± jitter 内均匀分布
+    pub jitter: Duration,
+    /// 数据报被额外滞留 reorder_gap、让后续数据报超过它的概率
+    pub reorder: f64,
+    pub reorder_gap: Duration,
+    /// 数据报被发送两次的概率
+    pub duplicate: f64,
+    /// 数据报被翻转一个随机比特的概率
+    pub corrupt: f64,
+    /// 链路带宽（字节/秒），超过时数据报排队等待
+    pub bandwidth: Option<u64>,
+    /// 随机数种子，None 时随机选择
+    pub seed: Option<u64>,
+}
+
+impl ImpairmentConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_loss(mut self, loss: f64) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    pub fn with_burst_loss(mut self, model: GilbertElliott) -> Self {
+        self.burst_loss = Some(model);
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration, jitter: Duration) -> Self {
+        self.delay = delay;
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_reorder(mut self, reorder: f64, gap: Duration) -> Self {
+        self.reorder = reorder;
+        self.reorder_gap = gap;
+        self
+    }
+
+    pub fn with_duplicate(mut self, duplicate: f64) -> Self {
+        self.duplicate = duplicate;
+        self
+    }
+
+    pub fn with_corrupt(mut self, corrupt: f64) -> Self {
+        self.corrupt = corrupt;
+        self
+    }
+
+    pub fn with_bandwidth(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+/// 损伤计数器
+#[derive(Debug, Default)]
+pub struct ImpairmentStats {
+    packets: AtomicU64,
+    dropped: AtomicU64,
+    duplicated: AtomicU64,
+    corrupted: AtomicU64,
+    reordered: AtomicU64,
+}
+
+impl ImpairmentStats {
+    // 经过损伤处理的数据报
+    pub fn packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn duplicated(&self) -> u64 {
+        self.duplicated.load(Ordering::Relaxed)
+    }
+
+    pub fn corrupted(&self) -> u64 {
+        self.corrupted.load(Ordering::Relaxed)
+    }
+
+    pub fn reordered(&self) -> u64 {
+        self.reordered.load(Ordering::Relaxed)
+    }
+}
+
+/// 损伤处理器，决定每个数据报是否丢弃、何时送达以及送达的内容
+pub struct Impairment {
+    config: ImpairmentConfig,
+    seed: u64,
+    rng: StdRng,
+    // Gilbert-Elliott 当前是否处于坏状态
+    bad_state: bool,
+    // 带宽限制下链路空闲的时间
+    link_free_at: Option<Instant>,
+    stats: Arc<ImpairmentStats>,
+}
+
+impl Impairment {
+    pub fn new(config: ImpairmentConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        Impairment {
+            config,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            bad_state: false,
+            link_free_at: None,
+            stats: Arc::new(ImpairmentStats::default()),
+        }
+    }
+
+    // 多个处理器共享同一组计数器
+    pub(crate) fn with_stats(mut self, stats: Arc<ImpairmentStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    // 实际使用的种子，用于复现
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stats(&self) -> Arc<ImpairmentStats> {
+        self.stats.clone()
+    }
+
+    // 处理在 now 发出的数据报，返回每个副本的送达时间和内容；丢弃时返回空
+    pub fn process(&mut self, data: &[u8], now: Instant) -> Vec<(Instant, Vec<u8>)> {
+        self.stats.packets.fetch_add(1, Ordering::Relaxed);
+        if self.lose() {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return Vec::new();
+        }
+
+        let copies = if self.chance(self.config.duplicate) {
+            self.stats.duplicated.fetch_add(1, Ordering::Relaxed);
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                let mut packet = data.to_vec();
+                if !packet.is_empty() && self.chance(self.config.corrupt) {
+                    let bit = self.rng.gen_range(0..packet.len() * 8);
+                    packet[bit / 8] ^= 1 << (bit % 8);
+                    self.stats.corrupted.fetch_add(1, Ordering::Relaxed);
+                }
+                let mut deliver_at = self.transmit(now, packet.len()) + self.delay();
+                if self.chance(self.config.reorder) {
+                    deliver_at += self.config.reorder_gap;
+                    self.stats.reordered.fetch_add(1, Ordering::Relaxed);
+                }
+                (deliver_at, packet)
+            })
+            .collect()
+    }
+
+    // 概率为 0 时不消耗随机数，关闭的损伤项不影响其他项的随机序列
+    fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && self.rng.gen_bool(p.min(1.0))
+    }
+
+    fn lose(&mut self) -> bool {
+        if let Some(model) = self.config.burst_loss {
+            let transition = if self.bad_state { model.p_bad_to_good } else { model.p_good_to_bad };
+            if self.chance(transition) {
+                self.bad_state = !self.bad_state;
+            }
+            let loss = if self.bad_state { model.loss_bad } else { model.loss_good };
+            if self.chance(loss) {
+                return true;
+            }
+        }
+        self.chance(self.config.loss)
+    }
+
+    fn delay(&mut self) -> Duration {
+        let jitter = self.config.jitter.as_nanos() as u64;
+        if jitter == 0 {
+            return self.config.delay;
+        }
+        let offset = Duration::from_nanos(self.rng.gen_range(0..=2 * jitter));
+        (self.config.delay + offset).saturating_sub(self.config.jitter)
+    }
+
+    // 按带宽计算数据报发送完成的时间
+    fn transmit(&mut self, now: Instant, len: usize) -> Instant {
+        let Some(bandwidth) = self.config.bandwidth.filter(|&b| b > 0) else {
+            return now;
+        };
+        let start = self.link_free_at.map_or(now, |free_at| free_at.max(now));
+        let done = start + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+        self.link_free_at = Some(done);
+        done
+    }
+}
+
+// 等待送达的数据报，按送达时间、其次按发送顺序排列
+struct Scheduled<A> {
+    deliver_at: Instant,
+    seq: u64,
+    addr: A,
+    data: Vec<u8>,
+    prioritized: bool,
+}
+
+impl<A> PartialEq for Scheduled<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl<A> Eq for Scheduled<A> {}
+
+impl<A> PartialOrd for Scheduled<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A> Ord for Scheduled<A> {
+    // BinaryHeap 是大顶堆，反转后最早送达的在堆顶
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (other.deliver_at, other.seq).cmp(&(self.deliver_at, self.seq))
+    }
+}
+
+struct Pending<A> {
+    heap: BinaryHeap<Scheduled<A>>,
+    next_seq: u64,
+    closed: bool,
+}
+
+struct Link<T: DatagramTransport> {
+    impairment: Mutex<Impairment>,
+    pending: Mutex<Pending<T::Addr>>,
+    ready: Condvar,
+}
+
+impl<T: DatagramTransport> Link<T> {
+    // 送达线程：按时间发送延迟的数据报，所有句柄释放后退出，未送达的数据报丢弃
+    fn deliver(&self, socket: T) {
+        let mut pending = self.pending.lock().unwrap();
+        loop {
+            if pending.closed {
+                return;
+            }
+            let now = Instant::now();
+            match pending.heap.peek().map(|next| next.deliver_at) {
+                Some(deliver_at) if deliver_at <= now => {
+                    let packet = pending.heap.pop().unwrap();
+                    drop(pending);
+                    let _ = if packet.prioritized {
+                        socket.send_prioritized(&packet.data, &packet.addr)
+                    } else {
+                        socket.send_to(&packet.data, &packet.addr)
+                    };
+                    pending = self.pending.lock().unwrap();
+                }
+                Some(deliver_at) => {
+                    pending = self.ready.wait_timeout(pending, deliver_at - now).unwrap().0;
+                }
+                None => {
+                    pending = self.ready.wait(pending).unwrap();
+                }
+            }
+        }
+    }
+}
+
+// 最后一个句柄释放时通知送达线程退出
+struct LinkGuard<T: DatagramTransport>(Arc<Link<T>>);
+
+impl<T: DatagramTransport> Drop for LinkGuard<T> {
+    fn drop(&mut self) {
+        self.0.pending.lock().unwrap().closed = true;
+        self.0.ready.notify_all();
+    }
+}
+
+/// 对发出的数据报施加损伤的传输包装，接收不受影响
+pub struct ImpairedTransport<T: DatagramTransport> {
+    inner: T,
+    link: Arc<LinkGuard<T>>,
+}
+
+impl<T: DatagramTransport> ImpairedTransport<T> {
+    pub fn new(inner: T, config: ImpairmentConfig) -> io::Result<Self> {
+        Self::with_impairment(inner, Impairment::new(config))
+    }
+
+    pub(crate) fn with_impairment(inner: T, impairment: Impairment) -> io::Result<Self> {
+        let link = Arc::new(Link {
+            impairment: Mutex::new(impairment),
+            pending: Mutex::new(Pending {
+                heap: BinaryHeap::new(),
+                next_seq: 0,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        });
+        let socket = inner.try_clone()?;
+        let deliver_link = link.clone();
+        thread::spawn(move || deliver_link.deliver(socket));
+        Ok(ImpairedTransport {
+            inner,
+            link: Arc::new(LinkGuard(link)),
+        })
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    // 实际使用的种子
+    pub fn seed(&self) -> u64 {
+        self.link.0.impairment.lock().unwrap().seed()
+    }
+
+    pub fn stats(&self) -> Arc<ImpairmentStats> {
+        self.link.0.impairment.lock().unwrap().stats()
+    }
+
+    // 到期的副本直接发送，其余交给送达线程
+    fn send_impaired(&self, buf: &[u8], addr: &T::Addr, prioritized: bool) -> io::Result<usize> {
+        let now = Instant::now();
+        let copies = self.link.0.impairment.lock().unwrap().process(buf, now);
+        for (deliver_at, data) in copies {
+            if deliver_at <= now {
+                if prioritized {
+                    self.inner.send_prioritized(&data, addr)?;
+                } else {
+                    self.inner.send_to(&data, addr)?;
+                }
+                continue;
+            }
+            let mut pending = self.link.0.pending.lock().unwrap();
+            let seq = pending.next_seq;
+            pending.next_seq += 1;
+            pending.heap.push(Scheduled {
+                deliver_at,
+                seq,
+                addr: addr.clone(),
+                data,
+                prioritized,
+            });
+            self.link.0.ready.notify_one();
+        }
+        // 和真实链路一样，丢弃的数据报对发送方来说也是发送成功
+        Ok(buf.len())
+    }
+}
+
+impl<T: DatagramTransport> DatagramTransport for ImpairedTransport<T> {
+    type Addr = T::Addr;
+
+    fn send_to(&self, buf: &[u8], addr: &T::Addr) -> io::Result<usize> {
+        self.send_impaired(buf, addr, false)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, T::Addr)> {
+        self.inner.recv_from(buf)
+    }
+
+    fn local_addr(&self) -> io::Result<T::Addr> {
+        self.inner.local_addr()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.inner.set_nonblocking(nonblocking)
+    }
+
+    // 克隆的句柄共享同一条损伤链路
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(ImpairedTransport {
+            inner: self.inner.try_clone()?,
+            link: self.link.clone(),
+        })
+    }
+
+    fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, T::Addr)> {
+        self.inner.recv_from_checked(buf)
+    }
+
+    fn recv_batch(&self, batch: &mut RecvBatch<T::Addr>) -> io::Result<usize> {
+        self.inner.recv_batch(batch)
+    }
+
+    fn send_prioritized(&self, buf: &[u8], addr: &T::Addr) -> io::Result<usize> {
+        self.send_impaired(buf, addr, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryNetwork, UdpClient};
+
+    fn run(config: &ImpairmentConfig, packets: usize) -> Vec<Vec<(Instant, Vec<u8>)>> {
+        let now = Instant::now();
+        let mut impairment = Impairment::new(config.clone());
+        (0..packets).map(|i| impairment.process(&(i as u32).to_le_bytes(), now)).collect()
+    }
+
+    #[test]
+    fn test_seeded_runs_reproducible() {
+        let config = ImpairmentConfig::new()
+            .with_loss(0.1)
+            .with_delay(Duration::from_millis(10), Duration::from_millis(5))
+            .with_reorder(0.1, Duration::from_millis(20))
+            .with_duplicate(0.1)
+            .with_corrupt(0.1)
+            .with_seed(42);
+        let now = Instant::now();
+        let mut a = Impairment::new(config.clone());
+        let mut b = Impairment::new(config);
+        for i in 0..1000u32 {
+            assert_eq!(a.process(&i.to_le_bytes(), now), b.process(&i.to_le_bytes(), now));
+        }
+        assert_eq!(a.stats().dropped(), b.stats().dropped());
+        assert!(a.stats().dropped() > 0 && a.stats().duplicated() > 0 && a.stats().corrupted() > 0);
+    }
+
+    #[test]
+    fn test_loss_rate() {
+        let results = run(&ImpairmentConfig::new().with_loss(0.3).with_seed(1), 10000);
+        let lost = results.iter().filter(|copies| copies.is_empty()).count();
+        assert!((2700..3300).contains(&lost), "lost {}", lost);
+    }
+
+    #[test]
+    fn test_burst_loss_clusters() {
+        // 平均突发长度约 1 / 0.25 = 4
+        let config = ImpairmentConfig::new().with_burst_loss(GilbertElliott::new(0.02, 0.25)).with_seed(7);
+        let results = run(&config, 20000);
+        let bursts: Vec<usize> = results
+            .split(|copies| !copies.is_empty())
+            .map(|run| run.len())
+            .filter(|&len| len > 0)
+            .collect();
+        let mean = bursts.iter().sum::<usize>() as f64 / bursts.len() as f64;
+        assert!(mean > 2.5, "mean burst length {}", mean);
+    }
+
+    #[test]
+    fn test_delay_jitter_and_bandwidth() {
+        let now = Instant::now();
+        let mut jittered = Impairment::new(
+            ImpairmentConfig::new().with_delay(Duration::from_millis(50), Duration::from_millis(10)).with_seed(3),
+        );
+        for _ in 0..100 {
+            let (deliver_at, _) = jittered.process(b"x", now)[0];
+            assert!((Duration::from_millis(40)..=Duration::from_millis(60)).contains(&(deliver_at - now)));
+        }
+
+        // 1000 字节/秒，10 个 100 字节的数据报同时发出，依次排队
+        let mut capped = Impairment::new(ImpairmentConfig::new().with_bandwidth(1000));
+        let times: Vec<Duration> = (0..10).map(|_| capped.process(&[0; 100], now)[0].0 - now).collect();
+        assert_eq!(times[0], Duration::from_millis(100));
+        assert_eq!(times[9], Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_duplicate_corrupt_and_reorder() {
+        let now = Instant::now();
+        let config = ImpairmentConfig::new()
+            .with_duplicate(1.0)
+            .with_corrupt(1.0)
+            .with_reorder(1.0, Duration::from_millis(30))
+            .with_seed(5);
+        let mut impairment = Impairment::new(config);
+        let data = [0xA5u8; 16];
+        let copies = impairment.process(&data, now);
+        assert_eq!(copies.len(), 2);
+        for (deliver_at, packet) in &copies {
+            assert_eq!(*deliver_at - now, Duration::from_millis(30));
+            let flipped: u32 = packet.iter().zip(&data).map(|(a, b)| (a ^ b).count_ones()).sum();
+            assert_eq!(flipped, 1);
+        }
+        let stats = impairment.stats();
+        assert_eq!((stats.duplicated(), stats.corrupted(), stats.reordered()), (1, 2, 2));
+    }
+
+    #[test]
+    fn test_impaired_transport_delays_and_drops() {
+        let network = MemoryNetwork::new();
+        let (a, b) = (network.bind(), network.bind());
+        let peer = b.addr();
+        let delayed = ImpairedTransport::new(a, ImpairmentConfig::new().with_delay(Duration::from_millis(50), Duration::ZERO))
+            .unwrap();
+        b.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+
+        let start = Instant::now();
+        delayed.send_to(b"late", &peer).unwrap();
+        let mut buf = [0; 16];
+        let (n, _) = b.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"late");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let lossy = UdpClient::from_socket(
+            ImpairedTransport::new(network.bind(), ImpairmentConfig::new().with_loss(1.0)).unwrap(),
+        );
+        let err = lossy.send_and_receive(peer, b"lost", Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(lossy.socket.stats().dropped(), 1);
+    }
+}
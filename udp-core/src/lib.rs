@@ -1,14 +1,92 @@
 use std::net::{SocketAddr, UdpSocket};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io;
-use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
+pub mod retry;
+pub mod reliable;
+pub mod queue;
+pub mod stats;
+pub mod pool;
+pub mod batch;
+pub mod offload;
+pub mod error;
+pub mod sockopt;
+pub mod multicast;
+pub mod discovery;
+pub mod frame;
+pub mod device;
+pub mod transport;
+pub mod memory;
+pub mod impair;
+pub mod proxy;
+pub mod tcp;
+pub mod bridge;
+pub mod pacer;
+pub mod metrics;
+pub mod peers;
+pub mod heartbeat;
+pub mod transfer;
+#[cfg(feature = "logging")]
+pub mod logging;
+#[cfg(target_os = "linux")]
+pub mod reuseport;
+#[cfg(target_os = "linux")]
+pub mod serial;
+
+pub use crate::retry::{RetryPolicy, RetryResponse};
+pub use crate::reliable::{ReliableChannel, ReliableConfig, ReliableStats};
+pub use crate::queue::OverflowPolicy;
+pub use crate::stats::{ServerStats, ServerStatsSnapshot};
+pub use crate::pool::ReplySender;
+pub use crate::batch::RecvBatch;
+pub use crate::error::{DeviceError, TruncatedDatagram, device_error_code, is_truncated};
+pub use crate::sockopt::{PmtuDiscovery, SocketOptions};
+pub use crate::multicast::MulticastInterface;
+pub use crate::discovery::{DiscoveredDevice, Scanner};
+pub use crate::frame::{FrameRequest, FrameServer, FrameServerHandle, FrameStats, Route};
+pub use crate::device::DeviceClient;
+pub use crate::transport::DatagramTransport;
+pub use crate::memory::{MemoryAddr, MemoryNetwork, MemoryTransport, memory_pair};
+pub use crate::impair::{GilbertElliott, ImpairedTransport, Impairment, ImpairmentConfig, ImpairmentStats};
+pub use crate::proxy::{ImpairmentProxy, ProxyHandle};
+pub use crate::tcp::{TcpClient, TcpConfig, TcpServer, TcpTransport};
+pub use crate::bridge::{Bridge, BridgeHandle, BridgeRoute, BridgeStats, Endpoint};
+pub use crate::pacer::Pacer;
+pub use crate::metrics::{Counter, Histogram, HistogramSnapshot, MetricsSnapshot, Registry};
+pub use crate::peers::{PeerEvent, PeerInfo, PeerTable};
+pub use crate::heartbeat::{
+    DeviceStatus, HeartbeatEvent, HeartbeatHandle, HeartbeatMonitor, LinkState, MonitoredDevice, heartbeat_reply,
+};
+pub use crate::transfer::{FileReceiver, FileSender, TransferOffer, TransferProgress, TransferReport};
+#[cfg(feature = "logging")]
+pub use crate::logging::LogFormat;
+#[cfg(target_os = "linux")]
+pub use crate::reuseport::{MultiServerHandle, ReusePortServer};
+#[cfg(target_os = "linux")]
+pub use crate::serial::{Parity, SerialConfig, SerialTransport, StopBits, pty_pair};
+
+use crate::metrics::TransportMetrics;
+use crate::queue::{BoundedQueue, CloseAllOnDrop, CloseOnDrop, PushOutcome};
+
 const PORT_RANGE_START: u16 = 58052;
 const PORT_RANGE_END: u16 = 58080;
+// 接收线程检查停止标志的间隔
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
-pub struct UdpClient {
-    pub socket: UdpSocket,
+/// 默认的最大数据报长度
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 8192;
+/// 可配置的最大数据报长度上限
+pub const MAX_DATAGRAM_SIZE: usize = 65535;
+
+pub struct UdpClient<T: DatagramTransport = UdpSocket> {
+    pub socket: T,
+    max_datagram_size: usize,
+    auto_dscp: bool,
+    pacer: Option<Arc<Pacer>>,
+    metrics: Option<TransportMetrics>,
 }
 
 impl UdpClient {
@@ -17,85 +95,409 @@ impl UdpClient {
         for port in PORT_RANGE_START..=PORT_RANGE_END {
             match UdpSocket::bind(("0.0.0.0", port)) {
                 Ok(socket) => {
-                    return Ok(UdpClient { socket });
+                    return Ok(Self::from_socket(socket));
                 }
                 Err(_) => continue,
             }
         }
         Err(io::Error::new(io::ErrorKind::AddrNotAvailable, "No available ports in range"))
     }
+}
+
+impl<T: DatagramTransport> UdpClient<T> {
+    // 使用已经绑定好的 socket 创建客户端
+    pub fn from_socket(socket: T) -> Self {
+        UdpClient {
+            socket,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            auto_dscp: false,
+            pacer: None,
+            metrics: None,
+        }
+    }
+
+    // 发送前按 pacer 限速，多个客户端共享同一个 pacer 时限制总速率
+    pub fn with_pacer(mut self, pacer: Arc<Pacer>) -> Self {
+        self.pacer = Some(pacer);
+        self
+    }
+
+    // 把收发字节数、数据报个数和请求往返时间记录到 registry
+    pub fn with_metrics(mut self, registry: Arc<Registry>) -> Self {
+        self.metrics = Some(TransportMetrics::new(registry));
+        self
+    }
+
+    // 设置接收回包的最大长度（不超过 64KB），超过的回包返回 TruncatedDatagram 错误
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size.clamp(1, MAX_DATAGRAM_SIZE);
+        self
+    }
+
+    pub fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
 
     // 发送消息并等待回包，带超时
-    pub fn send_and_receive(&self, addr: SocketAddr, msg: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
-        self.socket.send_to(msg, addr)?;
+    pub fn send_and_receive(&self, addr: T::Addr, msg: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+        let sent_at = self.send_timed(addr, msg)?;
 
         // 设置接收超时
         self.socket.set_read_timeout(Some(timeout))?;
 
-        let mut buf = vec![0; 8192];
-        let (num_bytes, _) = self.socket.recv_from(&mut buf)?;
+        let mut buf = vec![0; self.max_datagram_size];
+        let (num_bytes, _) = self.socket.recv_from_checked(&mut buf).inspect_err(|e| self.record_error(e))?;
+        self.record_reply(num_bytes, Some(sent_at.elapsed()));
         buf.truncate(num_bytes);
         Ok(buf)
     }
 
     // 只发送消息，不等待回包
-    pub fn send_only(&self, addr: SocketAddr, msg: &[u8]) -> io::Result<()> {
-        self.socket.send_to(msg, addr)?;
-        Ok(())
+    pub fn send_only(&self, addr: T::Addr, msg: &[u8]) -> io::Result<()> {
+        self.send_timed(addr, msg).map(|_| ())
+    }
+
+    // 发送消息并返回实际发出的时刻（限速等待之后），用于计算往返时间
+    pub(crate) fn send_timed(&self, addr: T::Addr, msg: &[u8]) -> io::Result<Instant> {
+        self.pace(msg.len());
+        if self.auto_dscp {
+            self.socket.send_prioritized(msg, &addr)?;
+        } else {
+            self.socket.send_to(msg, &addr)?;
+        }
+        let sent_at = Instant::now();
+        self.record_sent(msg.len());
+        Ok(sent_at)
+    }
+
+    // 设置了 pacer 时等待到可以发送 len 字节的数据报
+    pub(crate) fn pace(&self, len: usize) {
+        if let Some(pacer) = &self.pacer {
+            pacer.acquire(len);
+        }
+    }
+
+    // 记录一个发出的数据报
+    pub(crate) fn record_sent(&self, len: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.sent(len);
+        }
+    }
+
+    // 记录一个回包及其往返时间，无法确定往返时间（如重传过）时 rtt 为 None
+    pub(crate) fn record_reply(&self, len: usize, rtt: Option<Duration>) {
+        if let Some(metrics) = &self.metrics {
+            metrics.received(len);
+            if let Some(rtt) = rtt {
+                metrics.rtt(rtt);
+            }
+        }
+    }
+
+    // 记录超时以外的接收错误
+    pub(crate) fn record_error(&self, e: &io::Error) {
+        if let Some(metrics) = self.metrics.as_ref().filter(|_| !retry::is_timeout(e)) {
+            metrics.receive_error();
+        }
     }
 
     // 获取客户端绑定的本地地址
-    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+    pub fn local_addr(&self) -> io::Result<T::Addr> {
         self.socket.local_addr()
     }
 }
 
-pub struct UdpServer {
-    pub socket: UdpSocket,
+/// 接收错误回调，在接收线程中调用
+pub type ErrorHandler = Box<dyn FnMut(io::Error) + Send>;
+
+// 接收队列中的数据报
+type Datagram<A = SocketAddr> = (A, Vec<u8>);
+
+// 默认接收队列容量
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+pub struct UdpServer<T: DatagramTransport = UdpSocket> {
+    pub socket: T,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    error_handler: Option<ErrorHandler>,
+    gro: bool,
+    max_datagram_size: usize,
+    auto_dscp: bool,
+    metrics: Option<TransportMetrics>,
+    peers: Option<Arc<PeerTable<T::Addr>>>,
 }
 
 impl UdpServer {
     // 创建新的UDP服务器，监听指定端口
     pub fn bind(port: u16) -> io::Result<Self> {
         let socket = UdpSocket::bind(("0.0.0.0", port))?;
-        Ok(UdpServer { socket })
+        Ok(Self::from_socket(socket))
     }
 
-    // 异步启动服务器，接收消息并通过回调处理
-    pub fn start_async<F>(&self, callback: F) -> io::Result<()>
+    // 接收时开启 GRO，回调收到的始终是单个数据报；内核不支持或开启失败时退化为逐个接收
+    pub fn with_gro(mut self) -> Self {
+        self.gro = offload::enable_gro(&self.socket).unwrap_or(false);
+        self
+    }
+}
+
+impl<T: DatagramTransport> UdpServer<T> {
+    // 使用已经绑定好的 socket 创建服务器
+    pub fn from_socket(socket: T) -> Self {
+        UdpServer {
+            socket,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
+            error_handler: None,
+            gro: false,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            auto_dscp: false,
+            metrics: None,
+            peers: None,
+        }
+    }
+
+    // 把接收、回包、队列丢弃和接收错误记录到 registry
+    pub fn with_metrics(mut self, registry: Arc<Registry>) -> Self {
+        self.metrics = Some(TransportMetrics::new(registry));
+        self
+    }
+
+    // 设置接收队列容量和队列满时的处理策略
+    pub fn with_queue(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.queue_capacity = capacity;
+        self.overflow_policy = policy;
+        self
+    }
+
+    // 设置接收错误回调，未设置时错误只计入统计
+    pub fn on_error<E>(mut self, handler: E) -> Self
     where
-        F: FnMut(SocketAddr, &[u8]) + Send + 'static,
+        E: FnMut(io::Error) + Send + 'static,
     {
-        let (tx, rx) = mpsc::channel();
-        let socket_clone = self.socket.try_clone()?;
-
-        // 启动接收线程
-        thread::spawn(move || {
-            let mut buf = vec![0; 8192];
-            loop {
-                match socket_clone.recv_from(&mut buf) {
-                    Ok((num_bytes, src_addr)) => {
-                        // println!("Received {} bytes from {}", num_bytes, src_addr);
-                        let data = &buf[..num_bytes];
-                        tx.send((src_addr, data.to_vec())).unwrap();
-                    }
-                    Err(e) => {
-                        eprintln!("Error receiving data: {}", e);
-                        break;
-                    }
+        self.error_handler = Some(Box::new(handler));
+        self
+    }
+
+    // 设置最大数据报长度（不超过 64KB），超过的数据报计入 truncated 并通过错误回调报告
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size.clamp(1, MAX_DATAGRAM_SIZE);
+        self
+    }
+
+    // 启动前配置 socket，返回接收用的批量缓冲区
+    fn prepare_receive(&self) -> io::Result<RecvBatch<T::Addr>> {
+        // 使用读超时周期性检查停止标志
+        self.socket.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
+        // 开启 GRO 时缓冲区要容纳合并后的数据报，拆开后的每个数据报仍按最大长度检查
+        let buf_size = if self.gro {
+            offload::GRO_BUFFER_SIZE.max(self.max_datagram_size)
+        } else {
+            self.max_datagram_size
+        };
+        Ok(RecvBatch::new(batch::DEFAULT_BATCH_SIZE, buf_size).with_max_datagram_size(self.max_datagram_size))
+    }
+
+    // 异步启动服务器，接收消息并通过回调处理，返回用于停止和等待服务器的句柄
+    pub fn start_async<F>(self, callback: F) -> io::Result<ServerHandle<T::Addr>>
+    where
+        F: FnMut(T::Addr, &[u8]) + Send + 'static,
+    {
+        let batch = self.prepare_receive()?;
+        let queue = Arc::new(BoundedQueue::new(self.queue_capacity, self.overflow_policy));
+        let stats = Arc::new(ServerStats::with_metrics(self.metrics));
+        let peers = self.peers;
+        let socket = self.socket;
+        let mut error_handler = self.error_handler;
+        let local_addr = socket.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+
+        // 启动接收线程，线程退出时释放 socket
+        let receiver = {
+            let queue = queue.clone();
+            let stats = stats.clone();
+            let peers = peers.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                let monitor = Monitor {
+                    stats: &stats,
+                    peers: peers.as_deref(),
+                };
+                let queues = [queue];
+                receive_loop(&socket, batch, &running, monitor, &queues, &mut error_handler, |datagram| {
+                    enqueue(&queues[0], &stats, datagram)
+                });
+            })
+        };
+
+        // 启动处理线程，接收线程关闭队列后处理完剩余的数据再退出
+        let handler = {
+            let queue = queue.clone();
+            let stats = stats.clone();
+            thread::spawn(move || {
+                let _guard = CloseOnDrop(&queue);
+                let mut callback = callback;
+                while let Some((src_addr, data)) = queue.pop() {
+                    callback(src_addr, &data);
+                    stats.add_processed();
+                }
+            })
+        };
+
+        Ok(ServerHandle {
+            local_addr,
+            running,
+            stats,
+            peers,
+            queues: vec![queue],
+            threads: vec![receiver, handler],
+        })
+    }
+}
+
+// 把数据报放入队列并更新计数，消费端已退出时返回 false
+fn enqueue<A>(queue: &BoundedQueue<Datagram<A>>, stats: &ServerStats, datagram: Datagram<A>) -> bool {
+    match queue.push(datagram) {
+        PushOutcome::Queued => stats.add_queued(),
+        PushOutcome::QueuedDroppedOldest => {
+            stats.add_queued();
+            stats.add_dropped();
+        }
+        PushOutcome::DroppedNewest => stats.add_dropped(),
+        PushOutcome::Closed => return false,
+    }
+    true
+}
+
+// 接收线程记录计数和对端状态的位置
+struct Monitor<'a, A> {
+    stats: &'a ServerStats,
+    peers: Option<&'a PeerTable<A>>,
+}
+
+// 接收循环，直到停止标志被清除或 dispatch 返回 false；停止后把内核缓冲区中已到达的数据报也分发出去。
+// 返回或 panic 时都会关闭 queues，让处理线程退出
+fn receive_loop<T, D>(
+    socket: &T,
+    mut batch: RecvBatch<T::Addr>,
+    running: &AtomicBool,
+    monitor: Monitor<T::Addr>,
+    queues: &[Arc<BoundedQueue<Datagram<T::Addr>>>],
+    error_handler: &mut Option<ErrorHandler>,
+    mut dispatch: D,
+) where
+    T: DatagramTransport,
+    D: FnMut(Datagram<T::Addr>) -> bool,
+{
+    let _guard = CloseAllOnDrop(queues);
+    let Monitor { stats, peers } = monitor;
+    let mut report = |e: io::Error| {
+        stats.add_error();
+        tracing::warn!(error = %e, "receive failed");
+        if let Some(handler) = error_handler.as_mut() {
+            handler(e);
+        }
+    };
+
+    // 每次系统调用取一整批数据报，全部分发后再接收下一批
+    // 分发一批数据报，被截断的只计数并报告错误，不交给回调；消费端退出时返回 false
+    let mut dispatch_batch = |batch: &RecvBatch<T::Addr>, report: &mut dyn FnMut(io::Error)| {
+        for (index, (src_addr, data)) in batch.iter().enumerate() {
+            stats.add_received(data.len());
+            if let Some(peers) = peers {
+                peers.record(&src_addr, data, Instant::now());
+            }
+            if let Some(truncated) = batch.truncation(index) {
+                stats.add_truncated();
+                report(truncated.into());
+                continue;
+            }
+            if !dispatch((src_addr, data.to_vec())) {
+                return false;
+            }
+        }
+        true
+    };
+
+    while running.load(Ordering::Acquire) {
+        let received = socket.recv_batch(&mut batch);
+        if let Some(peers) = peers {
+            peers.expire(Instant::now());
+        }
+        match received {
+            Ok(_) => {
+                if !dispatch_batch(&batch, &mut report) {
+                    report(io::Error::new(io::ErrorKind::BrokenPipe, "Handler thread exited"));
+                    return;
                 }
             }
-        });
+            Err(e) if retry::is_timeout(&e) => continue,
+            Err(e) => report(e),
+        }
+    }
 
-        // 启动处理线程
-        thread::spawn(move || {
-            let mut callback = callback;
-            for (src_addr, data) in rx {
-                callback(src_addr, &data);
+    if socket.set_nonblocking(true).is_ok() {
+        while socket.recv_batch(&mut batch).is_ok() {
+            if !dispatch_batch(&batch, &mut report) {
+                return;
             }
-        });
+        }
+    }
+}
+
+/// 服务器运行句柄，用于停止接收并等待后台线程退出
+///
+/// 直接丢弃句柄不会停止服务器，线程会继续在后台运行。
+pub struct ServerHandle<A = SocketAddr> {
+    local_addr: A,
+    running: Arc<AtomicBool>,
+    stats: Arc<ServerStats>,
+    peers: Option<Arc<PeerTable<A>>>,
+    queues: Vec<Arc<BoundedQueue<Datagram<A>>>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl<A: Clone> ServerHandle<A> {
+    // 服务器监听的本地地址
+    pub fn local_addr(&self) -> A {
+        self.local_addr.clone()
+    }
 
-        Ok(())
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    // 接收、入队、丢弃和处理计数，服务器停止后仍可读取
+    pub fn stats(&self) -> Arc<ServerStats> {
+        self.stats.clone()
+    }
+
+    // 对端表，未开启时为 None
+    pub fn peers(&self) -> Option<Arc<PeerTable<A>>> {
+        self.peers.clone()
+    }
+
+    // 当前排队等待处理的数据报个数
+    pub fn queue_len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+
+    // 停止接收，等待队列中的数据全部交给回调处理后返回，返回时 socket 已关闭
+    pub fn shutdown(self) -> thread::Result<()> {
+        self.running.store(false, Ordering::Release);
+        self.join()
+    }
+
+    // 等待后台线程退出，不主动停止服务器
+    pub fn join(self) -> thread::Result<()> {
+        let mut result = Ok(());
+        for handle in self.threads {
+            if let Err(e) = handle.join() {
+                result = Err(e);
+            }
+        }
+        result
     }
 }
 
@@ -108,68 +510,181 @@ mod tests {
     
     #[test]
     fn test_send_and_receive() {
-        let server = UdpServer::bind(12345).unwrap();
+        let server = UdpServer::bind(0).unwrap();
         let client = UdpClient::new().unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], server.socket.local_addr().unwrap().port()).into();
 
         //克隆服务器socket一个实例，用于发送响应
         let server_socket = server.socket.try_clone().unwrap();
 
-        // 启动服务器线程，这里使用闭包语法，线程会获取所有权，此时move强制闭包获取线程所有权
-        let handle = thread::spawn(move || {
-            server.start_async(move |src_addr, data| {
-                let resp = format!("Echo: {}", String::from_utf8_lossy(data));
-                // 这里创建了一个随机的实例给客户端回复数据，端口会随机
-                // let server = UdpServer::bind(0).unwrap();
-                server_socket.send_to(resp.as_bytes(), src_addr).unwrap();
-                // server.socket.send_to(resp.as_bytes(), src_addr).unwrap();
-            }).unwrap();
-
-            // 保持线程运行一段时间
-            thread::sleep(Duration::from_secs(2));
-        });
+        // 启动服务器，这里使用闭包语法，此时move强制闭包获取所有权
+        let handle = server.start_async(move |src_addr, data| {
+            let resp = format!("Echo: {}", String::from_utf8_lossy(data));
+            // 这里创建了一个随机的实例给客户端回复数据，端口会随机
+            // let server = UdpServer::bind(0).unwrap();
+            server_socket.send_to(resp.as_bytes(), src_addr).unwrap();
+            // server.socket.send_to(resp.as_bytes(), src_addr).unwrap();
+        }).unwrap();
 
         // 客户端发送消息并接收回复
-        thread::sleep(Duration::from_millis(500));
-        let response = client.send_and_receive("127.0.0.1:12345".parse().unwrap(),
+        let response = client.send_and_receive(addr,
                                                b"Hello, server!",
                                                Duration::from_secs(1)).unwrap();
 
         assert!(response.starts_with(b"Echo: "));
 
-        handle.join().unwrap();
+        handle.shutdown().unwrap();
     }
 
     #[test]
     fn test_send_only() {
-        let server = UdpServer::bind(12346).unwrap();
+        let server = UdpServer::bind(0).unwrap();
         let client = UdpClient::new().unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], server.socket.local_addr().unwrap().port()).into();
 
         // 使用 Arc 和 Mutex 共享状态
         let received = Arc::new(Mutex::new(false));
-        let received_clone = Arc::clone(&received);
+        // 为回调创建一个单独的克隆
+        let callback_received = Arc::clone(&received);
 
-        // 启动服务器线程
-        let handle = thread::spawn(move || {
-            // 为回调创建一个单独的克隆
-            let callback_received = Arc::clone(&received_clone);
+        let handle = server.start_async(move |_src_addr, data| {
+            assert_eq!(data, b"Fire and forget!");
+            // 使用锁来修改共享状态
+            *callback_received.lock().unwrap() = true;
+        }).unwrap();
 
-            server.start_async(move |_src_addr, data| {
-                assert_eq!(data, b"Fire and forget!");
-                // 使用锁来修改共享状态
-                *callback_received.lock().unwrap() = true;
-            }).unwrap();
+        // 客户端发送消息
+        client.send_only(addr, b"Fire and forget!").unwrap();
 
-            // 保持线程运行一段时间，确保有足够时间接收消息
-            thread::sleep(Duration::from_secs(2));
+        // 保持一段时间，确保有足够时间接收消息
+        thread::sleep(Duration::from_millis(200));
+        handle.shutdown().unwrap();
 
-            // 检查是否接收到消息
-            assert!(*received_clone.lock().unwrap(), "Message not received");
-        });
+        // 检查是否接收到消息
+        assert!(*received.lock().unwrap(), "Message not received");
+    }
 
-        // 客户端发送消息
-        client.send_only("127.0.0.1:12346".parse().unwrap(), b"Fire and forget!").unwrap();
+    #[test]
+    fn test_shutdown_drains_and_releases_socket() {
+        let server = UdpServer::bind(0).unwrap();
+        let client = UdpClient::new().unwrap();
+
+        let processed = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&processed);
+        let handle = server.start_async(move |_src_addr, _data| {
+            // 处理较慢，让数据报在队列中积压
+            thread::sleep(Duration::from_millis(5));
+            *counter.lock().unwrap() += 1;
+        }).unwrap();
+
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        for _ in 0..50 {
+            client.send_only(addr, b"queued").unwrap();
+        }
+        handle.shutdown().unwrap();
 
-        // 等待服务器线程完成
-        handle.join().unwrap();
+        // 已到达的数据报全部交给了回调
+        assert_eq!(*processed.lock().unwrap(), 50);
+        // 端口已经释放，可以再次绑定
+        UdpServer::bind(addr.port()).unwrap();
     }
-}    
\ No newline at end of file
+
+    #[test]
+    fn test_drop_newest_counts_and_errors() {
+        let server = UdpServer::bind(0)
+            .unwrap()
+            .with_queue(2, OverflowPolicy::DropNewest)
+            .on_error(|e| panic!("unexpected receive error: {}", e));
+        let client = UdpClient::new().unwrap();
+
+        // 回调被阻塞，队列只能容纳两个数据报
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let handle = server.start_async(move |_src_addr, _data| {
+            let _ = release_rx.recv();
+        }).unwrap();
+        let stats = handle.stats();
+
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        for _ in 0..10 {
+            client.send_only(addr, b"burst").unwrap();
+        }
+        thread::sleep(Duration::from_millis(200));
+
+        // 回调取走一个后阻塞，队列里最多再放两个
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.received, 10);
+        assert!(snapshot.dropped >= 7);
+        assert_eq!(snapshot.queued + snapshot.dropped, 10);
+
+        drop(release_tx);
+        handle.shutdown().unwrap();
+        assert_eq!(stats.processed(), stats.queued());
+        assert_eq!(stats.errors(), 0);
+    }
+
+    #[test]
+    fn test_oversized_datagram_counted_and_reported() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported_clone = reported.clone();
+        let server = UdpServer::bind(0)
+            .unwrap()
+            .with_max_datagram_size(64)
+            .on_error(move |e| reported_clone.lock().unwrap().push(is_truncated(&e)));
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let delivered_clone = delivered.clone();
+        let handle = server
+            .start_async(move |_src_addr, data| delivered_clone.lock().unwrap().push(data.to_vec()))
+            .unwrap();
+        let stats = handle.stats();
+
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        let client = UdpClient::new().unwrap();
+        client.send_only(addr, &[0u8; 200]).unwrap();
+        client.send_only(addr, b"small").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        handle.shutdown().unwrap();
+
+        // 超长数据报不交给回调，只计数并通过错误回调报告
+        assert_eq!(*delivered.lock().unwrap(), vec![b"small".to_vec()]);
+        assert_eq!(*reported.lock().unwrap(), vec![true]);
+        assert_eq!(stats.truncated(), 1);
+    }
+
+    #[test]
+    fn test_client_reply_truncated() {
+        let server = UdpServer::bind(0).unwrap();
+        let handle = server
+            .start_pool(1, |_src_addr, _data, reply| {
+                reply.send(&[0xAA; 512]).unwrap();
+            })
+            .unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+
+        let client = UdpClient::new().unwrap().with_max_datagram_size(128);
+        let err = client.send_and_receive(addr, b"ping", Duration::from_secs(1)).unwrap_err();
+        assert!(is_truncated(&err));
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_handler_panic_reported_through_error_callback() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors_clone = Arc::clone(&errors);
+        let server = UdpServer::bind(0)
+            .unwrap()
+            .on_error(move |e| errors_clone.lock().unwrap().push(e.kind()));
+        let client = UdpClient::new().unwrap();
+
+        let handle = server.start_async(|_src_addr, _data| panic!("handler failure")).unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        for _ in 0..3 {
+            client.send_only(addr, b"boom").unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        // 处理线程 panic 后接收线程停止，而不是跟着 panic
+        assert!(handle.shutdown().is_err());
+        assert_eq!(*errors.lock().unwrap(), vec![io::ErrorKind::BrokenPipe]);
+    }
+}
@@ -0,0 +1,233 @@
+// memory.rs
+// 内存数据报网络：不占用端口，用于测试上层逻辑。
+// 每个端点有唯一的 MemoryAddr，发往不存在的地址的数据报和 UDP 一样被静默丢弃。
+use crate::error::TruncatedDatagram;
+use crate::transport::DatagramTransport;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 内存端点地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MemoryAddr(pub u64);
+
+impl fmt::Display for MemoryAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mem:{}", self.0)
+    }
+}
+
+type Packet = (MemoryAddr, Vec<u8>);
+
+#[derive(Default)]
+struct Registry {
+    next_addr: AtomicU64,
+    endpoints: Mutex<HashMap<MemoryAddr, Sender<Packet>>>,
+}
+
+/// 内存网络，同一网络内的端点可以互相收发
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    registry: Arc<Registry>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 创建一个分配了新地址的端点
+    pub fn bind(&self) -> MemoryTransport {
+        let addr = MemoryAddr(self.registry.next_addr.fetch_add(1, Ordering::Relaxed) + 1);
+        let (tx, rx) = mpsc::channel();
+        self.registry.endpoints.lock().unwrap().insert(addr, tx);
+        MemoryTransport {
+            endpoint: Arc::new(Endpoint {
+                addr,
+                registry: self.registry.clone(),
+                rx: Mutex::new(rx),
+                read_timeout: Mutex::new(None),
+                nonblocking: Mutex::new(false),
+            }),
+        }
+    }
+}
+
+// 创建一对互相连通的端点
+pub fn memory_pair() -> (MemoryTransport, MemoryTransport) {
+    let network = MemoryNetwork::new();
+    (network.bind(), network.bind())
+}
+
+struct Endpoint {
+    addr: MemoryAddr,
+    registry: Arc<Registry>,
+    rx: Mutex<Receiver<Packet>>,
+    // 和 socket 一样，克隆出的句柄共享读超时和阻塞模式
+    read_timeout: Mutex<Option<Duration>>,
+    nonblocking: Mutex<bool>,
+}
+
+impl Drop for Endpoint {
+    // 最后一个句柄释放时注销地址
+    fn drop(&mut self) {
+        self.registry.endpoints.lock().unwrap().remove(&self.addr);
+    }
+}
+
+/// 内存端点
+#[derive(Clone)]
+pub struct MemoryTransport {
+    endpoint: Arc<Endpoint>,
+}
+
+impl MemoryTransport {
+    pub fn addr(&self) -> MemoryAddr {
+        self.endpoint.addr
+    }
+
+    // 取出一个数据报，遵循读超时和阻塞模式
+    fn recv_packet(&self) -> io::Result<Packet> {
+        let rx = self.endpoint.rx.lock().unwrap();
+        let timed_out = || io::Error::new(io::ErrorKind::WouldBlock, "Receive timed out");
+        let disconnected = || io::Error::new(io::ErrorKind::NotConnected, "Memory network closed");
+
+        if *self.endpoint.nonblocking.lock().unwrap() {
+            return rx.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => timed_out(),
+                TryRecvError::Disconnected => disconnected(),
+            });
+        }
+        let timeout = *self.endpoint.read_timeout.lock().unwrap();
+        match timeout {
+            Some(timeout) => rx.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => timed_out(),
+                RecvTimeoutError::Disconnected => disconnected(),
+            }),
+            None => rx.recv().map_err(|_| disconnected()),
+        }
+    }
+}
+
+impl fmt::Debug for MemoryTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTransport").field("addr", &self.endpoint.addr).finish()
+    }
+}
+
+impl DatagramTransport for MemoryTransport {
+    type Addr = MemoryAddr;
+
+    fn send_to(&self, buf: &[u8], addr: &MemoryAddr) -> io::Result<usize> {
+        let endpoints = self.endpoint.registry.endpoints.lock().unwrap();
+        if let Some(tx) = endpoints.get(addr) {
+            let _ = tx.send((self.endpoint.addr, buf.to_vec()));
+        }
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, MemoryAddr)> {
+        let (src_addr, data) = self.recv_packet()?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok((len, src_addr))
+    }
+
+    fn local_addr(&self) -> io::Result<MemoryAddr> {
+        Ok(self.endpoint.addr)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        if timeout.is_some_and(|t| t.is_zero()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Zero duration is not a valid timeout"));
+        }
+        *self.endpoint.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        *self.endpoint.nonblocking.lock().unwrap() = nonblocking;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+
+    // 内存数据报的实际长度总是已知的
+    fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, MemoryAddr)> {
+        let (src_addr, data) = self.recv_packet()?;
+        if data.len() > buf.len() {
+            return Err(TruncatedDatagram { len: Some(data.len()), max: buf.len() }.into());
+        }
+        buf[..data.len()].copy_from_slice(&data);
+        Ok((data.len(), src_addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{UdpClient, UdpServer, is_truncated};
+    use std::thread;
+
+    #[test]
+    fn test_memory_echo_server() {
+        let network = MemoryNetwork::new();
+        let server = UdpServer::from_socket(network.bind());
+        let handle = server
+            .start_pool(2, |_src_addr, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+        let server_addr = handle.local_addr();
+
+        // 多个客户端并行请求，各自只收到自己的回包
+        let clients: Vec<_> = (0..4)
+            .map(|i| {
+                let client = UdpClient::from_socket(network.bind());
+                thread::spawn(move || {
+                    let msg = format!("client {}", i);
+                    let response = client.send_and_receive(server_addr, msg.as_bytes(), Duration::from_secs(1)).unwrap();
+                    assert_eq!(response, msg.as_bytes());
+                })
+            })
+            .collect();
+        for client in clients {
+            client.join().unwrap();
+        }
+
+        let stats = handle.stats();
+        handle.shutdown().unwrap();
+        assert_eq!(stats.processed(), 4);
+    }
+
+    #[test]
+    fn test_memory_timeout_and_truncation() {
+        let (a, b) = memory_pair();
+        let client = UdpClient::from_socket(a).with_max_datagram_size(4);
+
+        let err = client.send_and_receive(b.addr(), b"ping", Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        b.send_to(b"too long", &client.local_addr().unwrap()).unwrap();
+        let err = client.send_and_receive(b.addr(), b"ping", Duration::from_millis(50)).unwrap_err();
+        assert!(is_truncated(&err));
+    }
+
+    #[test]
+    fn test_dropped_endpoint_unregistered() {
+        let network = MemoryNetwork::new();
+        let sender = network.bind();
+        let receiver = network.bind();
+        let addr = receiver.addr();
+        drop(receiver);
+        // 和 UDP 一样，发往不存在的地址不报错
+        assert_eq!(sender.send_to(b"lost", &addr).unwrap(), 4);
+        assert!(network.registry.endpoints.lock().unwrap().get(&addr).is_none());
+    }
+}
@@ -0,0 +1,528 @@
+// metrics.rs
+// 指标注册表：计数器和直方图，可以取快照，也可以输出 Prometheus 文本格式。
+// 客户端和服务器通过 with_metrics 共享同一个注册表；开启 metrics-http 特性后可以在本地 HTTP 端口上抓取。
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// RTT 直方图默认的桶上界（秒），覆盖本地回环到跨网段的往返时间
+pub const DEFAULT_RTT_BUCKETS: [f64; 14] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// 单调递增计数器
+#[derive(Debug, Default)]
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// 固定桶直方图
+#[derive(Debug)]
+pub struct Histogram {
+    // 升序排列的桶上界，不含 +Inf
+    bounds: Vec<f64>,
+    // 每个桶自己的计数（非累计），最后一个是 +Inf 桶
+    counts: Vec<AtomicU64>,
+    // f64 的位模式，用 CAS 累加
+    sum: AtomicU64,
+}
+
+/// 直方图在某一时刻的快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// (桶上界, 小于等于上界的累计个数)，最后一项的上界是 +Inf
+    pub buckets: Vec<(f64, u64)>,
+    /// 观测值个数
+    pub count: u64,
+    /// 观测值之和
+    pub sum: f64,
+}
+
+impl Histogram {
+    fn new(buckets: &[f64]) -> Self {
+        let mut bounds: Vec<f64> = buckets.iter().copied().filter(|b| b.is_finite()).collect();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        Histogram {
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            bounds,
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let index = self.bounds.partition_point(|&bound| bound < value);
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+        let _ = self.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + value).to_bits())
+        });
+    }
+
+    // 按秒记录一段时间
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(&self.counts)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (bound, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            count: cumulative,
+            sum: f64::from_bits(self.sum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Family {
+    // 标签串（如 kind="ChecksumMismatch"，无标签时为空）到计数器
+    Counter(BTreeMap<String, Arc<Counter>>),
+    Histogram(Arc<Histogram>),
+}
+
+#[derive(Debug)]
+struct Metric {
+    help: String,
+    family: Family,
+}
+
+/// 指标注册表，同名指标只创建一次，重复获取返回同一个实例
+#[derive(Debug, Default)]
+pub struct Registry {
+    metrics: Mutex<BTreeMap<String, Metric>>,
+}
+
+/// 注册表快照，键为 Prometheus 序列名，如 `udp_decode_failures_total{kind="BadMagic"}`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub counters: BTreeMap<String, u64>,
+    pub histograms: BTreeMap<String, HistogramSnapshot>,
+}
+
+impl MetricsSnapshot {
+    // 计数器的值，不存在时为 0
+    pub fn counter(&self, series: &str) -> u64 {
+        self.counters.get(series).copied().unwrap_or(0)
+    }
+
+    pub fn histogram(&self, name: &str) -> Option<&HistogramSnapshot> {
+        self.histograms.get(name)
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 获取或创建无标签计数器
+    pub fn counter(&self, name: &str, help: &str) -> Arc<Counter> {
+        self.counter_with_labels(name, help, &[])
+    }
+
+    // 获取或创建带标签的计数器，同名不同标签是同一指标下的不同序列
+    pub fn counter_with_labels(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Counter> {
+        let mut metrics = self.metrics.lock().unwrap();
+        let metric = metrics.entry(name.to_string()).or_insert_with(|| Metric {
+            help: help.to_string(),
+            family: Family::Counter(BTreeMap::new()),
+        });
+        let Family::Counter(series) = &mut metric.family else {
+            panic!("metric {} is already registered as a histogram", name);
+        };
+        series.entry(format_labels(labels)).or_default().clone()
+    }
+
+    // 获取或创建直方图，已存在时忽略 buckets
+    pub fn histogram(&self, name: &str, help: &str, buckets: &[f64]) -> Arc<Histogram> {
+        let mut metrics = self.metrics.lock().unwrap();
+        let metric = metrics.entry(name.to_string()).or_insert_with(|| Metric {
+            help: help.to_string(),
+            family: Family::Histogram(Arc::new(Histogram::new(buckets))),
+        });
+        match &metric.family {
+            Family::Histogram(histogram) => histogram.clone(),
+            Family::Counter(_) => panic!("metric {} is already registered as a counter", name),
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = MetricsSnapshot::default();
+        for (name, metric) in self.metrics.lock().unwrap().iter() {
+            match &metric.family {
+                Family::Counter(series) => {
+                    for (labels, counter) in series {
+                        snapshot.counters.insert(series_name(name, labels), counter.get());
+                    }
+                }
+                Family::Histogram(histogram) => {
+                    snapshot.histograms.insert(name.clone(), histogram.snapshot());
+                }
+            }
+        }
+        snapshot
+    }
+
+    // Prometheus 文本格式（0.0.4）
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        for (name, metric) in self.metrics.lock().unwrap().iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, escape_help(&metric.help));
+            match &metric.family {
+                Family::Counter(series) => {
+                    let _ = writeln!(out, "# TYPE {} counter", name);
+                    for (labels, counter) in series {
+                        let _ = writeln!(out, "{} {}", series_name(name, labels), counter.get());
+                    }
+                }
+                Family::Histogram(histogram) => {
+                    let _ = writeln!(out, "# TYPE {} histogram", name);
+                    let snapshot = histogram.snapshot();
+                    for (bound, count) in &snapshot.buckets {
+                        let le = if bound.is_infinite() { "+Inf".to_string() } else { bound.to_string() };
+                        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+                    }
+                    let _ = writeln!(out, "{}_sum {}", name, snapshot.sum);
+                    let _ = writeln!(out, "{}_count {}", name, snapshot.count);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    let mut labels = labels.to_vec();
+    labels.sort();
+    labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn series_name(name: &str, labels: &str) -> String {
+    if labels.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}{{{}}}", name, labels)
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+// 客户端和服务器记录的传输层指标，同一个注册表上多次创建得到的是同一组计数器
+#[derive(Debug, Clone)]
+pub(crate) struct TransportMetrics {
+    registry: Arc<Registry>,
+    datagrams_sent: Arc<Counter>,
+    bytes_sent: Arc<Counter>,
+    datagrams_received: Arc<Counter>,
+    bytes_received: Arc<Counter>,
+    receive_errors: Arc<Counter>,
+    queue_drops: Arc<Counter>,
+    truncated: Arc<Counter>,
+    rtt: Arc<Histogram>,
+}
+
+impl TransportMetrics {
+    pub(crate) fn new(registry: Arc<Registry>) -> Self {
+        TransportMetrics {
+            datagrams_sent: registry.counter("udp_datagrams_sent_total", "Datagrams sent"),
+            bytes_sent: registry.counter("udp_bytes_sent_total", "Bytes sent"),
+            datagrams_received: registry.counter("udp_datagrams_received_total", "Datagrams received"),
+            bytes_received: registry.counter("udp_bytes_received_total", "Bytes received"),
+            receive_errors: registry.counter("udp_receive_errors_total", "Receive errors other than timeouts"),
+            queue_drops: registry.counter("udp_queue_drops_total", "Datagrams dropped because the receive queue was full"),
+            truncated: registry.counter("udp_truncated_total", "Datagrams dropped for exceeding the maximum size"),
+            rtt: registry.histogram("udp_rtt_seconds", "Request round-trip time in seconds", &DEFAULT_RTT_BUCKETS),
+            registry,
+        }
+    }
+
+    pub(crate) fn sent(&self, len: usize) {
+        self.datagrams_sent.inc();
+        self.bytes_sent.add(len as u64);
+    }
+
+    pub(crate) fn received(&self, len: usize) {
+        self.datagrams_received.inc();
+        self.bytes_received.add(len as u64);
+    }
+
+    pub(crate) fn receive_error(&self) {
+        self.receive_errors.inc();
+    }
+
+    pub(crate) fn queue_drop(&self) {
+        self.queue_drops.inc();
+    }
+
+    pub(crate) fn truncated(&self) {
+        self.truncated.inc();
+    }
+
+    pub(crate) fn rtt(&self, rtt: Duration) {
+        self.rtt.observe_duration(rtt);
+    }
+
+    // 按解析错误种类计数
+    pub(crate) fn decode_failure(&self, kind: &str) {
+        self.registry
+            .counter_with_labels("udp_decode_failures_total", "Frames that failed to decode", &[("kind", kind)])
+            .inc();
+    }
+}
+
+#[cfg(feature = "metrics-http")]
+pub use self::http::{MetricsEndpoint, serve};
+
+#[cfg(feature = "metrics-http")]
+mod http {
+    use super::Registry;
+    use std::io;
+    use std::net::{SocketAddr, ToSocketAddrs};
+    use std::sync::Arc;
+    use std::thread;
+    use tiny_http::{Header, Response, Server};
+
+    /// 指标 HTTP 端点的运行句柄
+    pub struct MetricsEndpoint {
+        server: Arc<Server>,
+        local_addr: SocketAddr,
+        thread: thread::JoinHandle<()>,
+    }
+
+    impl MetricsEndpoint {
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+
+        // 停止接受新请求并等待服务线程退出
+        pub fn shutdown(self) -> thread::Result<()> {
+            self.server.unblock();
+            self.thread.join()
+        }
+    }
+
+    // 在 addr 上启动 HTTP 服务，GET /metrics 返回 Prometheus 文本格式，其他路径返回 404
+    pub fn serve(registry: Arc<Registry>, addr: impl ToSocketAddrs) -> io::Result<MetricsEndpoint> {
+        let server = Arc::new(Server::http(addr).map_err(io::Error::other)?);
+        let local_addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| io::Error::other("metrics endpoint is not bound to an IP address"))?;
+        let thread = {
+            let server = server.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let path = request.url().split('?').next().unwrap_or_default();
+                    let result = if path == "/metrics" {
+                        let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+                        request.respond(Response::from_string(registry.render_prometheus()).with_header(content_type))
+                    } else {
+                        request.respond(Response::empty(404))
+                    };
+                    // 客户端提前断开不影响服务
+                    let _ = result;
+                }
+            })
+        };
+        Ok(MetricsEndpoint {
+            server,
+            local_addr,
+            thread,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DatagramTransport, FrameServer, MemoryNetwork, OverflowPolicy, Route, UdpClient, UdpServer};
+    use std::thread;
+    use udp_protocol::RequestBodyType;
+
+    #[test]
+    fn test_counter_and_histogram_snapshot() {
+        let registry = Registry::new();
+        registry.counter("requests_total", "Requests").add(3);
+        registry.counter("requests_total", "Requests").inc();
+        registry.counter_with_labels("errors_total", "Errors", &[("kind", "a")]).inc();
+        let histogram = registry.histogram("latency_seconds", "Latency", &[0.1, 1.0]);
+        histogram.observe(0.05);
+        histogram.observe(0.5);
+        histogram.observe(5.0);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.counter("requests_total"), 4);
+        assert_eq!(snapshot.counter("errors_total{kind=\"a\"}"), 1);
+        assert_eq!(snapshot.counter("missing_total"), 0);
+        let latency = snapshot.histogram("latency_seconds").unwrap();
+        assert_eq!(latency.buckets, vec![(0.1, 1), (1.0, 2), (f64::INFINITY, 3)]);
+        assert_eq!(latency.count, 3);
+        assert!((latency.sum - 5.55).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let registry = Registry::new();
+        registry.counter_with_labels("errors_total", "Errors", &[("kind", "say \"hi\"")]).add(2);
+        registry.histogram("latency_seconds", "Latency", &[0.5]).observe(0.25);
+
+        let text = registry.render_prometheus();
+        let expected = "# HELP errors_total Errors\n\
+                        # TYPE errors_total counter\n\
+                        errors_total{kind=\"say \\\"hi\\\"\"} 2\n\
+                        # HELP latency_seconds Latency\n\
+                        # TYPE latency_seconds histogram\n\
+                        latency_seconds_bucket{le=\"0.5\"} 1\n\
+                        latency_seconds_bucket{le=\"+Inf\"} 1\n\
+                        latency_seconds_sum 0.25\n\
+                        latency_seconds_count 1\n";
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn test_client_and_server_metrics() {
+        let registry = Arc::new(Registry::new());
+        let network = MemoryNetwork::new();
+        let server = UdpServer::from_socket(network.bind()).with_metrics(registry.clone());
+        let handle = server
+            .start_pool(1, |_, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+        let client = UdpClient::from_socket(network.bind()).with_metrics(registry.clone());
+        for _ in 0..3 {
+            client.send_and_receive(handle.local_addr(), b"ping", Duration::from_secs(1)).unwrap();
+        }
+        handle.shutdown().unwrap();
+
+        let snapshot = registry.snapshot();
+        // 客户端和服务器各发 3 个、各收 3 个
+        assert_eq!(snapshot.counter("udp_datagrams_sent_total"), 6);
+        assert_eq!(snapshot.counter("udp_bytes_sent_total"), 24);
+        assert_eq!(snapshot.counter("udp_datagrams_received_total"), 6);
+        assert_eq!(snapshot.counter("udp_bytes_received_total"), 24);
+        assert_eq!(snapshot.histogram("udp_rtt_seconds").unwrap().count, 3);
+    }
+
+    #[test]
+    fn test_batch_and_segmented_sends_counted() {
+        let registry = Arc::new(Registry::new());
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let client = UdpClient::new().unwrap().with_metrics(registry.clone());
+        assert_eq!(client.send_batch(addr, &[b"one".as_slice(), b"three"]).unwrap(), 2);
+        // 最后一个分段较短
+        assert_eq!(client.send_segmented(addr, &[0u8; 25], 10).unwrap(), 3);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.counter("udp_datagrams_sent_total"), 5);
+        assert_eq!(snapshot.counter("udp_bytes_sent_total"), 33);
+    }
+
+    #[test]
+    fn test_queue_drops_and_decode_failures() {
+        let registry = Arc::new(Registry::new());
+        let network = MemoryNetwork::new();
+        let sender = network.bind();
+
+        // 队列容量 1，处理函数阻塞期间后到的数据报被丢弃
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let rx = std::sync::Mutex::new(rx);
+        let server = UdpServer::from_socket(network.bind())
+            .with_queue(1, OverflowPolicy::DropNewest)
+            .with_metrics(registry.clone());
+        let handle = server
+            .start_async(move |_, _| {
+                let _ = rx.lock().unwrap().recv();
+            })
+            .unwrap();
+        for _ in 0..5 {
+            sender.send_to(b"x", &handle.local_addr()).unwrap();
+        }
+        while handle.stats().received() < 5 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(tx);
+        handle.shutdown().unwrap();
+        assert!(registry.snapshot().counter("udp_queue_drops_total") >= 3);
+
+        let frames = FrameServer::new(UdpServer::from_socket(network.bind()).with_metrics(registry.clone()))
+            .route(Route::new(RequestBodyType::TlvProtocol), |_| None)
+            .start()
+            .unwrap();
+        sender.send_to(&[0u8; 4], &frames.local_addr()).unwrap();
+        while frames.frame_stats().decode_error_total() < 1 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        frames.shutdown().unwrap();
+        let snapshot = registry.snapshot();
+        let failures: u64 = snapshot
+            .counters
+            .iter()
+            .filter(|(series, _)| series.starts_with("udp_decode_failures_total{kind="))
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(failures, 1);
+    }
+
+    #[cfg(feature = "metrics-http")]
+    #[test]
+    fn test_http_endpoint() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+
+        let registry = Arc::new(Registry::new());
+        registry.counter("udp_datagrams_sent_total", "Datagrams sent").add(7);
+        let endpoint = serve(registry, "127.0.0.1:0").unwrap();
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(endpoint.local_addr()).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("udp_datagrams_sent_total 7\n"));
+        assert!(get("/other").starts_with("HTTP/1.1 404"));
+        endpoint.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+// multicast.rs
+// 组播和广播：把 Layer2 的 8 字节 group 映射为组播地址，一次发送由组内所有设备接收，
+// 再在截止时间前收集多个设备的回包。
+use crate::{UdpClient, UdpServer};
+use socket2::{Domain, InterfaceIndexOrAddress, Protocol, SockAddr, SockRef, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// IPv4 组映射使用的 /16 前缀（239.255.0.0/16，组织内部范围）
+pub const GROUP_BASE_V4: Ipv4Addr = Ipv4Addr::new(239, 255, 0, 0);
+/// IPv6 组映射使用的前缀（ff15::/64，站点范围的临时组播地址），低 64 位为 group
+pub const GROUP_PREFIX_V6: Ipv6Addr = Ipv6Addr::new(0xff15, 0, 0, 0, 0, 0, 0, 0);
+/// 受限广播地址
+pub const BROADCAST_V4: Ipv4Addr = Ipv4Addr::BROADCAST;
+
+/// 收发组播使用的网卡
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MulticastInterface {
+    /// 由系统按路由选择
+    #[default]
+    Default,
+    /// 按 IPv4 地址指定网卡
+    V4(Ipv4Addr),
+    /// 按网卡序号指定，IPv4 和 IPv6 都可以使用
+    Index(u32),
+}
+
+// group 映射到 IPv4 组播地址：8 字节按 u16 小端异或折叠为低 16 位。
+// 不同的 group 可能映射到同一个地址，设备仍需按 Layer2 的 group 字段过滤
+pub fn group_multicast_v4(group: &[u8; 8]) -> Ipv4Addr {
+    let folded = group
+        .chunks_exact(2)
+        .fold(0u16, |acc, pair| acc ^ u16::from_le_bytes([pair[0], pair[1]]));
+    let [hi, lo] = folded.to_be_bytes();
+    let base = GROUP_BASE_V4.octets();
+    Ipv4Addr::new(base[0], base[1], hi, lo)
+}
+
+// group 映射到 IPv6 组播地址，group 原样放在低 64 位，不会冲突
+pub fn group_multicast_v6(group: &[u8; 8]) -> Ipv6Addr {
+    let mut octets = GROUP_PREFIX_V6.octets();
+    octets[8..].copy_from_slice(group);
+    Ipv6Addr::from(octets)
+}
+
+// 按 socket 的地址族选择 group 对应的组播地址
+pub fn group_multicast_addr(socket: &UdpSocket, group: &[u8; 8]) -> io::Result<IpAddr> {
+    Ok(match socket.local_addr()? {
+        SocketAddr::V4(_) => IpAddr::V4(group_multicast_v4(group)),
+        SocketAddr::V6(_) => IpAddr::V6(group_multicast_v6(group)),
+    })
+}
+
+// 开启 SO_REUSEADDR 绑定 0.0.0.0:port，同一主机上的多个接收者可以共用组播或广播端口
+pub fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SockAddr::from(SocketAddr::from(([0, 0, 0, 0], port))))?;
+    Ok(socket.into())
+}
+
+fn invalid_interface() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "IPv6 multicast requires an interface index")
+}
+
+// 加入组播组
+pub fn join_multicast(socket: &UdpSocket, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+    match (group, interface) {
+        (IpAddr::V4(group), MulticastInterface::Default) => socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED),
+        (IpAddr::V4(group), MulticastInterface::V4(addr)) => socket.join_multicast_v4(&group, &addr),
+        (IpAddr::V4(group), MulticastInterface::Index(index)) => {
+            SockRef::from(socket).join_multicast_v4_n(&group, &InterfaceIndexOrAddress::Index(index))
+        }
+        (IpAddr::V6(group), MulticastInterface::Default) => socket.join_multicast_v6(&group, 0),
+        (IpAddr::V6(group), MulticastInterface::Index(index)) => socket.join_multicast_v6(&group, index),
+        (IpAddr::V6(_), MulticastInterface::V4(_)) => Err(invalid_interface()),
+    }
+}
+
+// 离开组播组，参数需与加入时一致
+pub fn leave_multicast(socket: &UdpSocket, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+    match (group, interface) {
+        (IpAddr::V4(group), MulticastInterface::Default) => socket.leave_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED),
+        (IpAddr::V4(group), MulticastInterface::V4(addr)) => socket.leave_multicast_v4(&group, &addr),
+        (IpAddr::V4(group), MulticastInterface::Index(index)) => {
+            SockRef::from(socket).leave_multicast_v4_n(&group, &InterfaceIndexOrAddress::Index(index))
+        }
+        (IpAddr::V6(group), MulticastInterface::Default) => socket.leave_multicast_v6(&group, 0),
+        (IpAddr::V6(group), MulticastInterface::Index(index)) => socket.leave_multicast_v6(&group, index),
+        (IpAddr::V6(_), MulticastInterface::V4(_)) => Err(invalid_interface()),
+    }
+}
+
+impl UdpClient {
+    // 设置发送组播使用的网卡
+    pub fn set_multicast_interface(&self, interface: MulticastInterface) -> io::Result<()> {
+        let sock = SockRef::from(&self.socket);
+        match (self.socket.local_addr()?, interface) {
+            (SocketAddr::V4(_), MulticastInterface::Default) => sock.set_multicast_if_v4(&Ipv4Addr::UNSPECIFIED),
+            (SocketAddr::V4(_), MulticastInterface::V4(addr)) => sock.set_multicast_if_v4(&addr),
+            (SocketAddr::V4(_), MulticastInterface::Index(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "IPv4 multicast send interface must be given by address",
+            )),
+            (SocketAddr::V6(_), MulticastInterface::Default) => sock.set_multicast_if_v6(0),
+            (SocketAddr::V6(_), MulticastInterface::Index(index)) => sock.set_multicast_if_v6(index),
+            (SocketAddr::V6(_), MulticastInterface::V4(_)) => Err(invalid_interface()),
+        }
+    }
+
+    // 设置组播的 TTL（IPv6 为跳数），系统默认为1，只在本网段内传播
+    pub fn set_multicast_ttl(&self, ttl: u32) -> io::Result<()> {
+        let sock = SockRef::from(&self.socket);
+        match self.socket.local_addr()? {
+            SocketAddr::V4(_) => sock.set_multicast_ttl_v4(ttl),
+            SocketAddr::V6(_) => sock.set_multicast_hops_v6(ttl),
+        }
+    }
+
+    pub fn join_multicast(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        join_multicast(&self.socket, group, interface)
+    }
+
+    pub fn leave_multicast(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        leave_multicast(&self.socket, group, interface)
+    }
+
+    // 发送广播，addr 可以是受限广播地址或子网广播地址
+    pub fn send_broadcast(&self, addr: SocketAddr, msg: &[u8]) -> io::Result<()> {
+        self.socket.set_broadcast(true)?;
+        self.send_only(addr, msg)
+    }
+
+    // 发送给 Layer2 group 对应的组播地址，返回实际使用的目的地址
+    pub fn send_to_group(&self, group: &[u8; 8], port: u16, msg: &[u8]) -> io::Result<SocketAddr> {
+        let addr = SocketAddr::new(group_multicast_addr(&self.socket, group)?, port);
+        self.send_only(addr, msg)?;
+        Ok(addr)
+    }
+
+    // 发送消息后在 timeout 内收集所有回包，收到 max_replies 个后提前返回。
+    // 超过最大长度的回包被丢弃，不影响其他设备的回包
+    pub fn send_and_collect(
+        &self,
+        addr: SocketAddr,
+        msg: &[u8],
+        timeout: Duration,
+        max_replies: Option<usize>,
+    ) -> io::Result<Vec<(SocketAddr, Vec<u8>)>> {
+        self.send_only(addr, msg)?;
+        self.collect_replies(timeout, max_replies)
+    }
+
+    // 在 timeout 内收集回包，用于广播或组播发送之后
+    pub fn collect_replies(
+        &self,
+        timeout: Duration,
+        max_replies: Option<usize>,
+    ) -> io::Result<Vec<(SocketAddr, Vec<u8>)>> {
+        let deadline = Instant::now() + timeout;
+        let mut replies = Vec::new();
+        let mut buf = vec![0; self.max_datagram_size()];
+
+        while max_replies.is_none_or(|max| replies.len() < max) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+            match crate::batch::recv_from_checked(&self.socket, &mut buf) {
+                Ok((num_bytes, src_addr)) => replies.push((src_addr, buf[..num_bytes].to_vec())),
+                Err(e) if crate::retry::is_timeout(&e) => break,
+                Err(e) if crate::is_truncated(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(replies)
+    }
+}
+
+impl UdpServer {
+    // 在启动前加入组播组，服务器停止后 socket 关闭时自动离开
+    pub fn join_multicast(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        join_multicast(&self.socket, group, interface)
+    }
+
+    // 加入 Layer2 group 对应的组播组
+    pub fn join_group(&self, group: &[u8; 8], interface: MulticastInterface) -> io::Result<()> {
+        join_multicast(&self.socket, group_multicast_addr(&self.socket, group)?, interface)
+    }
+
+    pub fn leave_multicast(&self, group: IpAddr, interface: MulticastInterface) -> io::Result<()> {
+        leave_multicast(&self.socket, group, interface)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_mapping() {
+        let group = [0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(group_multicast_v4(&group), Ipv4Addr::new(239, 255, 0, 3));
+        assert!(group_multicast_v4(&[0xFF; 8]).is_multicast());
+        assert_eq!(
+            group_multicast_v6(&[1, 2, 3, 4, 5, 6, 7, 8]),
+            "ff15::102:304:506:708".parse::<Ipv6Addr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_collect_replies_from_group() {
+        let group = [0x10, 0x20, 0, 0, 0, 0, 0, 0];
+        let loopback = MulticastInterface::V4(Ipv4Addr::LOCALHOST);
+
+        // 三个设备在同一端口加入同一个组
+        let first = bind_shared(0).unwrap();
+        let port = first.local_addr().unwrap().port();
+        let mut sockets = vec![first];
+        sockets.extend((1..3).map(|_| bind_shared(port).unwrap()));
+        let mut handles = Vec::new();
+        for (index, socket) in sockets.into_iter().enumerate() {
+            let index = index as u8;
+            let server = UdpServer::from_socket(socket);
+            server.join_group(&group, loopback).unwrap();
+            handles.push(
+                server
+                    .start_pool(1, move |_src_addr, data, reply| {
+                        reply.send(&[data, &[index]].concat()).unwrap();
+                    })
+                    .unwrap(),
+            );
+        }
+
+        let client = UdpClient::new().unwrap();
+        client.set_multicast_interface(loopback).unwrap();
+        let addr = client.send_to_group(&group, port, b"write").unwrap();
+        assert_eq!(addr.ip(), IpAddr::V4(group_multicast_v4(&group)));
+
+        let mut replies = client.collect_replies(Duration::from_millis(500), Some(3)).unwrap();
+        replies.sort_by_key(|(_, data)| data[5]);
+        let data: Vec<&[u8]> = replies.iter().map(|(_, data)| data.as_slice()).collect();
+        assert_eq!(data, [b"write\x00", b"write\x01", b"write\x02"]);
+
+        for handle in handles {
+            handle.shutdown().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_broadcast_collects_until_deadline() {
+        let server = UdpServer::bind(0).unwrap();
+        let handle = server
+            .start_pool(1, |_src_addr, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+
+        // 127.255.255.255 是回环网段的广播地址
+        let addr = SocketAddr::from(([127, 255, 255, 255], handle.local_addr().port()));
+        let client = UdpClient::new().unwrap();
+        client.send_broadcast(addr, b"hello").unwrap();
+
+        let start = Instant::now();
+        let replies = client.collect_replies(Duration::from_millis(300), None).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].1, b"hello");
+
+        handle.shutdown().unwrap();
+    }
+}
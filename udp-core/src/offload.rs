@@ -0,0 +1,253 @@
+// offload.rs
+// UDP 分段卸载：发送端 GSO（UDP_SEGMENT）把一个大缓冲区交给内核按固定大小切分成多个数据报，
+// 接收端 GRO（UDP_GRO）把同一条流的数据报合并后一次交付，由 batch 模块按分段大小拆回。
+// 运行时检测内核支持情况，不支持时退化为逐个数据报收发。
+use crate::UdpClient;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+/// 单次 GSO 发送最多的分段数（内核 UDP_MAX_SEGMENTS）
+pub const MAX_GSO_SEGMENTS: usize = 64;
+/// 开启 GRO 时的接收缓冲区大小，能容纳一次合并的最大长度
+pub const GRO_BUFFER_SIZE: usize = 65535;
+// 单次 GSO 发送的最大字节数（IPv4 UDP 负载上限）
+const MAX_GSO_BYTES: usize = 65507;
+
+// 当前内核和 socket 是否支持 GSO 发送
+pub fn gso_supported(socket: &UdpSocket) -> bool {
+    sys::gso_supported(socket)
+}
+
+// 在 socket 上开启 GRO，内核不支持时返回 Ok(false)
+pub fn enable_gro(socket: &UdpSocket) -> io::Result<bool> {
+    sys::enable_gro(socket)
+}
+
+// 把 data 按 segment_size 切分成多个数据报发送给 addr（最后一个可以更短），返回数据报个数
+pub fn send_segmented(socket: &UdpSocket, addr: SocketAddr, data: &[u8], segment_size: usize) -> io::Result<usize> {
+    if segment_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Segment size must be positive"));
+    }
+    let per_send = (MAX_GSO_BYTES / segment_size).clamp(1, MAX_GSO_SEGMENTS);
+    let use_gso = gso_supported(socket);
+
+    for chunk in data.chunks(segment_size * per_send) {
+        if use_gso && chunk.len() > segment_size && sys::send_gso(socket, addr, chunk, segment_size)? {
+            continue;
+        }
+        for segment in chunk.chunks(segment_size) {
+            socket.send_to(segment, addr)?;
+        }
+    }
+    Ok(data.len().div_ceil(segment_size))
+}
+
+impl UdpClient {
+    // 按固定分段大小批量发送，优先使用 GSO。设置了 pacer 时每个分段都先取得令牌
+    pub fn send_segmented(&self, addr: SocketAddr, data: &[u8], segment_size: usize) -> io::Result<usize> {
+        if segment_size > 0 {
+            for segment in data.chunks(segment_size) {
+                self.pace(segment.len());
+            }
+        }
+        let sent = send_segmented(&self.socket, addr, data, segment_size)?;
+        for segment in data.chunks(segment_size) {
+            self.record_sent(segment.len());
+        }
+        Ok(sent)
+    }
+
+    pub fn gso_supported(&self) -> bool {
+        gso_supported(&self.socket)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use socket2::SockAddr;
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::{mem, ptr};
+
+    // 发送时出现设备不支持等错误后，本进程不再尝试 GSO
+    static GSO_DISABLED: AtomicBool = AtomicBool::new(false);
+
+    fn is_unsupported(e: &io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(libc::EIO) | Some(libc::ENOPROTOOPT) | Some(libc::EOPNOTSUPP)
+        )
+    }
+
+    pub(super) fn gso_supported(socket: &UdpSocket) -> bool {
+        if GSO_DISABLED.load(Ordering::Relaxed) {
+            return false;
+        }
+        let mut value: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: value 和 len 在调用期间有效
+        let ret = unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_UDP,
+                libc::UDP_SEGMENT,
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        ret == 0
+    }
+
+    pub(super) fn enable_gro(socket: &UdpSocket) -> io::Result<bool> {
+        let value: libc::c_int = 1;
+        // SAFETY: value 在调用期间有效
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_UDP,
+                libc::UDP_GRO,
+                &value as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret == 0 {
+            return Ok(true);
+        }
+        let e = io::Error::last_os_error();
+        if is_unsupported(&e) || e.raw_os_error() == Some(libc::EINVAL) {
+            Ok(false)
+        } else {
+            Err(e)
+        }
+    }
+
+    // 使用 UDP_SEGMENT 控制消息发送，内核不接受时返回 Ok(false) 由调用方退化为逐个发送
+    pub(super) fn send_gso(socket: &UdpSocket, addr: SocketAddr, data: &[u8], segment_size: usize) -> io::Result<bool> {
+        let addr = SockAddr::from(addr);
+        let mut iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        let mut control = [0u64; 4];
+
+        // SAFETY: msghdr 全零是合法的初始值，其中的指针都指向本函数内存活的数据；
+        // control 足够容纳一个 u16 的控制消息
+        let ret = unsafe {
+            let mut hdr: libc::msghdr = mem::zeroed();
+            hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+            hdr.msg_namelen = addr.len();
+            hdr.msg_iov = &mut iov;
+            hdr.msg_iovlen = 1;
+            hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_controllen = libc::CMSG_SPACE(mem::size_of::<u16>() as u32) as usize;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as usize;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment_size as u16);
+
+            libc::sendmsg(socket.as_raw_fd(), &hdr, 0)
+        };
+        if ret >= 0 {
+            return Ok(true);
+        }
+
+        let e = io::Error::last_os_error();
+        if is_unsupported(&e) {
+            GSO_DISABLED.store(true, Ordering::Relaxed);
+            return Ok(false);
+        }
+        // 分段超过路径 MTU 等情况只对这一次发送退化
+        if e.raw_os_error() == Some(libc::EINVAL) {
+            return Ok(false);
+        }
+        Err(e)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+
+    pub(super) fn gso_supported(_socket: &UdpSocket) -> bool {
+        false
+    }
+
+    pub(super) fn enable_gro(_socket: &UdpSocket) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(super) fn send_gso(_socket: &UdpSocket, _addr: SocketAddr, _data: &[u8], _segment_size: usize) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UdpServer;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_segmented_send_split_for_handler() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let server = UdpServer::bind(0).unwrap().with_gro();
+        let handle = server
+            .start_async(move |_src_addr, data| received_clone.lock().unwrap().push(data.to_vec()))
+            .unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+
+        // 9 个 1000 字节的分段加一个 500 字节的尾段
+        let data: Vec<u8> = (0..9500u32).map(|i| (i / 1000) as u8).collect();
+        let client = UdpClient::new().unwrap();
+        assert_eq!(client.send_segmented(addr, &data, 1000).unwrap(), 10);
+
+        thread::sleep(Duration::from_millis(200));
+        handle.shutdown().unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 10);
+        for (i, segment) in received.iter().enumerate() {
+            let expected = if i == 9 { 500 } else { 1000 };
+            assert_eq!(segment.len(), expected);
+            assert!(segment.iter().all(|&b| b == i as u8));
+        }
+    }
+
+    #[test]
+    fn test_max_datagram_size_applies_to_segments() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let server = UdpServer::bind(0).unwrap().with_gro().with_max_datagram_size(800);
+        let handle = server
+            .start_async(move |_src_addr, data| received_clone.lock().unwrap().push(data.len()))
+            .unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+
+        // 9 个 1000 字节的分段超过最大长度，只有 500 字节的尾段交给回调
+        let client = UdpClient::new().unwrap();
+        assert_eq!(client.send_segmented(addr, &[0x5A; 9500], 1000).unwrap(), 10);
+
+        thread::sleep(Duration::from_millis(200));
+        let stats = handle.stats();
+        handle.shutdown().unwrap();
+        assert_eq!(*received.lock().unwrap(), [500]);
+        assert_eq!(stats.truncated(), 9);
+    }
+
+    #[test]
+    fn test_segment_size_validation() {
+        let client = UdpClient::new().unwrap();
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        assert!(client.send_segmented(addr, b"data", 0).is_err());
+        // 数据不超过一个分段时直接发送
+        assert_eq!(client.send_segmented(addr, b"data", 1000).unwrap(), 1);
+    }
+}
@@ -0,0 +1,260 @@
+// pacer.rs
+// 令牌桶限速：按包速率或比特速率发送，允许不超过桶容量的突发。
+// 多个线程共享同一个 Pacer 时总速率受限；等待时先睡眠，最后一小段让出 CPU 自旋，
+// 既能达到亚毫秒精度，又不会在长间隔下占满一个核。
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 默认的自旋区间，等待的最后这段时间改为让出 CPU 轮询
+pub const DEFAULT_SPIN: Duration = Duration::from_micros(100);
+/// 包速率下限，更低的速率等待时间过长
+pub const MIN_RATE_PPS: f64 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Packets,
+    Bits,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // 可为负，表示已经预约但尚未到期的发送
+    tokens: f64,
+    last: Instant,
+}
+
+/// 令牌桶限速器
+#[derive(Debug)]
+pub struct Pacer {
+    unit: Unit,
+    // 每秒补充的令牌数
+    rate: f64,
+    // 桶容量
+    burst: f64,
+    spin: Duration,
+    bucket: Mutex<Bucket>,
+}
+
+impl Pacer {
+    // 每秒 pps 个包，最多连续突发 burst 个包
+    pub fn packets_per_sec(pps: f64, burst: u32) -> Self {
+        Self::new(Unit::Packets, pps, burst.max(1) as f64)
+    }
+
+    // 每秒 bps 比特（按数据报长度计算，不含 IP/UDP 头），最多连续突发 burst_bytes 字节
+    pub fn bits_per_sec(bps: u64, burst_bytes: usize) -> Self {
+        Self::new(Unit::Bits, bps as f64, (burst_bytes.max(1) * 8) as f64)
+    }
+
+    // 命令行工具用：按包速率或兆比特速率创建，包速率优先，都没有时不限速。
+    // 突发按 burst 个 packet_size 字节的包计算
+    pub fn from_rates(rate_pps: Option<f64>, rate_mbps: Option<f64>, burst: u32, packet_size: usize) -> Option<Self> {
+        match (rate_pps, rate_mbps) {
+            (Some(pps), _) => Some(Self::packets_per_sec(pps, burst)),
+            (None, Some(mbps)) => Some(Self::bits_per_sec((mbps * 1_000_000.0) as u64, packet_size * burst as usize)),
+            (None, None) => None,
+        }
+    }
+
+    fn new(unit: Unit, rate: f64, burst: f64) -> Self {
+        assert!(rate > 0.0 && rate.is_finite(), "pacing rate must be positive");
+        Pacer {
+            unit,
+            rate,
+            burst,
+            spin: DEFAULT_SPIN,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    // 设置自旋区间，Duration::ZERO 表示只睡眠（精度受系统定时器限制）
+    pub fn with_spin(mut self, spin: Duration) -> Self {
+        self.spin = spin;
+        self
+    }
+
+    // 等待直到可以发送 len 字节的数据报
+    pub fn acquire(&self, len: usize) {
+        if let Some(deadline) = self.reserve(len) {
+            wait_until(deadline, self.spin);
+        }
+    }
+
+    // 不等待，令牌足够时扣除并返回 true
+    pub fn try_acquire(&self, len: usize) -> bool {
+        let cost = self.cost(len);
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, Instant::now());
+        // 超过桶容量的大包在桶满时也允许发送，差额记为欠账
+        if bucket.tokens >= cost.min(self.burst) {
+            bucket.tokens -= cost;
+            true
+        } else {
+            false
+        }
+    }
+
+    // 扣除令牌并返回需要等待到的时间；令牌不足时先记欠账，保证多个线程按预约顺序发送
+    fn reserve(&self, len: usize) -> Option<Instant> {
+        let cost = self.cost(len);
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        self.refill(&mut bucket, now);
+        let deficit = cost.min(self.burst) - bucket.tokens;
+        bucket.tokens -= cost;
+        (deficit > 0.0).then(|| now + Duration::from_secs_f64(deficit / self.rate))
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last = now;
+    }
+
+    fn cost(&self, len: usize) -> f64 {
+        match self.unit {
+            Unit::Packets => 1.0,
+            Unit::Bits => (len * 8) as f64,
+        }
+    }
+}
+
+// 解析包速率参数（包/秒），用作 clap 的 value_parser
+pub fn parse_rate_pps(s: &str) -> Result<f64, String> {
+    let pps: f64 = s.parse().map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if !(MIN_RATE_PPS..=f64::MAX).contains(&pps) {
+        return Err(format!("速率必须是不小于 {} 的有限值（包/秒）", MIN_RATE_PPS));
+    }
+    Ok(pps)
+}
+
+// 解析比特速率参数（兆比特/秒）。限速器按整数比特/秒计算，不足 1 bit/s 的速率会变成 0
+pub fn parse_rate_mbps(s: &str) -> Result<f64, String> {
+    let mbps: f64 = s.parse().map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if !(mbps * 1_000_000.0 >= 1.0 && mbps.is_finite()) {
+        return Err("速率必须是不小于 0.000001 的有限值（兆比特/秒，即 1 bit/s）".to_string());
+    }
+    Ok(mbps)
+}
+
+// 睡眠到 deadline 前 spin 处，剩余时间让出 CPU 轮询
+fn wait_until(deadline: Instant, spin: Duration) {
+    let now = Instant::now();
+    if deadline <= now {
+        return;
+    }
+    let remaining = deadline - now;
+    if remaining > spin {
+        thread::sleep(remaining - spin);
+    }
+    while Instant::now() < deadline {
+        thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryNetwork, UdpClient};
+    use std::sync::Arc;
+
+    fn timed(f: impl FnOnce()) -> Duration {
+        let start = Instant::now();
+        f();
+        start.elapsed()
+    }
+
+    // 下限检验限速；上限留出余量，测试并行运行时线程可能晚醒
+    fn assert_near(elapsed: Duration, expected: Duration) {
+        assert!(elapsed >= expected.mul_f64(0.95), "{:?} < {:?}", elapsed, expected);
+        assert!(elapsed < expected.mul_f64(2.5), "{:?} > {:?}", elapsed, expected);
+    }
+
+    #[test]
+    fn test_packet_rate_sub_millisecond() {
+        // 间隔 100 微秒
+        let pacer = Pacer::packets_per_sec(10_000.0, 1);
+        let elapsed = timed(|| (0..1001).for_each(|_| pacer.acquire(64)));
+        // 按毫秒取整的等待至少需要 1 秒，上限只需排除这种情况
+        assert!(elapsed >= Duration::from_millis(95), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_bit_rate() {
+        // 8 Mbit/s 即 1 MB/s，100 个 1000 字节的数据报
+        let pacer = Pacer::bits_per_sec(8_000_000, 1000);
+        let elapsed = timed(|| (0..101).for_each(|_| pacer.acquire(1000)));
+        assert_near(elapsed, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_burst() {
+        let pacer = Pacer::packets_per_sec(10.0, 5);
+        assert!((0..5).all(|_| pacer.try_acquire(1)));
+        assert!(!pacer.try_acquire(1));
+        let elapsed = timed(|| pacer.acquire(1));
+        assert_near(elapsed, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        let pacer = Arc::new(Pacer::packets_per_sec(2000.0, 1));
+        pacer.acquire(0);
+        let elapsed = timed(|| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    let pacer = pacer.clone();
+                    thread::spawn(move || (0..50).for_each(|_| pacer.acquire(0)))
+                })
+                .collect();
+            threads.into_iter().for_each(|t| t.join().unwrap());
+        });
+        assert_near(elapsed, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_parse_rates() {
+        assert_eq!(parse_rate_pps("2.5"), Ok(2.5));
+        assert!(parse_rate_pps("0").is_err());
+        assert!(parse_rate_pps("inf").is_err());
+        assert!(parse_rate_pps("fast").is_err());
+        assert_eq!(parse_rate_mbps("0.000001"), Ok(0.000001));
+        assert!(parse_rate_mbps("0.0000001").is_err());
+        assert!(parse_rate_mbps("NaN").is_err());
+
+        assert!(Pacer::from_rates(None, None, 1, 1000).is_none());
+        let pacer = Pacer::from_rates(Some(10.0), Some(1.0), 3, 1000).unwrap();
+        assert_eq!((pacer.unit, pacer.rate, pacer.burst), (Unit::Packets, 10.0, 3.0));
+        let pacer = Pacer::from_rates(None, Some(1.0), 2, 1000).unwrap();
+        assert_eq!((pacer.unit, pacer.rate, pacer.burst), (Unit::Bits, 1_000_000.0, 16_000.0));
+    }
+
+    #[test]
+    fn test_batch_and_segmented_sends_paced() {
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let client = UdpClient::new().unwrap().with_pacer(Arc::new(Pacer::packets_per_sec(500.0, 1)));
+        // 每个数据报或分段各占一个令牌，桶里的一个令牌让第一个立即发出
+        let elapsed = timed(|| {
+            client.send_batch(addr, &[b"paced".as_slice(); 26]).unwrap();
+            assert_eq!(client.send_segmented(addr, &[0u8; 250], 10).unwrap(), 25);
+        });
+        assert_near(elapsed, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_client_send_paced() {
+        let network = MemoryNetwork::new();
+        let receiver = network.bind();
+        let client = UdpClient::from_socket(network.bind()).with_pacer(Arc::new(Pacer::packets_per_sec(500.0, 1)));
+        // 返回的发送时刻在限速等待之后
+        let start = Instant::now();
+        let sent_at = (0..51).fold(start, |_, _| client.send_timed(receiver.addr(), b"paced").unwrap());
+        assert_near(sent_at - start, Duration::from_millis(100));
+    }
+}
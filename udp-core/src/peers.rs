@@ -0,0 +1,360 @@
+// peers.rs
+// 对端表：记录每个来源地址的首次/最近活动时间、包数、字节数和序列号跳变，
+// 能解析出 Layer1/Layer2 帧头时同时记录设备类型和设备序号。空闲超过超时时间的对端被移除并通过回调通知。
+use crate::UdpServer;
+use crate::transport::DatagramTransport;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use udp_protocol::{DeviceType, Layer1Protocol, Layer2Protocol};
+
+/// 未指定时的对端空闲超时
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 两次过期检查之间的最短间隔，与接收线程的轮询间隔一致
+const SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 单个对端的状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo<A = SocketAddr> {
+    pub addr: A,
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+    /// 收到的数据报个数
+    pub packets: u64,
+    /// 收到的字节数
+    pub bytes: u64,
+    /// 序列号向前跳变的次数
+    pub seq_gaps: u64,
+    /// 跳过的序列号个数，可用来估计丢包
+    pub seq_missing: u64,
+    /// 最近一个按顺序到达的帧序列号
+    pub last_seq: Option<u16>,
+    /// 最近一帧中的设备类型和序号
+    pub device_type: Option<DeviceType>,
+    pub device_index: Option<u16>,
+}
+
+impl<A> PeerInfo<A> {
+    fn new(addr: A, now: Instant) -> Self {
+        PeerInfo {
+            addr,
+            first_seen: now,
+            last_seen: now,
+            packets: 0,
+            bytes: 0,
+            seq_gaps: 0,
+            seq_missing: 0,
+            last_seq: None,
+            device_type: None,
+            device_index: None,
+        }
+    }
+
+    // 距最近一次活动的时间
+    pub fn idle(&self) -> Duration {
+        self.last_seen.elapsed()
+    }
+
+    fn record(&mut self, data: &[u8], now: Instant) {
+        self.last_seen = now;
+        self.packets += 1;
+        self.bytes += data.len() as u64;
+
+        let Ok(layer1) = Layer1Protocol::deserialize(data) else {
+            return;
+        };
+        let seq = layer1.frame_seq_number;
+        match self.last_seq {
+            Some(last) => {
+                // 差值落在后半区间视为重传或乱序到达的旧帧，不更新序列号
+                let skipped = seq.wrapping_sub(last.wrapping_add(1));
+                if skipped < 0x8000 {
+                    if skipped > 0 {
+                        self.seq_gaps += 1;
+                        self.seq_missing += skipped as u64;
+                    }
+                    self.last_seq = Some(seq);
+                }
+            }
+            None => self.last_seq = Some(seq),
+        }
+        if let Ok(layer2) = Layer2Protocol::deserialize(&layer1.payload) {
+            self.device_type = Some(layer2.device_type);
+            self.device_index = Some(layer2.device_index);
+        }
+    }
+}
+
+/// 对端表事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerEvent<A = SocketAddr> {
+    /// 第一次收到该地址的数据报
+    Joined(PeerInfo<A>),
+    /// 空闲超时被移除，携带移除前的最终状态
+    Expired(PeerInfo<A>),
+}
+
+/// 对端事件回调，在接收线程中调用
+pub type PeerHandler<A = SocketAddr> = Box<dyn FnMut(PeerEvent<A>) + Send>;
+
+/// 对端表，可在处理函数和其他线程中查询
+pub struct PeerTable<A = SocketAddr> {
+    idle_timeout: Duration,
+    peers: Mutex<HashMap<A, PeerInfo<A>>>,
+    handler: Mutex<Option<PeerHandler<A>>>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl<A> PeerTable<A>
+where
+    A: Clone + Eq + Hash + Debug,
+{
+    pub fn new(idle_timeout: Duration) -> Self {
+        PeerTable {
+            idle_timeout,
+            peers: Mutex::new(HashMap::new()),
+            handler: Mutex::new(None),
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    pub fn get(&self, addr: &A) -> Option<PeerInfo<A>> {
+        self.peers.lock().unwrap().get(addr).cloned()
+    }
+
+    // 所有对端，按首次出现时间排序
+    pub fn peers(&self) -> Vec<PeerInfo<A>> {
+        let mut peers: Vec<_> = self.peers.lock().unwrap().values().cloned().collect();
+        peers.sort_by_key(|peer| peer.first_seen);
+        peers
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn set_handler(&self, handler: PeerHandler<A>) {
+        *self.handler.lock().unwrap() = Some(handler);
+    }
+
+    // 记录一个数据报
+    pub(crate) fn record(&self, addr: &A, data: &[u8], now: Instant) {
+        let joined = {
+            let mut peers = self.peers.lock().unwrap();
+            let joined = !peers.contains_key(addr);
+            let peer = peers.entry(addr.clone()).or_insert_with(|| PeerInfo::new(addr.clone(), now));
+            peer.record(data, now);
+            joined.then(|| peer.clone())
+        };
+        if let Some(peer) = joined {
+            tracing::debug!(peer = ?peer.addr, "peer joined");
+            self.emit(PeerEvent::Joined(peer));
+        }
+    }
+
+    // 移除空闲超时的对端，距上次检查不足检查间隔时直接返回
+    pub(crate) fn expire(&self, now: Instant) {
+        {
+            let mut last_sweep = self.last_sweep.lock().unwrap();
+            if now.saturating_duration_since(*last_sweep) < SWEEP_INTERVAL {
+                return;
+            }
+            *last_sweep = now;
+        }
+        let mut expired = Vec::new();
+        self.peers.lock().unwrap().retain(|_, peer| {
+            let alive = now.saturating_duration_since(peer.last_seen) < self.idle_timeout;
+            if !alive {
+                expired.push(peer.clone());
+            }
+            alive
+        });
+        expired.sort_by_key(|peer| peer.last_seen);
+        for peer in expired {
+            tracing::debug!(peer = ?peer.addr, packets = peer.packets, "peer expired");
+            self.emit(PeerEvent::Expired(peer));
+        }
+    }
+
+    fn emit(&self, event: PeerEvent<A>) {
+        if let Some(handler) = self.handler.lock().unwrap().as_mut() {
+            handler(event);
+        }
+    }
+}
+
+impl<T: DatagramTransport> UdpServer<T> {
+    // 开启对端表，空闲超过 idle_timeout 的对端被移除
+    pub fn with_peer_table(mut self, idle_timeout: Duration) -> Self {
+        self.peers = Some(Arc::new(PeerTable::new(idle_timeout)));
+        self
+    }
+
+    // 设置对端加入和过期的回调，未开启对端表时按默认 60 秒空闲超时开启
+    pub fn on_peer_event<H>(mut self, handler: H) -> Self
+    where
+        H: FnMut(PeerEvent<T::Addr>) + Send + 'static,
+    {
+        if self.peers.is_none() {
+            self = self.with_peer_table(DEFAULT_IDLE_TIMEOUT);
+        }
+        if let Some(peers) = &self.peers {
+            peers.set_handler(Box::new(handler));
+        }
+        self
+    }
+
+    // 对端表，启动前取得后可以交给处理函数查询
+    pub fn peer_table(&self) -> Option<Arc<PeerTable<T::Addr>>> {
+        self.peers.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryNetwork, UdpClient};
+    use std::sync::mpsc;
+    use udp_protocol::{CheckType, FrameType, Priority, ReqRsp, RequestBodyType};
+
+    fn frame(seq: u16, device_index: u16) -> Vec<u8> {
+        let layer2 = Layer2Protocol {
+            req_rsp: ReqRsp::Request,
+            is_need_reply: false,
+            code: false,
+            flag: false,
+            request_body_type: RequestBodyType::RegisterProtocol,
+            device_type: DeviceType::MCU,
+            device_index,
+            group: [0; 8],
+            payload: Vec::new(),
+        };
+        Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: Priority::Medium,
+            check_type: CheckType::CheckSum,
+            frame_type: FrameType::Type0,
+            frame_seq_number: seq,
+            frame_length: 0,
+            payload: layer2.serialize(),
+            checksum: 0,
+        }
+        .serialize()
+    }
+
+    #[test]
+    fn test_sequence_gaps_and_device() {
+        let now = Instant::now();
+        let mut peer = PeerInfo::new(1u32, now);
+        for seq in [0xFFFE, 0xFFFF, 0, 3, 2, 3, 4] {
+            peer.record(&frame(seq, 7), now);
+        }
+        peer.record(b"raw", now);
+
+        assert_eq!(peer.packets, 8);
+        // 0 -> 3 跳过 1、2；回退的 2 和重复的 3 不算跳变
+        assert_eq!(peer.seq_gaps, 1);
+        assert_eq!(peer.seq_missing, 2);
+        assert_eq!(peer.last_seq, Some(4));
+        assert_eq!(peer.device_type, Some(DeviceType::MCU));
+        assert_eq!(peer.device_index, Some(7));
+    }
+
+    #[test]
+    fn test_server_peer_table_and_expiry() {
+        let network = MemoryNetwork::new();
+        let (events_tx, events) = mpsc::channel();
+        let server = UdpServer::from_socket(network.bind())
+            .with_peer_table(Duration::from_millis(200))
+            .on_peer_event(move |event| {
+                let _ = events_tx.send(event);
+            });
+        let peers = server.peer_table().unwrap();
+
+        // 处理函数中查询当前对端
+        let handler_peers = peers.clone();
+        let handle = server
+            .start_pool(1, move |src_addr, _, reply| {
+                let packets = handler_peers.get(&src_addr).map_or(0, |peer| peer.packets);
+                reply.send(&packets.to_le_bytes()).unwrap();
+            })
+            .unwrap();
+
+        let client = UdpClient::from_socket(network.bind());
+        for (seq, expected) in [(1u16, 1u64), (2, 2), (5, 3)] {
+            let reply = client
+                .send_and_receive(handle.local_addr(), &frame(seq, 3), Duration::from_secs(1))
+                .unwrap();
+            assert_eq!(reply, expected.to_le_bytes());
+        }
+
+        let peer = handle.peers().unwrap().get(&client.local_addr().unwrap()).unwrap();
+        assert_eq!(peer.seq_gaps, 1);
+        assert_eq!(peer.bytes, 3 * frame(1, 3).len() as u64);
+        assert_eq!(peer.device_index, Some(3));
+
+        let joined = events.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(joined, PeerEvent::Joined(ref peer) if peer.addr == client.local_addr().unwrap()));
+        let PeerEvent::Expired(expired) = events.recv_timeout(Duration::from_secs(2)).unwrap() else {
+            panic!("expected an expiry event");
+        };
+        assert_eq!(expired.packets, 3);
+        assert!(Arc::ptr_eq(&peers, &handle.peers().unwrap()));
+        assert!(peers.is_empty());
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_bad_frame_length_with_peer_table() {
+        let network = MemoryNetwork::new();
+        let handle = UdpServer::from_socket(network.bind())
+            .with_peer_table(DEFAULT_IDLE_TIMEOUT)
+            .start_pool(1, |_, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+
+        // Frame Length 为 0xFFFF 的数据报只记为普通数据报，服务器继续回复
+        let client = UdpClient::from_socket(network.bind());
+        let bad = [0x55, 0xBB, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00];
+        for _ in 0..2 {
+            let reply = client.send_and_receive(handle.local_addr(), &bad, Duration::from_secs(1)).unwrap();
+            assert_eq!(reply, bad);
+        }
+        let peer = handle.peers().unwrap().get(&client.local_addr().unwrap()).unwrap();
+        assert_eq!((peer.packets, peer.last_seq), (2, None));
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_receive_thread_panic_does_not_block_shutdown() {
+        let network = MemoryNetwork::new();
+        let handle = UdpServer::from_socket(network.bind())
+            .on_peer_event(|_| panic!("peer handler failed"))
+            .start_async(|_, _| {})
+            .unwrap();
+
+        let client = UdpClient::from_socket(network.bind());
+        client.send_only(handle.local_addr(), b"hello").unwrap();
+        // 接收线程 panic 后处理队列被关闭，shutdown 返回错误而不是一直等待
+        let (done_tx, done) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = done_tx.send(handle.shutdown().is_err());
+        });
+        assert_eq!(done.recv_timeout(Duration::from_secs(2)), Ok(true));
+    }
+}
@@ -0,0 +1,252 @@
+// pool.rs
+use crate::metrics::TransportMetrics;
+use crate::queue::{BoundedQueue, CloseOnDrop};
+use crate::stats::ServerStats;
+use crate::transport::DatagramTransport;
+use crate::{Datagram, Monitor, ServerHandle, UdpServer, enqueue, receive_loop};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+
+/// 回复发送器，通过接收数据报的 socket 给来源地址回包
+pub struct ReplySender<'a, T: DatagramTransport = UdpSocket> {
+    socket: &'a T,
+    peer: T::Addr,
+    auto_dscp: bool,
+    metrics: Option<&'a TransportMetrics>,
+}
+
+impl<'a, T: DatagramTransport> ReplySender<'a, T> {
+    pub(crate) fn new(socket: &'a T, peer: T::Addr, auto_dscp: bool, metrics: Option<&'a TransportMetrics>) -> Self {
+        ReplySender {
+            socket,
+            peer,
+            auto_dscp,
+            metrics,
+        }
+    }
+
+    // 数据报的来源地址
+    pub fn peer(&self) -> T::Addr {
+        self.peer.clone()
+    }
+
+    // 回复给数据报的来源地址
+    pub fn send(&self, data: &[u8]) -> io::Result<usize> {
+        self.send_to(data, &self.peer)
+    }
+
+    // 通过同一个 socket 发送给其他地址
+    pub fn send_to(&self, data: &[u8], addr: &T::Addr) -> io::Result<usize> {
+        let sent = if self.auto_dscp {
+            self.socket.send_prioritized(data, addr)?
+        } else {
+            self.socket.send_to(data, addr)?
+        };
+        if let Some(metrics) = self.metrics {
+            metrics.sent(sent);
+        }
+        Ok(sent)
+    }
+}
+
+// 按来源地址分片，同一个对端总是落在同一个工作线程上，保证单个对端内的顺序
+pub(crate) fn shard_for<A: Hash>(addr: &A, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    addr.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+impl<T: DatagramTransport> UdpServer<T> {
+    // 启动 workers 个处理线程，按来源地址分发数据报；处理函数通过 ReplySender 回包
+    pub fn start_pool<F>(self, workers: usize, handler: F) -> io::Result<ServerHandle<T::Addr>>
+    where
+        F: Fn(T::Addr, &[u8], &ReplySender<T>) + Send + Sync + 'static,
+    {
+        let workers = workers.max(1);
+        let batch = self.prepare_receive()?;
+        let queues: Vec<Arc<BoundedQueue<Datagram<T::Addr>>>> = (0..workers)
+            .map(|_| Arc::new(BoundedQueue::new(self.queue_capacity, self.overflow_policy)))
+            .collect();
+        let stats = Arc::new(ServerStats::with_metrics(self.metrics));
+        let peers = self.peers;
+        let handler = Arc::new(handler);
+        let socket = self.socket;
+        let mut error_handler = self.error_handler;
+        let auto_dscp = self.auto_dscp;
+        let local_addr = socket.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+
+        // 每个工作线程持有一个 socket 克隆用于回包。先克隆完再启动线程，
+        // 克隆失败时直接返回，不会留下阻塞在空队列上的工作线程
+        let reply_sockets = queues.iter().map(|_| socket.try_clone()).collect::<io::Result<Vec<_>>>()?;
+        let mut threads = Vec::with_capacity(workers + 1);
+        for (queue, reply_socket) in queues.iter().zip(reply_sockets) {
+            let queue = queue.clone();
+            let stats = stats.clone();
+            let handler = handler.clone();
+            threads.push(thread::spawn(move || {
+                let _guard = CloseOnDrop(&queue);
+                while let Some((src_addr, data)) = queue.pop() {
+                    let reply = ReplySender::new(&reply_socket, src_addr.clone(), auto_dscp, stats.metrics());
+                    handler(src_addr, &data, &reply);
+                    stats.add_processed();
+                }
+            }));
+        }
+
+        let receiver = {
+            let queues = queues.clone();
+            let stats = stats.clone();
+            let peers = peers.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                let monitor = Monitor {
+                    stats: &stats,
+                    peers: peers.as_deref(),
+                };
+                receive_loop(&socket, batch, &running, monitor, &queues, &mut error_handler, |datagram| {
+                    let queue = &queues[shard_for(&datagram.0, queues.len())];
+                    enqueue(queue, &stats, datagram)
+                });
+            })
+        };
+        threads.insert(0, receiver);
+
+        Ok(ServerHandle {
+            local_addr,
+            running,
+            stats,
+            peers,
+            queues,
+            threads,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryAddr, MemoryNetwork, MemoryTransport, UdpClient};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_pool_reply_sender() {
+        let server = UdpServer::bind(0).unwrap();
+        let handle = server
+            .start_pool(4, |_src_addr, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        let client = UdpClient::new().unwrap();
+        let response = client.send_and_receive(addr, b"pooled", Duration::from_secs(1)).unwrap();
+        assert_eq!(response, b"pooled");
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_pool_keeps_per_peer_order() {
+        let server = UdpServer::bind(0).unwrap();
+        let seen: Arc<Mutex<HashMap<SocketAddr, Vec<u32>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let seen_clone = seen.clone();
+        let handle = server
+            .start_pool(4, move |src_addr, data, _reply| {
+                let seq = u32::from_le_bytes(data.try_into().unwrap());
+                seen_clone.lock().unwrap().entry(src_addr).or_default().push(seq);
+            })
+            .unwrap();
+
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        let clients: Vec<UdpClient> = (0..3).map(|_| UdpClient::new().unwrap()).collect();
+        for seq in 0..100u32 {
+            for client in &clients {
+                client.send_only(addr, &seq.to_le_bytes()).unwrap();
+            }
+        }
+        thread::sleep(Duration::from_millis(200));
+        let stats = handle.stats();
+        handle.shutdown().unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        for seqs in seen.values() {
+            assert!(seqs.windows(2).all(|w| w[0] < w[1]), "per-peer order broken");
+        }
+        assert_eq!(stats.processed(), stats.queued());
+    }
+
+    // 只允许克隆 remaining 次的内存传输，用于模拟 try_clone 失败
+    struct CloneLimited {
+        inner: MemoryTransport,
+        remaining: Arc<AtomicUsize>,
+    }
+
+    impl DatagramTransport for CloneLimited {
+        type Addr = MemoryAddr;
+
+        fn send_to(&self, buf: &[u8], addr: &MemoryAddr) -> io::Result<usize> {
+            self.inner.send_to(buf, addr)
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, MemoryAddr)> {
+            self.inner.recv_from(buf)
+        }
+
+        fn local_addr(&self) -> io::Result<MemoryAddr> {
+            self.inner.local_addr()
+        }
+
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            self.inner.set_read_timeout(timeout)
+        }
+
+        fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+            self.inner.set_nonblocking(nonblocking)
+        }
+
+        fn try_clone(&self) -> io::Result<Self> {
+            self.remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .map_err(|_| io::Error::other("Clone limit reached"))?;
+            Ok(CloneLimited {
+                inner: self.inner.try_clone()?,
+                remaining: self.remaining.clone(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_pool_clone_failure_leaves_no_workers() {
+        let socket = CloneLimited {
+            inner: MemoryNetwork::new().bind(),
+            remaining: Arc::new(AtomicUsize::new(2)),
+        };
+        // 处理函数被每个工作线程持有，启动失败后应只剩这里的引用
+        let token = Arc::new(());
+        let held = token.clone();
+        let result = UdpServer::from_socket(socket).start_pool(4, move |_, _, _| {
+            let _ = &held;
+        });
+        assert!(result.is_err());
+        assert_eq!(Arc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn test_shard_is_stable() {
+        let addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let shard = shard_for(&addr, 8);
+        assert!(shard < 8);
+        assert_eq!(shard_for(&addr, 8), shard);
+    }
+}
@@ -0,0 +1,342 @@
+// proxy.rs
+// 损伤代理：在客户端和上游服务之间转发 UDP 数据报，两个方向分别施加损伤。
+// 每个客户端地址使用独立的上游 socket，上游回包据此转发回对应的客户端。
+use crate::impair::{ImpairedTransport, Impairment, ImpairmentConfig, ImpairmentStats};
+use crate::transport::DatagramTransport;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// 接收线程检查停止标志的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// 默认的会话空闲超时
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 损伤代理
+pub struct ImpairmentProxy {
+    socket: UdpSocket,
+    upstream: SocketAddr,
+    uplink: ImpairmentConfig,
+    downlink: ImpairmentConfig,
+    session_timeout: Duration,
+}
+
+impl ImpairmentProxy {
+    // 在 port 上监听，转发到 upstream；port 为 0 时由系统分配
+    pub fn bind(port: u16, upstream: SocketAddr) -> io::Result<Self> {
+        Ok(Self::from_socket(UdpSocket::bind(("0.0.0.0", port))?, upstream))
+    }
+
+    pub fn from_socket(socket: UdpSocket, upstream: SocketAddr) -> Self {
+        ImpairmentProxy {
+            socket,
+            upstream,
+            uplink: ImpairmentConfig::default(),
+            downlink: ImpairmentConfig::default(),
+            session_timeout: DEFAULT_SESSION_TIMEOUT,
+        }
+    }
+
+    // 客户端到上游方向的损伤
+    pub fn with_uplink(mut self, config: ImpairmentConfig) -> Self {
+        self.uplink = config;
+        self
+    }
+
+    // 上游到客户端方向的损伤
+    pub fn with_downlink(mut self, config: ImpairmentConfig) -> Self {
+        self.downlink = config;
+        self
+    }
+
+    // 客户端超过该时间没有收发数据时释放其上游 socket
+    pub fn with_session_timeout(mut self, timeout: Duration) -> Self {
+        self.session_timeout = timeout;
+        self
+    }
+
+    pub fn start(self) -> io::Result<ProxyHandle> {
+        let local_addr = self.socket.local_addr()?;
+        self.socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let downlink_impairment = Impairment::new(self.downlink);
+        let downlink_seed = downlink_impairment.seed();
+        let downlink_stats = downlink_impairment.stats();
+        let downlink = Arc::new(ImpairedTransport::with_impairment(self.socket, downlink_impairment)?);
+
+        // 每个会话的上游损伤使用由同一种子派生的独立随机序列，共享一组计数器
+        let uplink_seed = self.uplink.seed.unwrap_or_else(rand::random);
+        let uplink_stats = Arc::new(ImpairmentStats::default());
+        let uplink = UplinkFactory {
+            config: self.uplink,
+            seed: uplink_seed,
+            stats: uplink_stats.clone(),
+            sessions: 0,
+        };
+
+        let running = Arc::new(AtomicBool::new(true));
+        let context = Arc::new(ProxyContext {
+            upstream: self.upstream,
+            session_timeout: self.session_timeout,
+            running: running.clone(),
+            downlink,
+            sessions: Mutex::new(HashMap::new()),
+        });
+        let thread = thread::spawn(move || context.forward_loop(uplink));
+
+        Ok(ProxyHandle {
+            local_addr,
+            uplink_seed,
+            downlink_seed,
+            uplink_stats,
+            downlink_stats,
+            running,
+            thread,
+        })
+    }
+}
+
+struct UplinkFactory {
+    config: ImpairmentConfig,
+    seed: u64,
+    stats: Arc<ImpairmentStats>,
+    sessions: u64,
+}
+
+impl UplinkFactory {
+    fn next(&mut self) -> Impairment {
+        let config = self.config.clone().with_seed(self.seed.wrapping_add(self.sessions));
+        self.sessions += 1;
+        Impairment::new(config).with_stats(self.stats.clone())
+    }
+}
+
+struct Session {
+    upstream: ImpairedTransport<UdpSocket>,
+    last_active: Mutex<Instant>,
+}
+
+impl Session {
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_active.lock().unwrap().elapsed()
+    }
+}
+
+struct ProxyContext {
+    upstream: SocketAddr,
+    session_timeout: Duration,
+    running: Arc<AtomicBool>,
+    downlink: Arc<ImpairedTransport<UdpSocket>>,
+    sessions: Mutex<HashMap<SocketAddr, Arc<Session>>>,
+}
+
+impl ProxyContext {
+    // 接收客户端数据报并通过对应会话转发到上游
+    fn forward_loop(self: Arc<Self>, mut uplink: UplinkFactory) {
+        let mut session_threads: Vec<JoinHandle<()>> = Vec::new();
+        let mut buf = vec![0; crate::MAX_DATAGRAM_SIZE];
+        while self.running.load(Ordering::Relaxed) {
+            let (num_bytes, client) = match self.downlink.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if crate::retry::is_timeout(&e) => continue,
+                Err(e) => {
+                    // 非超时错误（如 ICMP 不可达）稍等再收，避免空转刷屏
+                    tracing::warn!(error = %e, "proxy receive failed");
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+
+            let session = match self.session(client, &mut uplink, &mut session_threads) {
+                Ok(session) => session,
+                Err(e) => {
+                    tracing::warn!(%client, error = %e, "failed to open upstream socket");
+                    continue;
+                }
+            };
+            session.touch();
+            if let Err(e) = session.upstream.send_to(&buf[..num_bytes], &self.upstream) {
+                tracing::warn!(upstream = %self.upstream, error = %e, "forward to upstream failed");
+            }
+            session_threads.retain(|thread| !thread.is_finished());
+        }
+
+        for thread in session_threads {
+            let _ = thread.join();
+        }
+    }
+
+    fn session(
+        self: &Arc<Self>,
+        client: SocketAddr,
+        uplink: &mut UplinkFactory,
+        session_threads: &mut Vec<JoinHandle<()>>,
+    ) -> io::Result<Arc<Session>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get(&client) {
+            return Ok(session.clone());
+        }
+
+        let bind_addr: SocketAddr = if self.upstream.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let session = Arc::new(Session {
+            upstream: ImpairedTransport::with_impairment(socket, uplink.next())?,
+            last_active: Mutex::new(Instant::now()),
+        });
+        sessions.insert(client, session.clone());
+        tracing::debug!(%client, "proxy session opened");
+
+        let context = self.clone();
+        let reader = session.clone();
+        session_threads.push(thread::spawn(move || context.reply_loop(client, &reader)));
+        Ok(session)
+    }
+
+    // 把上游回包转发给客户端，会话空闲超时后退出
+    fn reply_loop(&self, client: SocketAddr, session: &Session) {
+        let mut buf = vec![0; crate::MAX_DATAGRAM_SIZE];
+        while self.running.load(Ordering::Relaxed) {
+            match session.upstream.recv_from(&mut buf) {
+                Ok((num_bytes, src_addr)) if src_addr == self.upstream => {
+                    session.touch();
+                    if let Err(e) = self.downlink.send_to(&buf[..num_bytes], &client) {
+                        tracing::warn!(%client, error = %e, "forward to client failed");
+                    }
+                }
+                Ok(_) => {}
+                Err(e) if crate::retry::is_timeout(&e) => {
+                    let mut sessions = self.sessions.lock().unwrap();
+                    if session.idle_for() >= self.session_timeout {
+                        sessions.remove(&client);
+                        tracing::debug!(%client, "proxy session expired");
+                        return;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(upstream = %self.upstream, error = %e, "upstream receive failed");
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+    }
+}
+
+/// 损伤代理运行句柄
+pub struct ProxyHandle {
+    local_addr: SocketAddr,
+    uplink_seed: u64,
+    downlink_seed: u64,
+    uplink_stats: Arc<ImpairmentStats>,
+    downlink_stats: Arc<ImpairmentStats>,
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ProxyHandle {
+    // 客户端应发往的代理地址
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // 上游方向使用的种子，第 n 个会话使用 seed + n
+    pub fn uplink_seed(&self) -> u64 {
+        self.uplink_seed
+    }
+
+    pub fn downlink_seed(&self) -> u64 {
+        self.downlink_seed
+    }
+
+    pub fn uplink_stats(&self) -> Arc<ImpairmentStats> {
+        self.uplink_stats.clone()
+    }
+
+    pub fn downlink_stats(&self) -> Arc<ImpairmentStats> {
+        self.downlink_stats.clone()
+    }
+
+    // 通知转发线程退出并等待结束
+    pub fn shutdown(self) -> thread::Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        self.thread.join()
+    }
+
+    // 等待转发线程结束（通常不会主动结束）
+    pub fn join(self) -> thread::Result<()> {
+        self.thread.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{UdpClient, UdpServer};
+
+    fn echo_server() -> (crate::ServerHandle, SocketAddr) {
+        let handle = UdpServer::bind(0)
+            .unwrap()
+            .start_pool(1, |_src_addr, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+        let addr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+        (handle, addr)
+    }
+
+    #[test]
+    fn test_proxy_delays_both_directions() {
+        let (server, upstream) = echo_server();
+        let delay = ImpairmentConfig::new().with_delay(Duration::from_millis(30), Duration::ZERO);
+        let proxy = ImpairmentProxy::bind(0, upstream)
+            .unwrap()
+            .with_uplink(delay.clone())
+            .with_downlink(delay)
+            .start()
+            .unwrap();
+        let proxy_addr: SocketAddr = ([127, 0, 0, 1], proxy.local_addr().port()).into();
+
+        let client = UdpClient::new().unwrap();
+        let start = Instant::now();
+        let response = client.send_and_receive(proxy_addr, b"via proxy", Duration::from_secs(1)).unwrap();
+        assert_eq!(response, b"via proxy");
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert_eq!(proxy.uplink_stats().packets(), 1);
+        assert_eq!(proxy.downlink_stats().packets(), 1);
+
+        proxy.shutdown().unwrap();
+        server.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_proxy_drops_uplink() {
+        let (server, upstream) = echo_server();
+        let server_stats = server.stats();
+        let proxy = ImpairmentProxy::bind(0, upstream)
+            .unwrap()
+            .with_uplink(ImpairmentConfig::new().with_loss(1.0))
+            .start()
+            .unwrap();
+        let proxy_addr: SocketAddr = ([127, 0, 0, 1], proxy.local_addr().port()).into();
+
+        let client = UdpClient::new().unwrap();
+        assert!(client.send_and_receive(proxy_addr, b"lost", Duration::from_millis(100)).is_err());
+        assert_eq!(proxy.uplink_stats().dropped(), 1);
+        assert_eq!(server_stats.received(), 0);
+
+        proxy.shutdown().unwrap();
+        server.shutdown().unwrap();
+    }
+}
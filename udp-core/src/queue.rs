@@ -0,0 +1,173 @@
+// queue.rs
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// 接收队列已满时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// 丢弃新到达的数据报
+    DropNewest,
+    /// 丢弃队列中最早的数据报，为新数据报腾出位置
+    DropOldest,
+    /// 阻塞接收线程直到队列有空位（由内核缓冲区承担丢包）
+    #[default]
+    Block,
+}
+
+// 入队结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PushOutcome {
+    Queued,
+    // 入队成功，但挤掉了一个旧数据
+    QueuedDroppedOldest,
+    DroppedNewest,
+    // 消费端已经退出
+    Closed,
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+// 有界阻塞队列，支持三种溢出策略，关闭后消费端取完剩余数据再结束
+pub(crate) struct BoundedQueue<T> {
+    state: Mutex<QueueState<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl<T> BoundedQueue<T> {
+    pub(crate) fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        BoundedQueue {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    pub(crate) fn push(&self, item: T) -> PushOutcome {
+        let mut state = self.state.lock().unwrap();
+        let mut outcome = PushOutcome::Queued;
+        loop {
+            if state.closed {
+                return PushOutcome::Closed;
+            }
+            if state.items.len() < self.capacity {
+                break;
+            }
+            match self.policy {
+                OverflowPolicy::DropNewest => return PushOutcome::DroppedNewest,
+                OverflowPolicy::DropOldest => {
+                    state.items.pop_front();
+                    outcome = PushOutcome::QueuedDroppedOldest;
+                }
+                OverflowPolicy::Block => state = self.not_full.wait(state).unwrap(),
+            }
+        }
+        state.items.push_back(item);
+        self.not_empty.notify_one();
+        outcome
+    }
+
+    // 取出一个数据，队列为空时阻塞；关闭且为空时返回 None
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+}
+
+// 消费线程退出（包括回调 panic）时关闭队列，避免接收线程一直阻塞
+pub(crate) struct CloseOnDrop<'a, T>(pub(crate) &'a BoundedQueue<T>);
+
+impl<T> Drop for CloseOnDrop<'_, T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+// 接收线程退出（包括 panic）时关闭所有分发队列，处理线程处理完剩余数据后退出，shutdown 不会卡住
+pub(crate) struct CloseAllOnDrop<'a, T>(pub(crate) &'a [Arc<BoundedQueue<T>>]);
+
+impl<T> Drop for CloseAllOnDrop<'_, T> {
+    fn drop(&mut self) {
+        for queue in self.0 {
+            queue.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_drop_newest() {
+        let queue = BoundedQueue::new(2, OverflowPolicy::DropNewest);
+        assert_eq!(queue.push(1), PushOutcome::Queued);
+        assert_eq!(queue.push(2), PushOutcome::Queued);
+        assert_eq!(queue.push(3), PushOutcome::DroppedNewest);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let queue = BoundedQueue::new(2, OverflowPolicy::DropOldest);
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.push(3), PushOutcome::QueuedDroppedOldest);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+    }
+
+    #[test]
+    fn test_block_until_space_and_close() {
+        let queue = Arc::new(BoundedQueue::new(1, OverflowPolicy::Block));
+        queue.push(1);
+
+        let producer_queue = queue.clone();
+        let producer = thread::spawn(move || producer_queue.push(2));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(queue.len(), 1);
+
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(producer.join().unwrap(), PushOutcome::Queued);
+
+        // 关闭后仍能取完剩余数据，之后的入队被拒绝
+        queue.close();
+        assert_eq!(queue.push(3), PushOutcome::Closed);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+}
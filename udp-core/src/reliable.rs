@@ -0,0 +1,710 @@
+// reliable.rs
+use crate::retry::is_timeout;
+use crate::transport::DatagramTransport;
+use crate::{DEFAULT_MAX_DATAGRAM_SIZE, UdpClient, UdpServer};
+use std::collections::VecDeque;
+use std::io;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+use udp_protocol::ControlFrame;
+
+// 控制帧开销：第一层帧头10字节 + 校验和2字节 + 控制类型1字节
+const CONTROL_OVERHEAD: usize = 13;
+// 选择确认位图覆盖的序列号个数
+const SACK_BITS: usize = 64;
+// 窗口上限，保证序列号回绕时新旧段不会混淆
+pub(crate) const MAX_WINDOW_SIZE: u16 = 1024;
+
+/// 默认最大数据报长度下单个数据段允许的最大长度，
+/// 客户端或服务器设置了 `with_max_datagram_size` 时以 `ReliableChannel::max_segment_size` 为准
+pub const MAX_SEGMENT_SIZE: usize = DEFAULT_MAX_DATAGRAM_SIZE - CONTROL_OVERHEAD;
+
+/// 可靠通道参数：滑动窗口大小和重传定时器范围
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReliableConfig {
+    /// 未确认数据段的最大个数
+    pub window_size: u16,
+    /// 尚未测得 RTT 时使用的重传超时
+    pub initial_rto: Duration,
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+    /// 单个数据段的最大重传次数，超过后认为对端不可达
+    pub max_retransmits: u32,
+}
+
+impl Default for ReliableConfig {
+    fn default() -> Self {
+        ReliableConfig {
+            window_size: 32,
+            initial_rto: Duration::from_millis(200),
+            min_rto: Duration::from_millis(20),
+            max_rto: Duration::from_secs(2),
+            max_retransmits: 15,
+        }
+    }
+}
+
+/// 可靠通道统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReliableStats {
+    pub segments_sent: u64,
+    pub retransmissions: u64,
+    pub segments_received: u64,
+    pub duplicates: u64,
+    pub acks_sent: u64,
+    pub acks_received: u64,
+}
+
+// 已发送未确认的数据段
+struct Segment {
+    frame: Vec<u8>,
+    sent_at: Instant,
+    retransmits: u32,
+}
+
+// 按 RFC 6298 估计 RTT 并计算重传超时
+struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl RttEstimator {
+    fn new(config: &ReliableConfig) -> Self {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: config.initial_rto,
+        }
+    }
+
+    fn sample(&mut self, rtt: Duration, config: &ReliableConfig) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                self.rttvar = (self.rttvar * 3 + srtt.abs_diff(rtt)) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + self.rttvar * 4).clamp(config.min_rto, config.max_rto);
+    }
+
+    fn backoff(&mut self, config: &ReliableConfig) {
+        self.rto = (self.rto * 2).min(config.max_rto);
+    }
+}
+
+// 发送窗口：已发送未确认的数据段、RTT 估计和重传定时器，可靠通道和文件传输共用。
+// 序列号由调用方维护，segments[i] 对应调用方窗口起点之后的第 i 个数据段，None 表示已被选择确认
+pub(crate) struct SendWindow {
+    config: ReliableConfig,
+    rtt: RttEstimator,
+    segments: VecDeque<Option<Segment>>,
+}
+
+impl SendWindow {
+    pub(crate) fn new(mut config: ReliableConfig) -> Self {
+        config.window_size = config.window_size.clamp(1, MAX_WINDOW_SIZE);
+        SendWindow {
+            rtt: RttEstimator::new(&config),
+            config,
+            segments: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn config(&self) -> &ReliableConfig {
+        &self.config
+    }
+
+    // 窗口起点到最后一个已发送数据段的个数，包括已被选择确认的
+    pub(crate) fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.segments.len() >= self.config.window_size as usize
+    }
+
+    // 尚未确认的数据段个数
+    pub(crate) fn in_flight(&self) -> usize {
+        self.segments.iter().flatten().count()
+    }
+
+    pub(crate) fn srtt(&self) -> Option<Duration> {
+        self.rtt.srtt
+    }
+
+    pub(crate) fn rto(&self) -> Duration {
+        self.rtt.rto
+    }
+
+    // 记录在 sent_at 发出的数据段
+    pub(crate) fn push(&mut self, frame: Vec<u8>, sent_at: Instant) {
+        self.segments.push_back(Some(Segment {
+            frame,
+            sent_at,
+            retransmits: 0,
+        }));
+    }
+
+    // 累计确认窗口起点的 acked 个数据段，sack_bitmap 第 i 位确认之后的第 i + 1 个。
+    // acked 超出窗口说明是过期或乱序到达的确认，返回 false，调用方不应移动窗口起点
+    pub(crate) fn ack(&mut self, acked: usize, sack_bitmap: u64) -> bool {
+        if acked > self.segments.len() {
+            return false;
+        }
+
+        let now = Instant::now();
+        for _ in 0..acked {
+            if let Some(Some(segment)) = self.segments.pop_front() {
+                self.sample_rtt(&segment, now);
+            }
+        }
+        for i in 0..SACK_BITS {
+            if sack_bitmap & (1 << i) == 0 {
+                continue;
+            }
+            if let Some(segment) = self.segments.get_mut(i + 1).and_then(Option::take) {
+                self.sample_rtt(&segment, now);
+            }
+        }
+        true
+    }
+
+    // Karn 算法：重传过的数据段不参与 RTT 采样
+    fn sample_rtt(&mut self, segment: &Segment, now: Instant) {
+        if segment.retransmits == 0 {
+            self.rtt.sample(now.duration_since(segment.sent_at), &self.config);
+        }
+    }
+
+    // 最近一个数据段的重传时间，窗口为空时为 None
+    pub(crate) fn next_retransmit(&self) -> Option<Instant> {
+        self.segments
+            .iter()
+            .flatten()
+            .map(|segment| segment.sent_at + self.rtt.rto)
+            .min()
+    }
+
+    // 通过 resend 重发所有超时的数据段并退避 RTO，返回重发的个数；
+    // 某个数据段重传次数超过上限时返回 TimedOut
+    pub(crate) fn retransmit_expired<F>(&mut self, mut resend: F) -> io::Result<u64>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let rto = self.rtt.rto;
+        let mut retransmitted = 0;
+        for segment in self.segments.iter_mut().flatten() {
+            if segment.sent_at.elapsed() < rto {
+                continue;
+            }
+            if segment.retransmits >= self.config.max_retransmits {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Peer is not acknowledging"));
+            }
+            resend(&segment.frame)?;
+            segment.sent_at = Instant::now();
+            segment.retransmits += 1;
+            retransmitted += 1;
+        }
+        if retransmitted > 0 {
+            self.rtt.backoff(&self.config);
+        }
+        Ok(retransmitted)
+    }
+}
+
+// 接收端的选择确认位图：reorder[0] 是下一个待交付的位置，第 i 位表示 reorder[i + 1] 已收到
+pub(crate) fn sack_bitmap<T>(reorder: &VecDeque<Option<T>>) -> u64 {
+    let mut sack_bitmap = 0u64;
+    for (i, slot) in reorder.iter().skip(1).take(SACK_BITS).enumerate() {
+        if slot.is_some() {
+            sack_bitmap |= 1 << i;
+        }
+    }
+    sack_bitmap
+}
+
+/// 基于数据报传输的可靠有序消息通道
+///
+/// 发送端使用滑动窗口，接收端回复累计确认加选择确认位图，
+/// 未确认的数据段按测得的 RTT 计算超时并重传，接收端按序交付。
+/// 通道没有后台线程，收发和重传都在 `send`/`recv`/`flush`/`linger` 调用中驱动。
+pub struct ReliableChannel<T: DatagramTransport = UdpSocket> {
+    socket: T,
+    peer: T::Addr,
+    // 发送端：窗口中第 i 个数据段的序列号为 send_base + i
+    send_base: u16,
+    window: SendWindow,
+    // 接收端：reorder[i] 对应序列号 recv_base + i
+    recv_base: u16,
+    reorder: VecDeque<Option<Vec<u8>>>,
+    ready: VecDeque<Vec<u8>>,
+    stats: ReliableStats,
+    // 接收缓冲区，长度为客户端或服务器的最大数据报长度
+    buf: Vec<u8>,
+}
+
+impl<T: DatagramTransport> ReliableChannel<T> {
+    fn with_socket(socket: T, peer: T::Addr, config: ReliableConfig, max_datagram_size: usize) -> Self {
+        ReliableChannel {
+            socket,
+            peer,
+            send_base: 0,
+            window: SendWindow::new(config),
+            recv_base: 0,
+            reorder: VecDeque::new(),
+            ready: VecDeque::new(),
+            stats: ReliableStats::default(),
+            buf: vec![0; max_datagram_size],
+        }
+    }
+
+    // 使用客户端的 socket 建立到 peer 的可靠通道
+    pub fn connect(client: UdpClient<T>, peer: T::Addr, config: ReliableConfig) -> Self {
+        Self::with_socket(client.socket, peer, config, client.max_datagram_size)
+    }
+
+    // 在服务器 socket 上等待第一个数据段，并与发送方建立可靠通道
+    pub fn accept(server: UdpServer<T>, config: ReliableConfig, timeout: Duration) -> io::Result<Self> {
+        let socket = server.socket;
+        let deadline = Instant::now() + timeout;
+        let mut buf = vec![0; server.max_datagram_size];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "No peer connected"));
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let (num_bytes, src_addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Ok((_, ControlFrame::Data(_))) = ControlFrame::decode(&buf[..num_bytes]) {
+                let mut channel = Self::with_socket(socket, src_addr, config, server.max_datagram_size);
+                channel.handle_datagram(&buf[..num_bytes])?;
+                return Ok(channel);
+            }
+        }
+    }
+
+    pub fn peer_addr(&self) -> T::Addr {
+        self.peer.clone()
+    }
+
+    pub fn local_addr(&self) -> io::Result<T::Addr> {
+        self.socket.local_addr()
+    }
+
+    pub fn stats(&self) -> &ReliableStats {
+        &self.stats
+    }
+
+    // 平滑后的 RTT，尚未采样时为 None
+    pub fn srtt(&self) -> Option<Duration> {
+        self.window.srtt()
+    }
+
+    // 当前重传超时
+    pub fn rto(&self) -> Duration {
+        self.window.rto()
+    }
+
+    // 单条消息允许的最大长度，由最大数据报长度减去控制帧开销得到
+    pub fn max_segment_size(&self) -> usize {
+        self.buf.len().saturating_sub(CONTROL_OVERHEAD)
+    }
+
+    // 已发送但尚未确认的数据段个数
+    pub fn in_flight(&self) -> usize {
+        self.window.in_flight()
+    }
+
+    // 发送一条消息，窗口已满时阻塞直到收到确认
+    pub fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        if msg.len() > self.max_segment_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Message exceeds maximum segment size"));
+        }
+        while self.window.is_full() {
+            self.poll(self.window.rto())?;
+        }
+
+        let seq = self.send_base.wrapping_add(self.window.len() as u16);
+        let frame = ControlFrame::Data(msg.to_vec()).encode(seq);
+        self.socket.send_to(&frame, &self.peer)?;
+        self.stats.segments_sent += 1;
+        self.window.push(frame, Instant::now());
+        Ok(())
+    }
+
+    // 按序接收下一条消息，超时返回 TimedOut
+    pub fn recv(&mut self, timeout: Duration) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(msg) = self.ready.pop_front() {
+                return Ok(msg);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "No message received"));
+            }
+            self.poll(remaining)?;
+        }
+    }
+
+    // 等待所有已发送的数据段被确认
+    pub fn flush(&mut self, timeout: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        while !self.window.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Unacknowledged segments remain"));
+            }
+            self.poll(remaining)?;
+        }
+        Ok(())
+    }
+
+    // 在给定时间内继续处理收到的数据和确认，用于接收结束后应答对端的重传
+    pub fn linger(&mut self, duration: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + duration;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            self.poll(remaining)?;
+        }
+    }
+
+    // 等待一个数据报（不超过 max_wait 和最近的重传时间），处理后检查重传定时器
+    fn poll(&mut self, max_wait: Duration) -> io::Result<()> {
+        let mut wait = max_wait;
+        if let Some(next) = self.window.next_retransmit() {
+            wait = wait.min(next.saturating_duration_since(Instant::now()));
+        }
+        // 读超时不能为0
+        self.socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+
+        let mut buf = std::mem::take(&mut self.buf);
+        let result = match self.socket.recv_from(&mut buf) {
+            Ok((num_bytes, src_addr)) if src_addr == self.peer => self.handle_datagram(&buf[..num_bytes]),
+            Ok(_) => Ok(()),
+            Err(e) if is_timeout(&e) => Ok(()),
+            Err(e) => Err(e),
+        };
+        self.buf = buf;
+        result?;
+
+        let (socket, peer) = (&self.socket, &self.peer);
+        self.stats.retransmissions += self.window.retransmit_expired(|frame| socket.send_to(frame, peer).map(|_| ()))?;
+        Ok(())
+    }
+
+    fn handle_datagram(&mut self, data: &[u8]) -> io::Result<()> {
+        match ControlFrame::decode(data) {
+            Ok((seq, ControlFrame::Data(payload))) => self.on_data(seq, payload),
+            Ok((_, ControlFrame::Ack { cumulative, sack_bitmap })) => {
+                self.on_ack(cumulative, sack_bitmap);
+                Ok(())
+            }
+            // 无法解析的数据报直接丢弃
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn on_data(&mut self, seq: u16, payload: Vec<u8>) -> io::Result<()> {
+        self.stats.segments_received += 1;
+        let offset = seq.wrapping_sub(self.recv_base) as usize;
+        if offset < self.window.config().window_size as usize {
+            if self.reorder.len() <= offset {
+                self.reorder.resize_with(offset + 1, || None);
+            }
+            if self.reorder[offset].is_some() {
+                self.stats.duplicates += 1;
+            } else {
+                self.reorder[offset] = Some(payload);
+            }
+            // 交付连续到达的数据段
+            while let Some(Some(_)) = self.reorder.front() {
+                if let Some(Some(msg)) = self.reorder.pop_front() {
+                    self.ready.push_back(msg);
+                }
+                self.recv_base = self.recv_base.wrapping_add(1);
+            }
+        } else {
+            // 已经交付过的旧段，说明对端没有收到确认
+            self.stats.duplicates += 1;
+        }
+        self.send_ack()
+    }
+
+    fn send_ack(&mut self) -> io::Result<()> {
+        let ack = ControlFrame::Ack {
+            cumulative: self.recv_base,
+            sack_bitmap: sack_bitmap(&self.reorder),
+        };
+        self.socket.send_to(&ack.encode(self.recv_base), &self.peer)?;
+        self.stats.acks_sent += 1;
+        Ok(())
+    }
+
+    fn on_ack(&mut self, cumulative: u16, sack_bitmap: u64) {
+        self.stats.acks_received += 1;
+        if self.window.ack(cumulative.wrapping_sub(self.send_base) as usize, sack_bitmap) {
+            self.send_base = cumulative;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ImpairedTransport, ImpairmentConfig, MemoryNetwork};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    // 进程内的有损转发：按固定种子随机丢包，偶尔交换相邻两个数据报的顺序
+    struct LossyProxy {
+        addr: SocketAddr,
+        stop: Arc<AtomicBool>,
+        handle: Option<thread::JoinHandle<()>>,
+    }
+
+    impl LossyProxy {
+        fn start(target: SocketAddr, loss: f64, seed: u64) -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+            let addr = socket.local_addr().unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            let stop_flag = stop.clone();
+
+            let handle = thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut client: Option<SocketAddr> = None;
+                let mut held: Option<(Vec<u8>, SocketAddr)> = None;
+                let mut buf = [0u8; DEFAULT_MAX_DATAGRAM_SIZE];
+                while !stop_flag.load(Ordering::Relaxed) {
+                    let (n, src) = match socket.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(_) => continue,
+                    };
+                    let dst = if src == target {
+                        match client {
+                            Some(client) => client,
+                            None => continue,
+                        }
+                    } else {
+                        client = Some(src);
+                        target
+                    };
+                    if rng.gen_bool(loss) {
+                        continue;
+                    }
+                    if held.is_none() && rng.gen_bool(0.1) {
+                        held = Some((buf[..n].to_vec(), dst));
+                        continue;
+                    }
+                    socket.send_to(&buf[..n], dst).unwrap();
+                    if let Some((data, dst)) = held.take() {
+                        socket.send_to(&data, dst).unwrap();
+                    }
+                }
+            });
+
+            LossyProxy { addr, stop, handle: Some(handle) }
+        }
+    }
+
+    impl Drop for LossyProxy {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    #[test]
+    fn test_rtt_estimator() {
+        let config = ReliableConfig::default();
+        let mut rtt = RttEstimator::new(&config);
+        assert_eq!(rtt.rto, config.initial_rto);
+
+        rtt.sample(Duration::from_millis(100), &config);
+        assert_eq!(rtt.srtt, Some(Duration::from_millis(100)));
+        assert_eq!(rtt.rto, Duration::from_millis(300));
+
+        rtt.backoff(&config);
+        assert_eq!(rtt.rto, Duration::from_millis(600));
+
+        // 很小的 RTT 会被限制在 min_rto
+        let mut rtt = RttEstimator::new(&config);
+        rtt.sample(Duration::from_micros(10), &config);
+        assert_eq!(rtt.rto, config.min_rto);
+    }
+
+    #[test]
+    fn test_send_window_ack() {
+        let mut window = SendWindow::new(ReliableConfig {
+            window_size: 3,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        (0..3).for_each(|i| window.push(vec![i], now));
+        assert!(window.is_full());
+
+        // 选择确认第 1 个，窗口起点不动
+        assert!(window.ack(0, 0b1));
+        assert_eq!((window.len(), window.in_flight()), (3, 2));
+        // 超出窗口的确认被忽略
+        assert!(!window.ack(4, 0));
+        assert!(window.ack(2, 0));
+        assert_eq!((window.len(), window.in_flight()), (1, 1));
+        assert!(window.srtt().is_some());
+
+        // 超时的数据段被重发，RTO 退避
+        let rto = window.rto();
+        let mut resent = Vec::new();
+        thread::sleep(rto);
+        let retransmitted = window.retransmit_expired(|frame| {
+            resent.push(frame.to_vec());
+            Ok(())
+        });
+        assert_eq!(retransmitted.unwrap(), 1);
+        assert_eq!(resent, [vec![2]]);
+        assert!(window.rto() > rto);
+    }
+
+    // 接收端持续应答重传直到发送端刷新完成，固定的等待时间在负载高时可能不够
+    fn linger_until<T: DatagramTransport>(channel: &mut ReliableChannel<T>, done: &AtomicBool) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !done.load(Ordering::Relaxed) && Instant::now() < deadline {
+            channel.linger(Duration::from_millis(50)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reliable_in_order_over_lossy_link() {
+        let server = UdpServer::bind(0).unwrap();
+        let server_port = server.socket.local_addr().unwrap().port();
+        let proxy = LossyProxy::start(SocketAddr::from(([127, 0, 0, 1], server_port)), 0.2, 7);
+        // 本机回环上限制退避后的超时，避免尾部丢包在负载高时拖过刷新时限
+        let config = ReliableConfig {
+            window_size: 16,
+            max_rto: Duration::from_millis(500),
+            ..Default::default()
+        };
+
+        let receiver_config = config.clone();
+        let done = Arc::new(AtomicBool::new(false));
+        let flushed = done.clone();
+        let receiver = thread::spawn(move || {
+            let mut channel = ReliableChannel::accept(server, receiver_config, Duration::from_secs(5)).unwrap();
+            let mut messages = Vec::new();
+            for _ in 0..200 {
+                messages.push(channel.recv(Duration::from_secs(10)).unwrap());
+            }
+            // 继续应答发送端对最后几个数据段的重传
+            linger_until(&mut channel, &flushed);
+            messages
+        });
+
+        let client = UdpClient::new().unwrap();
+        let mut channel = ReliableChannel::connect(client, proxy.addr, config);
+        for i in 0..200 {
+            channel.send(format!("segment-{}", i).as_bytes()).unwrap();
+        }
+        let flushed = channel.flush(Duration::from_secs(20));
+        done.store(true, Ordering::Relaxed);
+        flushed.unwrap();
+
+        assert!(channel.stats().retransmissions > 0);
+        assert!(channel.srtt().is_some());
+        assert_eq!(channel.in_flight(), 0);
+
+        let messages = receiver.join().unwrap();
+        for (i, msg) in messages.iter().enumerate() {
+            assert_eq!(msg, format!("segment-{}", i).as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_reliable_over_impaired_memory_transport() {
+        let network = MemoryNetwork::new();
+        let server_socket = network.bind();
+        let server_addr = server_socket.addr();
+        let lossy = |seed| ImpairmentConfig::new().with_loss(0.2).with_seed(seed);
+        let server = UdpServer::from_socket(ImpairedTransport::new(server_socket, lossy(1)).unwrap());
+        let client = UdpClient::from_socket(ImpairedTransport::new(network.bind(), lossy(2)).unwrap());
+
+        let done = Arc::new(AtomicBool::new(false));
+        let flushed = done.clone();
+        let receiver = thread::spawn(move || {
+            let mut channel = ReliableChannel::accept(server, ReliableConfig::default(), Duration::from_secs(5)).unwrap();
+            let messages: Vec<_> = (0..100).map(|_| channel.recv(Duration::from_secs(10)).unwrap()).collect();
+            linger_until(&mut channel, &flushed);
+            messages
+        });
+
+        let mut channel = ReliableChannel::connect(client, server_addr, ReliableConfig::default());
+        for i in 0..100u32 {
+            channel.send(&i.to_le_bytes()).unwrap();
+        }
+        let flushed = channel.flush(Duration::from_secs(10));
+        done.store(true, Ordering::Relaxed);
+        flushed.unwrap();
+        assert!(channel.stats().retransmissions > 0);
+
+        let messages = receiver.join().unwrap();
+        assert!(messages.iter().enumerate().all(|(i, msg)| msg[..] == (i as u32).to_le_bytes()));
+    }
+
+    #[test]
+    fn test_oversized_message_rejected() {
+        let client = UdpClient::new().unwrap();
+        let mut channel = ReliableChannel::connect(client, "127.0.0.1:9".parse().unwrap(), ReliableConfig::default());
+        let err = channel.send(&vec![0u8; MAX_SEGMENT_SIZE + 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // 段长上限跟随客户端的最大数据报长度
+        let client = UdpClient::new().unwrap().with_max_datagram_size(1024);
+        let mut channel = ReliableChannel::connect(client, "127.0.0.1:9".parse().unwrap(), ReliableConfig::default());
+        assert_eq!(channel.max_segment_size(), 1024 - CONTROL_OVERHEAD);
+        let err = channel.send(&[0u8; 1024 - CONTROL_OVERHEAD + 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_max_size_segment_delivered() {
+        let server = UdpServer::bind(0).unwrap();
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server.socket.local_addr().unwrap().port()));
+        let receiver = thread::spawn(move || {
+            let mut channel = ReliableChannel::accept(server, ReliableConfig::default(), Duration::from_secs(5)).unwrap();
+            let msg = channel.recv(Duration::from_secs(5)).unwrap();
+            channel.linger(Duration::from_millis(200)).unwrap();
+            msg
+        });
+
+        let mut channel = ReliableChannel::connect(UdpClient::new().unwrap(), server_addr, ReliableConfig::default());
+        let msg: Vec<u8> = (0..MAX_SEGMENT_SIZE).map(|i| i as u8).collect();
+        channel.send(&msg).unwrap();
+        channel.flush(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(receiver.join().unwrap(), msg);
+        assert_eq!(channel.stats().retransmissions, 0);
+    }
+}
@@ -0,0 +1,258 @@
+// retry.rs
+use crate::UdpClient;
+use crate::transport::DatagramTransport;
+use rand::Rng;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// 重传策略：最大尝试次数、初始超时、指数退避（带抖动）以及总截止时间
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（包含第一次发送），至少为1
+    pub max_attempts: u32,
+    /// 第一次发送后等待回包的超时
+    pub initial_timeout: Duration,
+    /// 每次重传后超时的放大倍数
+    pub backoff_factor: f64,
+    /// 单次等待超时的上限
+    pub max_timeout: Duration,
+    /// 抖动比例（0.0 ~ 1.0），实际超时在 timeout * (1 ± jitter) 之间随机
+    pub jitter: f64,
+    /// 整个请求的总截止时间，None 表示只受尝试次数限制
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_timeout: Duration::from_millis(200),
+            backoff_factor: 2.0,
+            max_timeout: Duration::from_secs(2),
+            jitter: 0.1,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_timeout: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            initial_timeout,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff_factor: f64, max_timeout: Duration) -> Self {
+        self.backoff_factor = backoff_factor;
+        self.max_timeout = max_timeout;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// 第 attempt 次（从1开始）发送后的基础超时，不含抖动
+    pub fn base_timeout(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31) as i32;
+        let secs = self.initial_timeout.as_secs_f64() * self.backoff_factor.max(1.0).powi(exp);
+        Duration::from_secs_f64(secs.min(self.max_timeout.as_secs_f64()))
+    }
+
+    /// 第 attempt 次发送后的实际超时，叠加随机抖动
+    pub fn timeout_for_attempt(&self, attempt: u32) -> Duration {
+        let base = self.base_timeout(attempt);
+        if self.jitter <= 0.0 {
+            return base;
+        }
+        let factor = 1.0 + rand::thread_rng().gen_range(-self.jitter..=self.jitter);
+        base.mul_f64(factor.max(0.0))
+    }
+}
+
+/// 带重传的请求结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryResponse<A = SocketAddr> {
+    pub data: Vec<u8>,
+    pub src_addr: A,
+    /// 实际发送次数
+    pub attempts: u32,
+    /// 往返时间，只在第一次发送就收到回包时给出。
+    /// 重传过的请求无法确定回包对应哪一次发送，按 Karn 算法不给出
+    pub rtt: Option<Duration>,
+}
+
+// 读超时在 Linux 上表现为 WouldBlock，在 Windows 上表现为 TimedOut
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+impl<T: DatagramTransport> UdpClient<T> {
+    // 发送消息并等待 addr 的回包，超时后按策略重传同一帧（序列号不变，便于设备端去重）。
+    // 其他地址发来的数据报被忽略
+    pub fn send_and_receive_with_retry(
+        &self,
+        addr: T::Addr,
+        msg: &[u8],
+        policy: &RetryPolicy,
+    ) -> io::Result<RetryResponse<T::Addr>> {
+        let start = Instant::now();
+        let max_attempts = policy.max_attempts.max(1);
+        let mut buf = vec![0; self.max_datagram_size()];
+
+        for attempt in 1..=max_attempts {
+            let mut timeout = policy.timeout_for_attempt(attempt);
+            if let Some(deadline) = policy.deadline {
+                let remaining = deadline.saturating_sub(start.elapsed());
+                if remaining.is_zero() {
+                    break;
+                }
+                timeout = timeout.min(remaining);
+            }
+
+            let sent_at = self.send_timed(addr.clone(), msg)?;
+            let attempt_deadline = sent_at + timeout;
+            loop {
+                let remaining = attempt_deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    tracing::debug!(peer = ?addr, attempt, ?timeout, "no reply within timeout");
+                    break;
+                }
+                self.socket.set_read_timeout(Some(remaining))?;
+                match self.socket.recv_from_checked(&mut buf) {
+                    Ok((num_bytes, src_addr)) if src_addr == addr => {
+                        let rtt = (attempt == 1).then(|| sent_at.elapsed());
+                        self.record_reply(num_bytes, rtt);
+                        return Ok(RetryResponse {
+                            data: buf[..num_bytes].to_vec(),
+                            src_addr,
+                            attempts: attempt,
+                            rtt,
+                        });
+                    }
+                    Ok((_, src_addr)) => tracing::trace!(src = ?src_addr, "ignoring datagram from another address"),
+                    Err(e) if is_timeout(&e) => {}
+                    Err(e) => {
+                        self.record_error(&e);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("No response from {:?} after {:?}", addr, start.elapsed()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    #[test]
+    fn test_backoff_timeouts() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100))
+            .with_backoff(2.0, Duration::from_millis(500))
+            .with_jitter(0.0);
+
+        assert_eq!(policy.timeout_for_attempt(1), Duration::from_millis(100));
+        assert_eq!(policy.timeout_for_attempt(2), Duration::from_millis(200));
+        assert_eq!(policy.timeout_for_attempt(3), Duration::from_millis(400));
+        // 超过上限后保持不变
+        assert_eq!(policy.timeout_for_attempt(4), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_jitter_within_bounds() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(100)).with_jitter(0.5);
+        for _ in 0..100 {
+            let t = policy.timeout_for_attempt(1);
+            assert!(t >= Duration::from_millis(50) && t <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_retry_until_reply() {
+        // 模拟丢包：服务器丢弃前两次收到的帧，第三次才回复
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let mut frames = Vec::new();
+            for _ in 0..3 {
+                let (n, src) = server.recv_from(&mut buf).unwrap();
+                frames.push(buf[..n].to_vec());
+                if frames.len() == 3 {
+                    server.send_to(b"ack", src).unwrap();
+                }
+            }
+            frames
+        });
+
+        let client = UdpClient::new().unwrap();
+        let policy = RetryPolicy::new(5, Duration::from_millis(50)).with_jitter(0.0);
+        let resp = client.send_and_receive_with_retry(server_addr, b"frame-seq-7", &policy).unwrap();
+
+        assert_eq!(resp.data, b"ack");
+        assert_eq!(resp.attempts, 3);
+        assert_eq!(resp.src_addr, server_addr);
+        // 重传过的请求不给出 RTT
+        assert_eq!(resp.rtt, None);
+
+        // 每次重传的内容完全一致
+        let frames = handle.join().unwrap();
+        assert!(frames.iter().all(|f| f == b"frame-seq-7"));
+    }
+
+    #[test]
+    fn test_reply_from_other_address_ignored() {
+        // 回包之前先有其他地址的数据报到达客户端
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, src) = server.recv_from(&mut buf).unwrap();
+            UdpSocket::bind("127.0.0.1:0").unwrap().send_to(b"stray", src).unwrap();
+            server.send_to(b"ack", src).unwrap();
+        });
+
+        let client = UdpClient::new().unwrap();
+        let policy = RetryPolicy::new(3, Duration::from_secs(1)).with_jitter(0.0);
+        let resp = client.send_and_receive_with_retry(server_addr, b"ping", &policy).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(resp.data, b"ack");
+        assert_eq!((resp.src_addr, resp.attempts), (server_addr, 1));
+        assert!(resp.rtt.is_some());
+    }
+
+    #[test]
+    fn test_retry_deadline() {
+        // 没有任何回包时，总截止时间先于尝试次数生效
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = UdpClient::new().unwrap();
+        let policy = RetryPolicy::new(100, Duration::from_millis(50))
+            .with_jitter(0.0)
+            .with_deadline(Duration::from_millis(300));
+
+        let start = Instant::now();
+        let err = client
+            .send_and_receive_with_retry(silent.local_addr().unwrap(), b"ping", &policy)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
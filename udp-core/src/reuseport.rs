@@ -0,0 +1,189 @@
+// reuseport.rs
+// 仅 Linux：多个 socket 通过 SO_REUSEPORT 绑定同一端口，由内核按四元组哈希分配数据报
+use crate::pool::ReplySender;
+use crate::queue::OverflowPolicy;
+use crate::stats::ServerStats;
+use crate::{Registry, ServerHandle, UdpServer};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+
+/// 多 socket 服务器，每个 socket 有独立的接收线程、处理线程和处理函数实例
+pub struct ReusePortServer {
+    servers: Vec<UdpServer>,
+}
+
+// 创建一个开启 SO_REUSEPORT 的 UDP socket 并绑定
+fn bind_reuseport(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&SockAddr::from(addr))?;
+    Ok(socket.into())
+}
+
+impl ReusePortServer {
+    // 在同一端口上打开 sockets 个 socket，端口为0时由第一个 socket 分配
+    pub fn bind(port: u16, sockets: usize) -> io::Result<Self> {
+        let first = bind_reuseport(SocketAddr::from(([0, 0, 0, 0], port)))?;
+        let addr = first.local_addr()?;
+        let mut servers = vec![UdpServer::from_socket(first)];
+        for _ in 1..sockets.max(1) {
+            servers.push(UdpServer::from_socket(bind_reuseport(addr)?));
+        }
+        Ok(ReusePortServer { servers })
+    }
+
+    pub fn socket_count(&self) -> usize {
+        self.servers.len()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.servers[0].socket.local_addr()
+    }
+
+    // 每个 socket 使用相同的接收队列配置
+    pub fn with_queue(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.servers = self
+            .servers
+            .into_iter()
+            .map(|server| server.with_queue(capacity, policy))
+            .collect();
+        self
+    }
+
+    // 所有 socket 共用同一个指标注册表
+    pub fn with_metrics(mut self, registry: Arc<Registry>) -> Self {
+        self.servers = self.servers.into_iter().map(|server| server.with_metrics(registry.clone())).collect();
+        self
+    }
+
+    // 设置接收错误回调，第一个参数为 socket 序号
+    pub fn on_error<E>(mut self, handler: E) -> Self
+    where
+        E: Fn(usize, io::Error) + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.servers = self
+            .servers
+            .into_iter()
+            .enumerate()
+            .map(|(index, server)| {
+                let handler = handler.clone();
+                server.on_error(move |e| handler(index, e))
+            })
+            .collect();
+        self
+    }
+
+    // 启动所有 socket，make_handler 按 socket 序号为每个 socket 创建独立的处理函数
+    pub fn start<M, F>(self, mut make_handler: M) -> io::Result<MultiServerHandle>
+    where
+        M: FnMut(usize) -> F,
+        F: FnMut(SocketAddr, &[u8], &ReplySender) + Send + 'static,
+    {
+        let mut handles = Vec::with_capacity(self.servers.len());
+        for (index, server) in self.servers.into_iter().enumerate() {
+            let reply_socket = server.socket.try_clone()?;
+            let auto_dscp = server.auto_dscp;
+            let metrics = server.metrics.clone();
+            let mut handler = make_handler(index);
+            let handle = server.start_async(move |src_addr, data| {
+                let reply = ReplySender::new(&reply_socket, src_addr, auto_dscp, metrics.as_ref());
+                handler(src_addr, data, &reply);
+            });
+            match handle {
+                Ok(handle) => handles.push(handle),
+                Err(e) => {
+                    // 启动失败时停掉已经启动的 socket
+                    for handle in handles {
+                        let _ = handle.shutdown();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(MultiServerHandle { handles })
+    }
+}
+
+/// 多 socket 服务器的运行句柄
+pub struct MultiServerHandle {
+    handles: Vec<ServerHandle>,
+}
+
+impl MultiServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.handles[0].local_addr()
+    }
+
+    // 按 socket 序号排列的统计
+    pub fn stats(&self) -> Vec<Arc<ServerStats>> {
+        self.handles.iter().map(|handle| handle.stats()).collect()
+    }
+
+    // 停止所有 socket 并等待线程退出
+    pub fn shutdown(self) -> thread::Result<()> {
+        let mut result = Ok(());
+        for handle in self.handles {
+            if let Err(e) = handle.shutdown() {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    pub fn join(self) -> thread::Result<()> {
+        let mut result = Ok(());
+        for handle in self.handles {
+            if let Err(e) = handle.join() {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UdpClient;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reuseport_echo_across_sockets() {
+        let server = ReusePortServer::bind(0, 4).unwrap();
+        assert_eq!(server.socket_count(), 4);
+
+        let handle = server
+            .start(|_index| {
+                |_src_addr: SocketAddr, data: &[u8], reply: &ReplySender| {
+                    reply.send(data).unwrap();
+                }
+            })
+            .unwrap();
+        let addr: SocketAddr = ([127, 0, 0, 1], handle.local_addr().port()).into();
+
+        for i in 0..16 {
+            let client = UdpClient::new().unwrap();
+            let msg = format!("client-{}", i);
+            let response = client.send_and_receive(addr, msg.as_bytes(), Duration::from_secs(1)).unwrap();
+            assert_eq!(response, msg.as_bytes());
+        }
+
+        let stats = handle.stats();
+        handle.shutdown().unwrap();
+
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats.iter().map(|s| s.processed()).sum::<u64>(), 16);
+    }
+
+    #[test]
+    fn test_plain_bind_conflicts_without_reuseport() {
+        let server = ReusePortServer::bind(0, 2).unwrap();
+        let port = server.local_addr().unwrap().port();
+        // 未开启 SO_REUSEPORT 的 socket 不能加入同一端口
+        assert!(UdpServer::bind(port).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+// serial.rs
+// 串口传输（仅 Linux）：以原始模式打开 tty，用流式解码器从字节流中切出 0x55 0xBB 帧，
+// 每个完整帧作为一个数据报交给上层，UdpClient / UdpServer 等无需修改即可在串口上使用。
+// 串口是点对点链路，地址即设备路径：发送时忽略目标地址，接收到的帧的来源地址为本端设备路径。
+use crate::error::TruncatedDatagram;
+use crate::transport::DatagramTransport;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use udp_protocol::FrameDecoder;
+
+// 单次读取的字节数
+const READ_CHUNK: usize = 4096;
+
+/// 校验位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// 停止位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// 串口参数，数据位固定为 8 位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig::new(115200)
+    }
+}
+
+impl SerialConfig {
+    // 默认无校验、1 位停止位
+    pub fn new(baud_rate: u32) -> Self {
+        SerialConfig {
+            baud_rate,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+
+    pub fn with_parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn with_stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    // 设置为原始模式并应用波特率、校验位和停止位
+    fn apply(&self, file: &File) -> io::Result<()> {
+        let speed = baud_constant(self.baud_rate)?;
+        let fd = file.as_raw_fd();
+        // SAFETY: termios 是纯数据结构，全零是合法的初始值，随后由 tcgetattr 填写
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: fd 来自调用期间存活的 file，termios 是有效的可写指针
+        if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: termios 已由 tcgetattr 初始化
+        unsafe { libc::cfmakeraw(&mut termios) };
+        termios.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB | libc::CRTSCTS);
+        termios.c_cflag |= libc::CS8 | libc::CLOCAL | libc::CREAD;
+        match self.parity {
+            Parity::None => {}
+            Parity::Even => termios.c_cflag |= libc::PARENB,
+            Parity::Odd => termios.c_cflag |= libc::PARENB | libc::PARODD,
+        }
+        if self.stop_bits == StopBits::Two {
+            termios.c_cflag |= libc::CSTOPB;
+        }
+        // 读取由 poll 控制超时，read 只取走已到达的字节
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        // SAFETY: termios 是有效的可写指针，speed 来自 baud_constant 的合法取值
+        if unsafe { libc::cfsetispeed(&mut termios, speed) != 0 || libc::cfsetospeed(&mut termios, speed) != 0 } {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd 仍然有效，termios 在调用期间只被读取
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn baud_constant(baud_rate: u32) -> io::Result<libc::speed_t> {
+    Ok(match baud_rate {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        1500000 => libc::B1500000,
+        2000000 => libc::B2000000,
+        3000000 => libc::B3000000,
+        4000000 => libc::B4000000,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported baud rate {}", baud_rate),
+            ));
+        }
+    })
+}
+
+struct SerialPort {
+    file: File,
+    path: PathBuf,
+    // 接收时持有，同一时刻只有一个线程从字节流中取帧
+    decoder: Mutex<FrameDecoder>,
+    // 保证每帧的字节连续写出，不和其他线程的帧交错
+    write_lock: Mutex<()>,
+    read_timeout: Mutex<Option<Duration>>,
+    nonblocking: AtomicBool,
+}
+
+/// 串口传输，克隆出的句柄共享同一个设备和解码缓冲区
+#[derive(Clone)]
+pub struct SerialTransport {
+    port: Arc<SerialPort>,
+}
+
+impl SerialTransport {
+    // 打开串口设备并按 config 配置
+    pub fn open(path: impl AsRef<Path>, config: &SerialConfig) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+        config.apply(&file)?;
+        Ok(Self::from_file(file, path))
+    }
+
+    // 使用已经打开并配置好的设备
+    pub fn from_file(file: File, path: impl Into<PathBuf>) -> Self {
+        SerialTransport {
+            port: Arc::new(SerialPort {
+                file,
+                path: path.into(),
+                decoder: Mutex::new(FrameDecoder::new()),
+                write_lock: Mutex::new(()),
+                read_timeout: Mutex::new(None),
+                nonblocking: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.port.path
+    }
+
+    // 重新同步时丢弃的噪声字节数
+    pub fn discarded(&self) -> u64 {
+        self.port.decoder.lock().unwrap().discarded()
+    }
+
+    // 读取下一个完整帧，遵循读超时和阻塞模式
+    fn recv_frame(&self) -> io::Result<Vec<u8>> {
+        let mut decoder = self.port.decoder.lock().unwrap();
+        let deadline = self.port.read_timeout.lock().unwrap().map(|timeout| Instant::now() + timeout);
+        let mut chunk = [0; READ_CHUNK];
+        loop {
+            if let Some(frame) = decoder.next_frame() {
+                return Ok(frame);
+            }
+            let wait = if self.port.nonblocking.load(Ordering::Relaxed) {
+                Some(Duration::ZERO)
+            } else {
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+            };
+            if !poll_readable(&self.port.file, wait)? {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "Receive timed out"));
+            }
+            match (&self.port.file).read(&mut chunk) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Serial device closed")),
+                Ok(n) => decoder.push(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// 等待设备可读，超时返回 false；wait 为 None 时一直等待
+fn poll_readable(file: &File, wait: Option<Duration>) -> io::Result<bool> {
+    // 向上取整到毫秒，避免剩余不足 1 毫秒时空转
+    let timeout_ms = wait.map_or(-1, |wait| wait.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32);
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        // SAFETY: pollfd 是本函数内存活的单个元素，fd 来自调用期间存活的 file
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret >= 0 {
+            return Ok(ret > 0);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+impl DatagramTransport for SerialTransport {
+    type Addr = PathBuf;
+
+    // 点对点链路，目标地址不参与发送
+    fn send_to(&self, buf: &[u8], _addr: &PathBuf) -> io::Result<usize> {
+        let _guard = self.port.write_lock.lock().unwrap();
+        (&self.port.file).write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, PathBuf)> {
+        let frame = self.recv_frame()?;
+        let len = frame.len().min(buf.len());
+        buf[..len].copy_from_slice(&frame[..len]);
+        Ok((len, self.port.path.clone()))
+    }
+
+    fn local_addr(&self) -> io::Result<PathBuf> {
+        Ok(self.port.path.clone())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        if timeout.is_some_and(|t| t.is_zero()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Zero duration is not a valid timeout"));
+        }
+        *self.port.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.port.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+
+    // 帧长度总是已知的
+    fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, PathBuf)> {
+        let frame = self.recv_frame()?;
+        if frame.len() > buf.len() {
+            return Err(TruncatedDatagram { len: Some(frame.len()), max: buf.len() }.into());
+        }
+        buf[..frame.len()].copy_from_slice(&frame);
+        Ok((frame.len(), self.port.path.clone()))
+    }
+}
+
+// 创建伪终端对，返回主设备端和按 config 配置的从设备端，用于在没有串口硬件时测试
+pub fn pty_pair(config: &SerialConfig) -> io::Result<(SerialTransport, SerialTransport)> {
+    // SAFETY: posix_openpt 只接收标志位，不涉及指针
+    let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd 是刚打开且没有其他所有者的描述符，交给 File 负责关闭
+    let master = unsafe { File::from_raw_fd(fd) };
+    // SAFETY: master 持有的描述符在调用期间有效
+    if unsafe { libc::grantpt(master.as_raw_fd()) != 0 || libc::unlockpt(master.as_raw_fd()) != 0 } {
+        return Err(io::Error::last_os_error());
+    }
+    let mut name = [0 as libc::c_char; 128];
+    // SAFETY: name 是可写的缓冲区，传入的长度与其大小一致
+    let ret = unsafe { libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    // SAFETY: ptsname_r 成功时 name 中是以 NUL 结尾的字符串
+    let slave_path = PathBuf::from(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned());
+    let slave = SerialTransport::open(&slave_path, config)?;
+    Ok((SerialTransport::from_file(master, "/dev/ptmx"), slave))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceClient, FrameServer, Route, UdpClient, UdpServer, is_truncated};
+    use udp_protocol::{DeviceType, ProtocolBody, RequestBodyType, TlvProtocol};
+
+    #[test]
+    fn test_config_applied() {
+        let config = SerialConfig::new(9600).with_parity(Parity::Odd).with_stop_bits(StopBits::Two);
+        let (_master, slave) = pty_pair(&config).unwrap();
+        // SAFETY: termios 全零是合法的初始值
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: slave 持有的描述符在调用期间有效，termios 是有效的可写指针
+        assert_eq!(unsafe { libc::tcgetattr(slave.port.file.as_raw_fd(), &mut termios) }, 0);
+        // SAFETY: termios 已由 tcgetattr 填写
+        assert_eq!(unsafe { libc::cfgetospeed(&termios) }, libc::B9600);
+        // 伪终端驱动总是清除 PARENB，这里只能检查其余的位
+        assert_eq!(termios.c_cflag & (libc::PARODD | libc::CSTOPB), libc::PARODD | libc::CSTOPB);
+        assert_eq!(termios.c_cflag & libc::CSIZE, libc::CS8);
+
+        let err = SerialTransport::open(slave.path(), &SerialConfig::new(12345)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_frames_over_pty_with_noise() {
+        let (master, slave) = pty_pair(&SerialConfig::default()).unwrap();
+        let frame = udp_protocol::encapsulate_data(
+            udp_protocol::FrameType::Type0,
+            udp_protocol::Priority::Medium,
+            udp_protocol::CheckType::CheckSum,
+            udp_protocol::ReqRsp::Request,
+            DeviceType::MCU,
+            1,
+            RequestBodyType::TlvProtocol,
+            [0; 8],
+            0x10,
+            0,
+            vec![0xAB; 300],
+        );
+        let mut stream = vec![0x00, 0x55, 0xFF];
+        stream.extend(&frame);
+        stream.extend(&frame);
+        master.send_to(&stream, &PathBuf::new()).unwrap();
+
+        slave.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let mut buf = [0; 1024];
+        for _ in 0..2 {
+            let (n, src_addr) = slave.recv_from_checked(&mut buf).unwrap();
+            assert_eq!(&buf[..n], &frame[..]);
+            assert_eq!(src_addr, slave.path());
+        }
+        assert_eq!(slave.discarded(), 3);
+
+        master.send_to(&frame, &PathBuf::new()).unwrap();
+        assert!(is_truncated(&slave.recv_from_checked(&mut buf[..64]).unwrap_err()));
+        let err = slave.recv_from(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_device_client_over_pty() {
+        let (master, slave) = pty_pair(&SerialConfig::new(921600)).unwrap();
+        // 从设备端模拟 MCU，主设备端作为上位机
+        let handle = FrameServer::new(UdpServer::from_socket(slave))
+            .route(Route::new(RequestBodyType::TlvProtocol).device_type(DeviceType::MCU), |req| {
+                let ProtocolBody::Tlv(tlv) = &req.body else { unreachable!() };
+                Some(ProtocolBody::Tlv(TlvProtocol::new(tlv.command_code, 0, tlv.user_data.iter().rev().copied().collect())))
+            })
+            .start()
+            .unwrap();
+
+        let target = master.path().to_path_buf();
+        let device = DeviceClient::new(UdpClient::from_socket(master), target, DeviceType::MCU, 0);
+        assert_eq!(device.command(0x20, b"uart").unwrap(), b"trau");
+        assert_eq!(device.command(0x21, &[1, 2, 3]).unwrap(), [3, 2, 1]);
+
+        let stats = handle.frame_stats();
+        handle.shutdown().unwrap();
+        assert_eq!(stats.replied(), 2);
+    }
+}
@@ -0,0 +1,395 @@
+// sockopt.rs
+// socket 调优选项：收发缓冲区、DSCP、TTL、绑定网卡和路径 MTU 发现。
+// 开启 auto_dscp 后按 Layer1 帧头中的优先级逐包设置 DSCP，高优先级控制帧可以被网络设备优先转发。
+use crate::{UdpClient, UdpServer};
+use socket2::SockRef;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use udp_protocol::types::Priority;
+
+/// 加速转发（EF），用于高优先级帧
+pub const DSCP_EXPEDITED: u8 = 46;
+/// 确保转发 AF31，用于中优先级帧
+pub const DSCP_ASSURED: u8 = 26;
+/// 尽力而为，用于低优先级帧
+pub const DSCP_BEST_EFFORT: u8 = 0;
+
+/// 路径 MTU 发现模式（IP_MTU_DISCOVER），仅 Linux 支持
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmtuDiscovery {
+    /// 不设置 DF 位，超过 MTU 时由路径上的设备分片
+    Dont,
+    /// 按路由缓存的 PMTU 决定是否分片
+    Want,
+    /// 总是设置 DF 位，超过 PMTU 的发送返回 EMSGSIZE
+    Do,
+    /// 设置 DF 位但忽略 PMTU，用于探测
+    Probe,
+}
+
+/// socket 选项，None 表示保持系统默认值
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// SO_RCVBUF，内核实际分配的大小可能不同
+    pub recv_buffer_size: Option<usize>,
+    /// SO_SNDBUF
+    pub send_buffer_size: Option<usize>,
+    /// 默认的 DSCP（0 ~ 63），写入 IP_TOS / IPV6_TCLASS 的高6位
+    pub dscp: Option<u8>,
+    /// IP_TTL / IPV6_UNICAST_HOPS
+    pub ttl: Option<u32>,
+    /// SO_BINDTODEVICE 绑定的网卡名，仅 Linux 支持
+    pub bind_device: Option<String>,
+    /// IP_MTU_DISCOVER，仅 Linux 支持
+    pub pmtu_discovery: Option<PmtuDiscovery>,
+    /// 发送 Layer1 帧时按帧头优先级逐包设置 DSCP，其他数据使用 dscp
+    pub auto_dscp: bool,
+}
+
+impl SocketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_buffer_sizes(mut self, recv: usize, send: usize) -> Self {
+        self.recv_buffer_size = Some(recv);
+        self.send_buffer_size = Some(send);
+        self
+    }
+
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_bind_device(mut self, device: impl Into<String>) -> Self {
+        self.bind_device = Some(device.into());
+        self
+    }
+
+    pub fn with_pmtu_discovery(mut self, mode: PmtuDiscovery) -> Self {
+        self.pmtu_discovery = Some(mode);
+        self
+    }
+
+    pub fn with_auto_dscp(mut self) -> Self {
+        self.auto_dscp = true;
+        self
+    }
+
+    // 把选项应用到 socket，遇到第一个失败的选项时返回错误
+    pub fn apply(&self, socket: &UdpSocket) -> io::Result<()> {
+        let sock = SockRef::from(socket);
+        let is_ipv6 = socket.local_addr()?.is_ipv6();
+
+        if let Some(size) = self.recv_buffer_size {
+            sock.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            sock.set_send_buffer_size(size)?;
+        }
+        if let Some(dscp) = self.dscp {
+            let tos = dscp_to_tos(dscp)?;
+            if is_ipv6 {
+                sys::set_tclass_v6(&sock, tos)?;
+            } else {
+                sock.set_tos_v4(tos)?;
+            }
+        }
+        if let Some(ttl) = self.ttl {
+            if is_ipv6 {
+                sock.set_unicast_hops_v6(ttl)?;
+            } else {
+                sock.set_ttl_v4(ttl)?;
+            }
+        }
+        if let Some(device) = &self.bind_device {
+            sys::bind_device(&sock, device)?;
+        }
+        if let Some(mode) = self.pmtu_discovery {
+            sys::set_pmtu_discovery(socket, mode, is_ipv6)?;
+        }
+        Ok(())
+    }
+}
+
+// DSCP 占 TOS 字节的高6位
+fn dscp_to_tos(dscp: u8) -> io::Result<u32> {
+    if dscp > 63 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("DSCP {} out of range 0-63", dscp)));
+    }
+    Ok((dscp as u32) << 2)
+}
+
+// 帧优先级对应的 DSCP
+pub fn dscp_for_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::High => DSCP_EXPEDITED,
+        Priority::Medium => DSCP_ASSURED,
+        Priority::Low => DSCP_BEST_EFFORT,
+    }
+}
+
+// 从 Layer1 帧头（0x55 0xBB | version | priority ...）读取优先级，不是 Layer1 帧时返回 None
+pub fn frame_priority(data: &[u8]) -> Option<Priority> {
+    match data {
+        [0x55, 0xBB, _, 0, ..] => Some(Priority::Low),
+        [0x55, 0xBB, _, 1, ..] => Some(Priority::Medium),
+        [0x55, 0xBB, _, 2, ..] => Some(Priority::High),
+        _ => None,
+    }
+}
+
+// 发送一个数据报，auto_dscp 开启且数据是 Layer1 帧时按帧优先级设置这一包的 DSCP
+pub(crate) fn send_to(socket: &UdpSocket, data: &[u8], addr: SocketAddr, auto_dscp: bool) -> io::Result<usize> {
+    match frame_priority(data).filter(|_| auto_dscp) {
+        Some(priority) => sys::send_with_tos(socket, data, addr, (dscp_for_priority(priority) as u32) << 2),
+        None => socket.send_to(data, addr),
+    }
+}
+
+impl UdpClient {
+    // 应用 socket 选项，auto_dscp 对之后的所有发送生效
+    pub fn with_socket_options(mut self, options: &SocketOptions) -> io::Result<Self> {
+        options.apply(&self.socket)?;
+        self.auto_dscp = options.auto_dscp;
+        Ok(self)
+    }
+}
+
+impl UdpServer {
+    // 应用 socket 选项，auto_dscp 对通过 ReplySender 发送的回包生效
+    pub fn with_socket_options(mut self, options: &SocketOptions) -> io::Result<Self> {
+        options.apply(&self.socket)?;
+        self.auto_dscp = options.auto_dscp;
+        Ok(self)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::PmtuDiscovery;
+    use socket2::{SockAddr, SockRef};
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::{mem, ptr};
+
+    pub(super) fn set_tclass_v6(sock: &SockRef<'_>, tclass: u32) -> io::Result<()> {
+        sock.set_tclass_v6(tclass)
+    }
+
+    pub(super) fn bind_device(sock: &SockRef<'_>, device: &str) -> io::Result<()> {
+        sock.bind_device(Some(device.as_bytes()))
+    }
+
+    pub(super) fn set_pmtu_discovery(socket: &UdpSocket, mode: PmtuDiscovery, is_ipv6: bool) -> io::Result<()> {
+        let (level, name, value) = if is_ipv6 {
+            let value = match mode {
+                PmtuDiscovery::Dont => libc::IPV6_PMTUDISC_DONT,
+                PmtuDiscovery::Want => libc::IPV6_PMTUDISC_WANT,
+                PmtuDiscovery::Do => libc::IPV6_PMTUDISC_DO,
+                PmtuDiscovery::Probe => libc::IPV6_PMTUDISC_PROBE,
+            };
+            (libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, value)
+        } else {
+            let value = match mode {
+                PmtuDiscovery::Dont => libc::IP_PMTUDISC_DONT,
+                PmtuDiscovery::Want => libc::IP_PMTUDISC_WANT,
+                PmtuDiscovery::Do => libc::IP_PMTUDISC_DO,
+                PmtuDiscovery::Probe => libc::IP_PMTUDISC_PROBE,
+            };
+            (libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, value)
+        };
+        // SAFETY: value 在调用期间有效
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    // 通过 IP_TOS / IPV6_TCLASS 控制消息只对这一包设置 TOS，多个线程共用 socket 时互不影响
+    pub(super) fn send_with_tos(socket: &UdpSocket, data: &[u8], addr: SocketAddr, tos: u32) -> io::Result<usize> {
+        let (level, kind) = match addr {
+            SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_TOS),
+            SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_TCLASS),
+        };
+        let addr = SockAddr::from(addr);
+        let mut iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        let mut control = [0u64; 4];
+
+        // SAFETY: msghdr 全零是合法的初始值，其中的指针都指向本函数内存活的数据；
+        // control 足够容纳一个 int 的控制消息
+        let ret = unsafe {
+            let mut hdr: libc::msghdr = mem::zeroed();
+            hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+            hdr.msg_namelen = addr.len();
+            hdr.msg_iov = &mut iov;
+            hdr.msg_iovlen = 1;
+            hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_controllen = libc::CMSG_SPACE(mem::size_of::<libc::c_int>() as u32) as usize;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+            (*cmsg).cmsg_level = level;
+            (*cmsg).cmsg_type = kind;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::c_int>() as u32) as usize;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, tos as libc::c_int);
+
+            libc::sendmsg(socket.as_raw_fd(), &hdr, 0)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::PmtuDiscovery;
+    use socket2::SockRef;
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+
+    fn unsupported(option: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, format!("{} is only supported on Linux", option))
+    }
+
+    pub(super) fn set_tclass_v6(_sock: &SockRef<'_>, _tclass: u32) -> io::Result<()> {
+        Err(unsupported("IPV6_TCLASS"))
+    }
+
+    pub(super) fn bind_device(_sock: &SockRef<'_>, _device: &str) -> io::Result<()> {
+        Err(unsupported("SO_BINDTODEVICE"))
+    }
+
+    pub(super) fn set_pmtu_discovery(_socket: &UdpSocket, _mode: PmtuDiscovery, _is_ipv6: bool) -> io::Result<()> {
+        Err(unsupported("IP_MTU_DISCOVER"))
+    }
+
+    // 其他平台不支持逐包设置 TOS，使用 socket 的默认 DSCP 发送
+    pub(super) fn send_with_tos(socket: &UdpSocket, data: &[u8], addr: SocketAddr, _tos: u32) -> io::Result<usize> {
+        socket.send_to(data, addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 只需要帧头，优先级之后的字段不参与判断
+    fn frame(priority: Priority) -> Vec<u8> {
+        vec![0x55, 0xBB, 0x01, priority as u8, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00]
+    }
+
+    #[test]
+    fn test_apply_options() {
+        let client = UdpClient::new()
+            .unwrap()
+            .with_socket_options(
+                &SocketOptions::new()
+                    .with_buffer_sizes(1 << 20, 1 << 19)
+                    .with_dscp(DSCP_EXPEDITED)
+                    .with_ttl(7),
+            )
+            .unwrap();
+
+        let sock = SockRef::from(&client.socket);
+        // Linux 会把设置值翻倍，其他平台至少不小于设置值或受系统上限约束
+        assert!(sock.recv_buffer_size().unwrap() > 0);
+        assert_eq!(sock.tos_v4().unwrap(), (DSCP_EXPEDITED as u32) << 2);
+        assert_eq!(sock.ttl_v4().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_invalid_dscp_rejected() {
+        let client = UdpClient::new().unwrap();
+        let err = SocketOptions::new().with_dscp(64).apply(&client.socket).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_frame_priority() {
+        assert_eq!(frame_priority(&frame(Priority::High)), Some(Priority::High));
+        assert_eq!(frame_priority(&frame(Priority::Low)), Some(Priority::Low));
+        assert_eq!(frame_priority(b"plain data"), None);
+        assert_eq!(dscp_for_priority(Priority::High), DSCP_EXPEDITED);
+        assert_eq!(dscp_for_priority(Priority::Medium), DSCP_ASSURED);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_auto_dscp_per_frame() {
+        use std::os::fd::AsRawFd;
+        use std::time::Duration;
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let enable: libc::c_int = 1;
+        // SAFETY: enable 在调用期间有效
+        unsafe {
+            libc::setsockopt(
+                receiver.as_raw_fd(),
+                libc::IPPROTO_IP,
+                libc::IP_RECVTOS,
+                &enable as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            );
+        }
+
+        // 读取一个数据报的 TOS 字节
+        let recv_tos = || -> u8 {
+            let mut buf = [0u8; 256];
+            let mut control = [0u64; 8];
+            let mut iov = libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            };
+            // SAFETY: 指针都指向闭包内存活的数据
+            unsafe {
+                let mut hdr: libc::msghdr = std::mem::zeroed();
+                hdr.msg_iov = &mut iov;
+                hdr.msg_iovlen = 1;
+                hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+                hdr.msg_controllen = std::mem::size_of_val(&control);
+                assert!(libc::recvmsg(receiver.as_raw_fd(), &mut hdr, 0) > 0);
+                let mut cmsg = libc::CMSG_FIRSTHDR(&hdr);
+                while !cmsg.is_null() {
+                    if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_TOS {
+                        return *libc::CMSG_DATA(cmsg);
+                    }
+                    cmsg = libc::CMSG_NXTHDR(&hdr, cmsg);
+                }
+            }
+            panic!("no IP_TOS control message");
+        };
+
+        let client = UdpClient::new()
+            .unwrap()
+            .with_socket_options(&SocketOptions::new().with_dscp(10).with_auto_dscp())
+            .unwrap();
+        let addr = receiver.local_addr().unwrap();
+
+        client.send_only(addr, &frame(Priority::High)).unwrap();
+        assert_eq!(recv_tos() >> 2, DSCP_EXPEDITED);
+        client.send_only(addr, &frame(Priority::Low)).unwrap();
+        assert_eq!(recv_tos() >> 2, DSCP_BEST_EFFORT);
+        // 不是 Layer1 帧时使用默认 DSCP
+        client.send_only(addr, b"raw").unwrap();
+        assert_eq!(recv_tos() >> 2, 10);
+    }
+}
@@ -0,0 +1,117 @@
+// stats.rs
+use crate::metrics::TransportMetrics;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 服务器接收流水线计数器，可在运行中从其他线程读取
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    received: AtomicU64,
+    queued: AtomicU64,
+    dropped: AtomicU64,
+    truncated: AtomicU64,
+    processed: AtomicU64,
+    errors: AtomicU64,
+    // 同时写入注册表的传输层指标
+    metrics: Option<TransportMetrics>,
+}
+
+/// 计数器在某一时刻的快照
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerStatsSnapshot {
+    /// 从 socket 收到的数据报
+    pub received: u64,
+    /// 成功进入队列的数据报
+    pub queued: u64,
+    /// 因队列已满被丢弃的数据报
+    pub dropped: u64,
+    /// 超过最大长度被截断而丢弃的数据报
+    pub truncated: u64,
+    /// 已交给回调处理完成的数据报
+    pub processed: u64,
+    /// 接收错误次数
+    pub errors: u64,
+}
+
+impl ServerStats {
+    pub(crate) fn with_metrics(metrics: Option<TransportMetrics>) -> Self {
+        ServerStats {
+            metrics,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Option<&TransportMetrics> {
+        self.metrics.as_ref()
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    pub fn queued(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn truncated(&self) -> u64 {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> ServerStatsSnapshot {
+        ServerStatsSnapshot {
+            received: self.received(),
+            queued: self.queued(),
+            dropped: self.dropped(),
+            truncated: self.truncated(),
+            processed: self.processed(),
+            errors: self.errors(),
+        }
+    }
+
+    pub(crate) fn add_received(&self, len: usize) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.received(len);
+        }
+    }
+
+    pub(crate) fn add_queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.queue_drop();
+        }
+    }
+
+    pub(crate) fn add_truncated(&self) {
+        self.truncated.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.truncated();
+        }
+    }
+
+    pub(crate) fn add_processed(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.receive_error();
+        }
+    }
+}
@@ -0,0 +1,596 @@
+// tcp.rs
+// TCP 传输：在 TCP 连接上承载同样的第一层帧，按 Frame Length 从字节流中切出帧边界。
+// 每个对端地址对应一条连接，收到的帧的来源地址即连接的对端地址，回复时按地址找到连接写回。
+// 监听端只回复已有连接；主动连接端在发送时按需建立连接，连接断开后在下次发送时重连，
+// 连续失败时按指数退避推迟下一次重连。
+use crate::transport::DatagramTransport;
+use crate::{UdpClient, UdpServer};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use udp_protocol::FrameDecoder;
+use udp_protocol::stream::FRAME_DELIMITER;
+
+// 单次读取的字节数
+const READ_CHUNK: usize = 16 * 1024;
+/// 默认的接收帧队列容量
+pub const DEFAULT_FRAME_QUEUE_CAPACITY: usize = 1024;
+
+/// TCP 上的客户端
+pub type TcpClient = UdpClient<TcpTransport>;
+/// TCP 上的服务器
+pub type TcpServer = UdpServer<TcpTransport>;
+
+/// TCP 连接参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct TcpConfig {
+    /// 建立连接的超时
+    pub connect_timeout: Duration,
+    /// 写入一帧的超时，超时后关闭该连接；None 表示一直阻塞
+    pub write_timeout: Option<Duration>,
+    /// 第一次连接失败后的重连间隔
+    pub reconnect_delay: Duration,
+    /// 重连间隔的上限，每次失败后间隔翻倍
+    pub max_reconnect_delay: Duration,
+    /// 关闭 Nagle 算法，小帧立即发出
+    pub nodelay: bool,
+    /// 已收到但还没被取走的帧数上限，队列满时读取线程停止读取，由 TCP 流控让对端等待
+    pub frame_queue_capacity: usize,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        TcpConfig {
+            connect_timeout: Duration::from_secs(3),
+            write_timeout: Some(Duration::from_secs(3)),
+            reconnect_delay: Duration::from_millis(100),
+            max_reconnect_delay: Duration::from_secs(5),
+            nodelay: true,
+            frame_queue_capacity: DEFAULT_FRAME_QUEUE_CAPACITY,
+        }
+    }
+}
+
+impl TcpConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn with_reconnect_backoff(mut self, delay: Duration, max_delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self.max_reconnect_delay = max_delay;
+        self
+    }
+
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn with_frame_queue_capacity(mut self, capacity: usize) -> Self {
+        self.frame_queue_capacity = capacity.max(1);
+        self
+    }
+
+    // 连续失败 failures 次后的重连间隔
+    fn reconnect_delay(&self, failures: u32) -> Duration {
+        let exp = failures.saturating_sub(1).min(31) as i32;
+        let secs = self.reconnect_delay.as_secs_f64() * 2f64.powi(exp);
+        Duration::from_secs_f64(secs.min(self.max_reconnect_delay.as_secs_f64()))
+    }
+}
+
+struct Connection {
+    stream: Mutex<TcpStream>,
+    local_addr: SocketAddr,
+}
+
+// 连接失败的次数和允许下一次尝试的时间
+struct Backoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+type Frame = (SocketAddr, Vec<u8>);
+
+struct Shared {
+    config: TcpConfig,
+    listen_addr: Option<SocketAddr>,
+    connections: Mutex<HashMap<SocketAddr, Arc<Connection>>>,
+    backoff: Mutex<HashMap<SocketAddr, Backoff>>,
+    frames_tx: SyncSender<Frame>,
+    frames_rx: Mutex<Receiver<Frame>>,
+    read_timeout: Mutex<Option<Duration>>,
+    nonblocking: AtomicBool,
+}
+
+impl Shared {
+    // 登记连接并启动读取线程
+    fn add_connection(self: &Arc<Self>, stream: TcpStream, peer: SocketAddr) -> io::Result<Arc<Connection>> {
+        stream.set_nodelay(self.config.nodelay)?;
+        // 发送在连接锁内阻塞写入，对端不读时靠写超时放弃该连接
+        stream.set_write_timeout(self.config.write_timeout)?;
+        let reader = stream.try_clone()?;
+        let connection = Arc::new(Connection {
+            local_addr: stream.local_addr()?,
+            stream: Mutex::new(stream),
+        });
+        if let Some(old) = self.connections.lock().unwrap().insert(peer, connection.clone()) {
+            let _ = old.stream.lock().unwrap().shutdown(Shutdown::Both);
+        }
+
+        tracing::debug!(%peer, "tcp connection established");
+
+        let weak = Arc::downgrade(self);
+        let frames_tx = self.frames_tx.clone();
+        let reading = connection.clone();
+        thread::spawn(move || read_frames(reader, peer, frames_tx, weak, reading));
+        Ok(connection)
+    }
+
+    fn remove_connection(&self, peer: SocketAddr, connection: &Arc<Connection>) {
+        let mut connections = self.connections.lock().unwrap();
+        if connections.get(&peer).is_some_and(|current| Arc::ptr_eq(current, connection)) {
+            connections.remove(&peer);
+        }
+    }
+
+    // 找到已有连接，主动连接端在没有连接时按退避规则建立连接
+    fn connection(self: &Arc<Self>, peer: SocketAddr) -> io::Result<Arc<Connection>> {
+        if let Some(connection) = self.connections.lock().unwrap().get(&peer) {
+            return Ok(connection.clone());
+        }
+        if self.listen_addr.is_some() {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, format!("No connection from {}", peer)));
+        }
+
+        // 只在检查和更新退避状态时持有锁，连接其他地址的发送不会被这里的建连阻塞
+        if let Some(state) = self.backoff.lock().unwrap().get(&peer) {
+            let wait = state.retry_at.saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    format!("Reconnect to {} backing off for {:?}", peer, wait),
+                ));
+            }
+        }
+        match TcpStream::connect_timeout(&peer, self.config.connect_timeout) {
+            Ok(stream) => {
+                self.backoff.lock().unwrap().remove(&peer);
+                // 并发发送可能已经建好了连接，沿用它而不是替换掉
+                if let Some(connection) = self.connections.lock().unwrap().get(&peer) {
+                    return Ok(connection.clone());
+                }
+                self.add_connection(stream, peer)
+            }
+            Err(e) => {
+                let mut backoff = self.backoff.lock().unwrap();
+                let state = backoff.entry(peer).or_insert(Backoff {
+                    failures: 0,
+                    retry_at: Instant::now(),
+                });
+                state.failures += 1;
+                let delay = self.config.reconnect_delay(state.failures);
+                state.retry_at = Instant::now() + delay;
+                tracing::warn!(%peer, error = %e, failures = state.failures, retry_in = ?delay, "tcp connect failed");
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for Shared {
+    // 所有句柄释放后关闭连接，读取线程随之退出；连接一次自身唤醒阻塞在 accept 的监听线程
+    fn drop(&mut self) {
+        for connection in self.connections.lock().unwrap().values() {
+            let _ = connection.stream.lock().unwrap().shutdown(Shutdown::Both);
+        }
+        if let Some(addr) = self.listen_addr {
+            let wake_addr = match addr {
+                SocketAddr::V4(v4) if v4.ip().is_unspecified() => (Ipv4Addr::LOCALHOST, v4.port()).into(),
+                SocketAddr::V6(v6) if v6.ip().is_unspecified() => (Ipv6Addr::LOCALHOST, v6.port()).into(),
+                addr => addr,
+            };
+            let _ = TcpStream::connect_timeout(&wake_addr, Duration::from_millis(100));
+        }
+    }
+}
+
+// 从连接中切出完整帧送入接收队列，连接断开或传输释放后退出
+fn read_frames(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    frames_tx: SyncSender<Frame>,
+    shared: Weak<Shared>,
+    connection: Arc<Connection>,
+) {
+    let mut decoder = FrameDecoder::new();
+    let mut chunk = vec![0; READ_CHUNK];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => decoder.push(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+        while let Some(frame) = decoder.next_frame() {
+            if frames_tx.send((peer, frame)).is_err() {
+                return;
+            }
+        }
+    }
+    if decoder.discarded() > 0 {
+        tracing::debug!(%peer, discarded = decoder.discarded(), "tcp stream contained invalid bytes");
+    }
+    if let Some(shared) = shared.upgrade() {
+        shared.remove_connection(peer, &connection);
+        tracing::debug!(%peer, "tcp connection closed");
+    }
+}
+
+fn accept_loop(listener: TcpListener, shared: Weak<Shared>) {
+    for stream in listener.incoming() {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if let Ok(peer) = stream.peer_addr() {
+            let _ = shared.add_connection(stream, peer);
+        }
+    }
+}
+
+/// TCP 传输，克隆出的句柄共享同一组连接
+#[derive(Clone)]
+pub struct TcpTransport {
+    shared: Arc<Shared>,
+}
+
+impl TcpTransport {
+    // 主动连接端，发送时按需建立连接
+    pub fn new(config: TcpConfig) -> Self {
+        Self::with_listener(None, config)
+    }
+
+    // 主动连接端，立即连接 addr
+    pub fn connect(addr: SocketAddr, config: TcpConfig) -> io::Result<Self> {
+        let transport = Self::new(config);
+        transport.shared.connection(addr)?;
+        Ok(transport)
+    }
+
+    // 监听端，port 为 0 时由系统分配
+    pub fn bind(port: u16, config: TcpConfig) -> io::Result<Self> {
+        Self::from_listener(TcpListener::bind(("0.0.0.0", port))?, config)
+    }
+
+    pub fn from_listener(listener: TcpListener, config: TcpConfig) -> io::Result<Self> {
+        let listen_addr = listener.local_addr()?;
+        let transport = Self::with_listener(Some(listen_addr), config);
+        let weak = Arc::downgrade(&transport.shared);
+        thread::spawn(move || accept_loop(listener, weak));
+        Ok(transport)
+    }
+
+    fn with_listener(listen_addr: Option<SocketAddr>, config: TcpConfig) -> Self {
+        let (frames_tx, frames_rx) = mpsc::sync_channel(config.frame_queue_capacity.max(1));
+        TcpTransport {
+            shared: Arc::new(Shared {
+                config,
+                listen_addr,
+                connections: Mutex::new(HashMap::new()),
+                backoff: Mutex::new(HashMap::new()),
+                frames_tx,
+                frames_rx: Mutex::new(frames_rx),
+                read_timeout: Mutex::new(None),
+                nonblocking: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    // 当前连接的对端地址
+    pub fn connections(&self) -> Vec<SocketAddr> {
+        self.shared.connections.lock().unwrap().keys().copied().collect()
+    }
+
+    // 主动断开与 peer 的连接，返回连接是否存在
+    pub fn disconnect(&self, peer: SocketAddr) -> bool {
+        match self.shared.connections.lock().unwrap().remove(&peer) {
+            Some(connection) => {
+                let _ = connection.stream.lock().unwrap().shutdown(Shutdown::Both);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn recv_frame(&self) -> io::Result<Frame> {
+        let frames_rx = self.shared.frames_rx.lock().unwrap();
+        let timed_out = || io::Error::new(io::ErrorKind::WouldBlock, "Receive timed out");
+        if self.shared.nonblocking.load(Ordering::Relaxed) {
+            return frames_rx.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => timed_out(),
+                TryRecvError::Disconnected => unreachable!("frame sender is owned by the transport"),
+            });
+        }
+        let timeout = *self.shared.read_timeout.lock().unwrap();
+        match timeout {
+            Some(timeout) => frames_rx.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => timed_out(),
+                RecvTimeoutError::Disconnected => unreachable!("frame sender is owned by the transport"),
+            }),
+            None => Ok(frames_rx.recv().expect("frame sender is owned by the transport")),
+        }
+    }
+}
+
+// 只接受单个完整的第一层帧，否则对端无法切分
+fn check_frame(buf: &[u8]) -> io::Result<()> {
+    let valid = buf.len() >= 12
+        && buf[..2] == FRAME_DELIMITER
+        && u16::from_le_bytes([buf[8], buf[9]]) as usize + 10 == buf.len();
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "TCP transport only carries complete Layer1 frames"))
+    }
+}
+
+impl DatagramTransport for TcpTransport {
+    type Addr = SocketAddr;
+
+    // 写入失败时关闭该连接，主动连接端下次发送会重连
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        check_frame(buf)?;
+        let connection = self.shared.connection(*addr)?;
+        let result = connection.stream.lock().unwrap().write_all(buf);
+        if let Err(e) = result {
+            let _ = connection.stream.lock().unwrap().shutdown(Shutdown::Both);
+            self.shared.remove_connection(*addr, &connection);
+            return Err(e);
+        }
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (peer, frame) = self.recv_frame()?;
+        let len = frame.len().min(buf.len());
+        buf[..len].copy_from_slice(&frame[..len]);
+        Ok((len, peer))
+    }
+
+    // 监听端返回监听地址，主动连接端返回任一连接的本地地址
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        if let Some(addr) = self.shared.listen_addr {
+            return Ok(addr);
+        }
+        self.shared
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .next()
+            .map(|connection| connection.local_addr)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "No TCP connection"))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        if timeout.is_some_and(|t| t.is_zero()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Zero duration is not a valid timeout"));
+        }
+        *self.shared.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.shared.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+
+    // 帧长度总是已知的
+    fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (peer, frame) = self.recv_frame()?;
+        if frame.len() > buf.len() {
+            return Err(crate::TruncatedDatagram { len: Some(frame.len()), max: buf.len() }.into());
+        }
+        buf[..frame.len()].copy_from_slice(&frame);
+        Ok((frame.len(), peer))
+    }
+}
+
+impl UdpClient<TcpTransport> {
+    // 连接到 addr 的 TCP 客户端
+    pub fn connect(addr: SocketAddr) -> io::Result<Self> {
+        Self::connect_with(addr, TcpConfig::default())
+    }
+
+    pub fn connect_with(addr: SocketAddr, config: TcpConfig) -> io::Result<Self> {
+        Ok(Self::from_socket(TcpTransport::connect(addr, config)?))
+    }
+}
+
+impl UdpServer<TcpTransport> {
+    // 在 port 上监听的 TCP 服务器，port 为 0 时由系统分配
+    pub fn listen(port: u16) -> io::Result<Self> {
+        Self::listen_with(port, TcpConfig::default())
+    }
+
+    pub fn listen_with(port: u16, config: TcpConfig) -> io::Result<Self> {
+        Ok(Self::from_socket(TcpTransport::bind(port, config)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DeviceClient;
+    use crate::{FrameServer, Route};
+    use udp_protocol::{DeviceType, ProtocolBody, RegisterProtocol, RequestBodyType};
+
+    fn frame(seq: u16, payload: &[u8]) -> Vec<u8> {
+        udp_protocol::Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: udp_protocol::Priority::Medium,
+            check_type: udp_protocol::CheckType::CheckSum,
+            frame_type: udp_protocol::FrameType::Type0,
+            frame_seq_number: seq,
+            frame_length: 0,
+            payload: payload.to_vec(),
+            checksum: 0,
+        }
+        .serialize()
+    }
+
+    fn localhost(port: u16) -> SocketAddr {
+        (Ipv4Addr::LOCALHOST, port).into()
+    }
+
+    #[test]
+    fn test_tcp_echo_exposes_peer() {
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let seen = peers.clone();
+        let handle = TcpServer::listen(0)
+            .unwrap()
+            .start_pool(2, move |src_addr, data, reply| {
+                seen.lock().unwrap().push(src_addr);
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+        let addr = localhost(handle.local_addr().port());
+
+        let client = TcpClient::connect(addr).unwrap();
+        for seq in 0..3 {
+            let request = frame(seq, &[seq as u8; 100]);
+            assert_eq!(client.send_and_receive(addr, &request, Duration::from_secs(1)).unwrap(), request);
+        }
+        assert!(peers.lock().unwrap().iter().all(|peer| *peer == client.local_addr().unwrap()));
+        assert_eq!(client.send_only(addr, b"not a frame").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_reconnect_after_disconnect() {
+        let server = TcpServer::listen(0).unwrap();
+        let transport = server.socket.clone();
+        let handle = server
+            .start_pool(1, |_src_addr, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+        let addr = localhost(handle.local_addr().port());
+
+        let client = TcpClient::connect(addr).unwrap();
+        let request = frame(1, b"before");
+        assert_eq!(client.send_and_receive(addr, &request, Duration::from_secs(1)).unwrap(), request);
+
+        // 服务器断开后客户端的读取线程移除连接，下次发送时重新连接
+        let first = client.local_addr().unwrap();
+        assert!(transport.disconnect(first));
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !client.socket.connections().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let request = frame(2, b"after");
+        assert_eq!(client.send_and_receive(addr, &request, Duration::from_secs(1)).unwrap(), request);
+        assert_ne!(client.local_addr().unwrap(), first);
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        // 拿到一个没有监听的端口
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let addr = localhost(port);
+        let config = TcpConfig::new().with_reconnect_backoff(Duration::from_millis(100), Duration::from_secs(1));
+        let transport = TcpTransport::new(config);
+
+        let err = transport.send_to(&frame(1, b""), &addr).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        // 退避期间不再尝试连接
+        let err = transport.send_to(&frame(1, b""), &addr).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+
+        thread::sleep(Duration::from_millis(120));
+        let err = transport.send_to(&frame(1, b""), &addr).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(transport.shared.config.reconnect_delay(2), Duration::from_millis(200));
+        assert_eq!(transport.shared.config.reconnect_delay(10), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_full_frame_queue_applies_backpressure() {
+        let server = TcpTransport::bind(0, TcpConfig::new().with_frame_queue_capacity(2)).unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = localhost(server.local_addr().unwrap().port());
+        let client = TcpTransport::connect(addr, TcpConfig::default()).unwrap();
+
+        // 队列只能放两帧，其余的留在连接里，取走后继续读取，不丢帧
+        for seq in 0..10 {
+            client.send_to(&frame(seq, &[seq as u8; 8]), &addr).unwrap();
+        }
+        let mut buf = [0u8; 64];
+        for seq in 0..10 {
+            let (len, _) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..len], frame(seq, &[seq as u8; 8]).as_slice());
+        }
+    }
+
+    #[test]
+    fn test_write_timeout_drops_stalled_connection() {
+        // 对端接受连接但从不读取
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = localhost(listener.local_addr().unwrap().port());
+        let config = TcpConfig::new().with_write_timeout(Some(Duration::from_millis(50)));
+        let transport = TcpTransport::connect(addr, config).unwrap();
+        let (_stalled, _) = listener.accept().unwrap();
+
+        let big = frame(1, &[0xAA; 60000]);
+        let err = (0..10_000)
+            .find_map(|_| transport.send_to(&big, &addr).err())
+            .expect("send buffer never filled");
+        assert!(matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut));
+        assert!(transport.connections().is_empty());
+    }
+
+    #[test]
+    fn test_device_client_over_tcp() {
+        let handle = FrameServer::new(TcpServer::listen(0).unwrap())
+            .route(Route::new(RequestBodyType::RegisterProtocol), |req| {
+                let ProtocolBody::Register(reg) = &req.body else { unreachable!() };
+                Some(ProtocolBody::Register(RegisterProtocol::new(reg.register_address, 0, vec![0x5A; reg.data_length as usize])))
+            })
+            .start()
+            .unwrap();
+        let addr = localhost(handle.local_addr().port());
+
+        let device = DeviceClient::new(TcpClient::connect(addr).unwrap(), addr, DeviceType::FPGA, 0);
+        assert_eq!(device.read_register(0x40, 3).unwrap(), [0x5A; 3]);
+
+        handle.shutdown().unwrap();
+    }
+}
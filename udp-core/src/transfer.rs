@@ -0,0 +1,715 @@
+// transfer.rs
+// 文件传输：发送方先发 Offer 等待确认，然后把文件按块封装为 TransferFrame::Chunk，
+// 用可靠通道的发送窗口（选择确认和 RTO 重传）发送；全部确认后发送整个文件的 SHA-256，
+// 接收方按序写入并计算摘要，比对结果通过 Complete 返回。两端都没有后台线程，进度通过回调报告。
+use crate::reliable::{MAX_WINDOW_SIZE, ReliableConfig, SendWindow, sack_bitmap};
+use crate::retry::is_timeout;
+use crate::transport::DatagramTransport;
+use crate::{UdpClient, UdpServer};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::UdpSocket;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use udp_protocol::transfer::{DIGEST_LENGTH, MAX_CHUNK_SIZE, MAX_NAME_LENGTH, TransferFrame};
+
+/// 未指定时的数据块大小
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+/// 接收方等待发送方下一帧的默认超时
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// 接收方回复 Complete 后继续应答重传的默认时长
+pub const DEFAULT_LINGER: Duration = Duration::from_secs(1);
+
+/// 传输进度
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferProgress {
+    /// 发送方为已确认的字节数，接收方为已按序写入的字节数
+    pub bytes: u64,
+    pub total: u64,
+    pub elapsed: Duration,
+    /// 发送方为重传次数，接收方为收到的重复块数
+    pub retransmissions: u64,
+}
+
+impl TransferProgress {
+    // 完成比例，空文件视为已完成
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.bytes as f64 / self.total as f64
+        }
+    }
+
+    // 平均吞吐量，字节/秒
+    pub fn throughput(&self) -> f64 {
+        throughput(self.bytes, self.elapsed)
+    }
+}
+
+/// 传输进度回调
+pub type ProgressHandler = Box<dyn FnMut(&TransferProgress) + Send>;
+
+/// 接收方收到的传输请求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferOffer<A = std::net::SocketAddr> {
+    pub peer: A,
+    pub name: String,
+    pub size: u64,
+    pub chunk_size: u16,
+}
+
+/// 完成的传输
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferReport {
+    pub name: String,
+    pub size: u64,
+    pub chunks: u32,
+    /// 整个文件的 SHA-256，接收方为自己计算的摘要
+    pub sha256: [u8; DIGEST_LENGTH],
+    pub elapsed: Duration,
+    /// 发送方为重传次数，接收方为收到的重复块数
+    pub retransmissions: u64,
+}
+
+impl TransferReport {
+    // 平均吞吐量，字节/秒
+    pub fn throughput(&self) -> f64 {
+        throughput(self.size, self.elapsed)
+    }
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 { bytes as f64 / secs } else { 0.0 }
+}
+
+fn chunk_count(size: u64, chunk_size: usize) -> Option<u32> {
+    size.div_ceil(chunk_size as u64).try_into().ok()
+}
+
+// 第 index 块的长度，最后一块可能不足一整块
+fn chunk_len(size: u64, chunk_size: usize, index: u32) -> usize {
+    let offset = index as u64 * chunk_size as u64;
+    size.saturating_sub(offset).min(chunk_size as u64) as usize
+}
+
+/// 文件发送方
+pub struct FileSender<T: DatagramTransport = UdpSocket> {
+    client: UdpClient<T>,
+    config: ReliableConfig,
+    chunk_size: usize,
+    progress: Option<ProgressHandler>,
+}
+
+impl<T: DatagramTransport> FileSender<T> {
+    pub fn new(client: UdpClient<T>) -> Self {
+        FileSender {
+            client,
+            config: ReliableConfig::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            progress: None,
+        }
+    }
+
+    // 窗口大小和重传定时器参数
+    pub fn with_config(mut self, mut config: ReliableConfig) -> Self {
+        config.window_size = config.window_size.clamp(1, MAX_WINDOW_SIZE);
+        self.config = config;
+        self
+    }
+
+    // 数据块大小，不超过 MAX_CHUNK_SIZE
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.clamp(1, MAX_CHUNK_SIZE);
+        self
+    }
+
+    // 每次确认推进时调用
+    pub fn on_progress<H>(mut self, handler: H) -> Self
+    where
+        H: FnMut(&TransferProgress) + Send + 'static,
+    {
+        self.progress = Some(Box::new(handler));
+        self
+    }
+
+    // 发送本地文件，使用文件名作为传输名称
+    pub fn send_file(&mut self, addr: T::Addr, path: &Path) -> io::Result<TransferReport> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File name is not valid UTF-8"))?;
+        self.send(addr, name, size, file)
+    }
+
+    // 从 reader 读取 size 字节发送给 addr，接收方确认摘要一致后返回
+    pub fn send<R: Read>(&mut self, addr: T::Addr, name: &str, size: u64, mut reader: R) -> io::Result<TransferReport> {
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "File name is too long"));
+        }
+        let chunks = chunk_count(size, self.chunk_size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "File has too many chunks"))?;
+        let span = tracing::debug_span!("send_file", peer = ?addr, name, size);
+        let _enter = span.enter();
+
+        let mut session = SendSession {
+            client: &self.client,
+            peer: addr,
+            transfer_id: rand::random(),
+            seq: 0,
+            send_base: 0,
+            window: SendWindow::new(self.config.clone()),
+            retransmissions: 0,
+            started: Instant::now(),
+            buf: vec![0; self.client.max_datagram_size],
+        };
+
+        let offer = TransferFrame::Offer {
+            transfer_id: session.transfer_id,
+            size,
+            chunk_size: self.chunk_size as u16,
+            name: name.to_string(),
+        };
+        session.exchange(&offer, |frame| matches!(frame, TransferFrame::Ack { next: 0, .. }))?;
+        tracing::debug!(chunks, "offer accepted");
+
+        let mut hasher = Sha256::new();
+        let mut next_chunk = 0;
+        while session.send_base < chunks {
+            while next_chunk < chunks && !session.window.is_full() {
+                let mut data = vec![0; chunk_len(size, self.chunk_size, next_chunk)];
+                reader.read_exact(&mut data)?;
+                hasher.update(&data);
+                session.send_chunk(next_chunk, data)?;
+                next_chunk += 1;
+            }
+
+            let acked = session.send_base;
+            session.poll()?;
+            if session.send_base != acked {
+                let progress = session.progress(size, self.chunk_size);
+                if let Some(handler) = self.progress.as_mut() {
+                    handler(&progress);
+                }
+            }
+        }
+
+        let sha256: [u8; DIGEST_LENGTH] = hasher.finalize().into();
+        let finish = TransferFrame::Finish {
+            transfer_id: session.transfer_id,
+            sha256,
+        };
+        match session.exchange(&finish, |frame| matches!(frame, TransferFrame::Complete { .. }))? {
+            TransferFrame::Complete { verified: true, .. } => Ok(TransferReport {
+                name: name.to_string(),
+                size,
+                chunks,
+                sha256,
+                elapsed: session.started.elapsed(),
+                retransmissions: session.retransmissions,
+            }),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Receiver reported a SHA-256 mismatch")),
+        }
+    }
+}
+
+// 一次发送的状态：发送窗口中第 i 个数据段为第 send_base + i 块
+struct SendSession<'a, T: DatagramTransport> {
+    client: &'a UdpClient<T>,
+    peer: T::Addr,
+    transfer_id: u32,
+    seq: u16,
+    send_base: u32,
+    window: SendWindow,
+    retransmissions: u64,
+    started: Instant,
+    buf: Vec<u8>,
+}
+
+impl<T: DatagramTransport> SendSession<'_, T> {
+    fn next_seq(&mut self) -> u16 {
+        self.seq = self.seq.wrapping_add(1);
+        self.seq
+    }
+
+    fn progress(&self, size: u64, chunk_size: usize) -> TransferProgress {
+        TransferProgress {
+            bytes: (self.send_base as u64 * chunk_size as u64).min(size),
+            total: size,
+            elapsed: self.started.elapsed(),
+            retransmissions: self.retransmissions,
+        }
+    }
+
+    // 停等发送一个请求，直到收到满足 accept 的响应。请求在空窗口中单独占一个位置，
+    // 重传和 RTT 采样与数据块相同
+    fn exchange<F>(&mut self, request: &TransferFrame, accept: F) -> io::Result<TransferFrame>
+    where
+        F: Fn(&TransferFrame) -> bool,
+    {
+        debug_assert!(self.window.is_empty());
+        let frame = request.encode(self.next_seq());
+        let sent_at = self.client.send_timed(self.peer.clone(), &frame)?;
+        self.window.push(frame, sent_at);
+        loop {
+            if let Some(response) = self.recv(self.retransmit_wait())?.filter(&accept) {
+                self.window.ack(1, 0);
+                return Ok(response);
+            }
+            self.retransmit_expired()?;
+        }
+    }
+
+    fn send_chunk(&mut self, index: u32, data: Vec<u8>) -> io::Result<()> {
+        let seq = self.next_seq();
+        let frame = TransferFrame::Chunk {
+            transfer_id: self.transfer_id,
+            index,
+            data,
+        }
+        .encode(seq);
+        let sent_at = self.client.send_timed(self.peer.clone(), &frame)?;
+        self.window.push(frame, sent_at);
+        Ok(())
+    }
+
+    // 等待一个属于本次传输的响应，超时或收到无关数据报返回 None
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<TransferFrame>> {
+        // 读超时不能为0
+        self.client.socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let (num_bytes, src_addr) = match self.client.socket.recv_from(&mut self.buf) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if src_addr != self.peer {
+            return Ok(None);
+        }
+        Ok(TransferFrame::decode(&self.buf[..num_bytes])
+            .ok()
+            .filter(|frame| frame.transfer_id() == self.transfer_id))
+    }
+
+    // 距最近一次重传的时间
+    fn retransmit_wait(&self) -> Duration {
+        self.window
+            .next_retransmit()
+            .map_or(self.window.rto(), |next| next.saturating_duration_since(Instant::now()))
+    }
+
+    // 等待确认（不超过最近的重传时间），处理后检查重传定时器
+    fn poll(&mut self) -> io::Result<()> {
+        if let Some(TransferFrame::Ack { next, sack_bitmap, .. }) = self.recv(self.retransmit_wait())? {
+            // 比窗口起点还旧的确认直接忽略
+            if let Some(acked) = next.checked_sub(self.send_base)
+                && self.window.ack(acked as usize, sack_bitmap)
+            {
+                self.send_base = next;
+            }
+        }
+        self.retransmit_expired()
+    }
+
+    fn retransmit_expired(&mut self) -> io::Result<()> {
+        let (client, peer) = (self.client, &self.peer);
+        self.retransmissions += self
+            .window
+            .retransmit_expired(|frame| client.send_only(peer.clone(), frame))?;
+        Ok(())
+    }
+}
+
+/// 文件接收方
+pub struct FileReceiver<T: DatagramTransport = UdpSocket> {
+    server: UdpServer<T>,
+    window_size: u16,
+    idle_timeout: Duration,
+    linger: Duration,
+    progress: Option<ProgressHandler>,
+}
+
+impl<T: DatagramTransport> FileReceiver<T> {
+    pub fn new(server: UdpServer<T>) -> Self {
+        FileReceiver {
+            server,
+            window_size: ReliableConfig::default().window_size,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            linger: DEFAULT_LINGER,
+            progress: None,
+        }
+    }
+
+    // 接收窗口，超出窗口的数据块被丢弃，应不小于发送方窗口
+    pub fn with_window_size(mut self, window_size: u16) -> Self {
+        self.window_size = window_size.clamp(1, MAX_WINDOW_SIZE);
+        self
+    }
+
+    // 传输开始后超过该时间收不到发送方的帧即放弃
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    // 回复 Complete 后继续应答重传的 Finish 的时长，防止 Complete 丢失后发送方一直重传
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    // 每写入一个数据块调用
+    pub fn on_progress<H>(mut self, handler: H) -> Self
+    where
+        H: FnMut(&TransferProgress) + Send + 'static,
+    {
+        self.progress = Some(Box::new(handler));
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<T::Addr> {
+        self.server.socket.local_addr()
+    }
+
+    // 等待一个传输请求（最长 timeout），用 open 创建输出后按序写入，
+    // 摘要一致时返回传输结果，不一致时返回 InvalidData
+    pub fn receive<W, F>(&mut self, timeout: Duration, open: F) -> io::Result<TransferReport>
+    where
+        W: Write,
+        F: FnOnce(&TransferOffer<T::Addr>) -> io::Result<W>,
+    {
+        let socket = &self.server.socket;
+        let mut buf = vec![0; self.server.max_datagram_size];
+        let deadline = Instant::now() + timeout;
+        let (offer, transfer_id) = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "No transfer offered"));
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let (num_bytes, src_addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Ok(TransferFrame::Offer {
+                transfer_id,
+                size,
+                chunk_size,
+                name,
+            }) = TransferFrame::decode(&buf[..num_bytes])
+            {
+                let offer = TransferOffer {
+                    peer: src_addr,
+                    name,
+                    size,
+                    chunk_size,
+                };
+                if chunk_count(size, chunk_size as usize).is_some() {
+                    break (offer, transfer_id);
+                }
+            }
+        };
+
+        let span = tracing::debug_span!("receive_file", peer = ?offer.peer, name = offer.name, size = offer.size);
+        let _enter = span.enter();
+        let mut session = ReceiveSession {
+            socket,
+            peer: offer.peer.clone(),
+            transfer_id,
+            size: offer.size,
+            chunk_size: offer.chunk_size as usize,
+            chunks: chunk_count(offer.size, offer.chunk_size as usize).unwrap_or(0),
+            window_size: self.window_size as usize,
+            writer: open(&offer)?,
+            hasher: Sha256::new(),
+            seq: 0,
+            next: 0,
+            reorder: VecDeque::new(),
+            bytes: 0,
+            duplicates: 0,
+            started: Instant::now(),
+        };
+        session.send_ack()?;
+
+        let mut last_activity = Instant::now();
+        let (verified, elapsed) = loop {
+            let remaining = self.idle_timeout.saturating_sub(last_activity.elapsed());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Sender stopped responding"));
+            }
+            let Some(frame) = session.recv(&mut buf, remaining)? else {
+                continue;
+            };
+            last_activity = Instant::now();
+            match frame {
+                TransferFrame::Offer { .. } => session.send_ack()?,
+                TransferFrame::Chunk { index, data, .. } => {
+                    let written = session.bytes;
+                    session.on_chunk(index, data)?;
+                    if session.bytes != written
+                        && let Some(handler) = self.progress.as_mut()
+                    {
+                        handler(&session.progress());
+                    }
+                }
+                TransferFrame::Finish { sha256, .. } if session.next == session.chunks => {
+                    session.writer.flush()?;
+                    let digest: [u8; DIGEST_LENGTH] = session.hasher.clone().finalize().into();
+                    break (digest == sha256, session.started.elapsed());
+                }
+                _ => {}
+            }
+        };
+        session.send_complete(verified)?;
+
+        // 继续应答重传的 Finish，直到 linger 结束
+        let linger_until = Instant::now() + self.linger;
+        while let Some(remaining) = linger_until.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+            if let Some(TransferFrame::Finish { .. }) = session.recv(&mut buf, remaining)? {
+                session.send_complete(verified)?;
+            }
+        }
+
+        if !verified {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "SHA-256 mismatch"));
+        }
+        Ok(TransferReport {
+            name: offer.name,
+            size: offer.size,
+            chunks: session.chunks,
+            sha256: session.hasher.finalize().into(),
+            elapsed,
+            retransmissions: session.duplicates,
+        })
+    }
+}
+
+// 一次接收的状态：reorder[i] 对应第 next + i 块
+struct ReceiveSession<'a, T: DatagramTransport, W> {
+    socket: &'a T,
+    peer: T::Addr,
+    transfer_id: u32,
+    size: u64,
+    chunk_size: usize,
+    chunks: u32,
+    window_size: usize,
+    writer: W,
+    hasher: Sha256,
+    seq: u16,
+    next: u32,
+    reorder: VecDeque<Option<Vec<u8>>>,
+    bytes: u64,
+    duplicates: u64,
+    started: Instant,
+}
+
+impl<T: DatagramTransport, W: Write> ReceiveSession<'_, T, W> {
+    fn progress(&self) -> TransferProgress {
+        TransferProgress {
+            bytes: self.bytes,
+            total: self.size,
+            elapsed: self.started.elapsed(),
+            retransmissions: self.duplicates,
+        }
+    }
+
+    // 等待一个属于本次传输的请求，超时或收到无关数据报返回 None
+    fn recv(&self, buf: &mut [u8], timeout: Duration) -> io::Result<Option<TransferFrame>> {
+        self.socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let (num_bytes, src_addr) = match self.socket.recv_from(buf) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if src_addr != self.peer {
+            return Ok(None);
+        }
+        Ok(TransferFrame::decode(&buf[..num_bytes])
+            .ok()
+            .filter(|frame| frame.transfer_id() == self.transfer_id))
+    }
+
+    fn on_chunk(&mut self, index: u32, data: Vec<u8>) -> io::Result<()> {
+        // 长度不符的块直接丢弃，等待重传
+        if index >= self.chunks || data.len() != chunk_len(self.size, self.chunk_size, index) {
+            return Ok(());
+        }
+        match index.checked_sub(self.next).map(|offset| offset as usize) {
+            Some(offset) if offset < self.window_size => {
+                if self.reorder.len() <= offset {
+                    self.reorder.resize_with(offset + 1, || None);
+                }
+                if self.reorder[offset].is_some() {
+                    self.duplicates += 1;
+                } else {
+                    self.reorder[offset] = Some(data);
+                }
+                // 写入连续到达的数据块
+                while let Some(Some(_)) = self.reorder.front() {
+                    if let Some(Some(chunk)) = self.reorder.pop_front() {
+                        self.writer.write_all(&chunk)?;
+                        self.hasher.update(&chunk);
+                        self.bytes += chunk.len() as u64;
+                    }
+                    self.next += 1;
+                }
+            }
+            // 超出窗口的块丢弃，发送方会重传
+            Some(_) => return Ok(()),
+            // 已经写入过的块，说明发送方没有收到确认
+            None => self.duplicates += 1,
+        }
+        self.send_ack()
+    }
+
+    fn send_ack(&mut self) -> io::Result<()> {
+        self.send(TransferFrame::Ack {
+            transfer_id: self.transfer_id,
+            next: self.next,
+            sack_bitmap: sack_bitmap(&self.reorder),
+        })
+    }
+
+    fn send_complete(&mut self, verified: bool) -> io::Result<()> {
+        self.send(TransferFrame::Complete {
+            transfer_id: self.transfer_id,
+            verified,
+        })
+    }
+
+    fn send(&mut self, frame: TransferFrame) -> io::Result<()> {
+        self.seq = self.seq.wrapping_add(1);
+        self.socket.send_to(&frame.encode(self.seq), &self.peer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ImpairedTransport, ImpairmentConfig, MemoryNetwork};
+    use rand::rngs::StdRng;
+    use rand::{RngCore, SeedableRng};
+    use std::thread;
+
+    fn random_data(len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        StdRng::seed_from_u64(7).fill_bytes(&mut data);
+        data
+    }
+
+    // 回环地址上的有损 UDP socket，按固定种子丢包并偶尔乱序
+    fn lossy(loss: f64, seed: u64) -> ImpairedTransport<UdpSocket> {
+        ImpairedTransport::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            ImpairmentConfig::new()
+                .with_loss(loss)
+                .with_reorder(0.05, Duration::from_millis(2))
+                .with_seed(seed),
+        )
+        .unwrap()
+    }
+
+    fn fast_config() -> ReliableConfig {
+        ReliableConfig {
+            initial_rto: Duration::from_millis(20),
+            min_rto: Duration::from_millis(5),
+            max_rto: Duration::from_millis(200),
+            ..ReliableConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_transfer_over_lossy_loopback() {
+        let data = random_data(300 * 1024 + 123);
+        let mut receiver = FileReceiver::new(UdpServer::from_socket(lossy(0.1, 1)))
+            .with_linger(Duration::from_millis(200));
+        let receiver_addr = receiver.local_addr().unwrap();
+        let receiving = thread::spawn(move || {
+            let mut out = Vec::new();
+            let report = receiver.receive(Duration::from_secs(5), |offer| {
+                assert_eq!(offer.name, "image.bin");
+                Ok(&mut out)
+            });
+            (report, out)
+        });
+
+        let (progress_tx, progress) = std::sync::mpsc::channel();
+        let mut sender = FileSender::new(UdpClient::from_socket(lossy(0.1, 2)))
+            .with_config(fast_config())
+            .on_progress(move |progress| {
+                let _ = progress_tx.send(progress.bytes);
+            });
+        let sent = sender.send(receiver_addr, "image.bin", data.len() as u64, &data[..]).unwrap();
+        let (received, out) = receiving.join().unwrap();
+        let received = received.unwrap();
+
+        assert_eq!(out, data);
+        let expected: [u8; DIGEST_LENGTH] = Sha256::digest(&data).into();
+        assert_eq!(sent.sha256, expected);
+        assert_eq!(received.sha256, expected);
+        assert_eq!(sent.chunks, data.len().div_ceil(DEFAULT_CHUNK_SIZE) as u32);
+        assert!(sent.retransmissions > 0);
+        assert!(sent.throughput() > 0.0);
+
+        // 进度单调增加，最后一次为整个文件
+        let progress: Vec<u64> = progress.try_iter().collect();
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress.last(), Some(&(data.len() as u64)));
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let network = MemoryNetwork::new();
+        let mut receiver = FileReceiver::new(UdpServer::from_socket(network.bind())).with_linger(Duration::ZERO);
+        let receiver_addr = receiver.local_addr().unwrap();
+        let receiving = thread::spawn(move || receiver.receive(Duration::from_secs(2), |_| Ok(io::sink())));
+
+        let mut sender = FileSender::new(UdpClient::from_socket(network.bind()));
+        let sent = sender.send(receiver_addr, "empty", 0, io::empty()).unwrap();
+        let received = receiving.join().unwrap().unwrap();
+        assert_eq!(sent.chunks, 0);
+        assert_eq!(received.sha256, sent.sha256);
+    }
+
+    #[test]
+    fn test_digest_mismatch_reported() {
+        let network = MemoryNetwork::new();
+        let mut receiver = FileReceiver::new(UdpServer::from_socket(network.bind())).with_linger(Duration::ZERO);
+        let receiver_addr = receiver.local_addr().unwrap();
+        let receiving = thread::spawn(move || receiver.receive(Duration::from_secs(2), |_| Ok(io::sink())));
+
+        // 手工发送一个摘要错误的传输
+        let client = UdpClient::from_socket(network.bind());
+        let timeout = Duration::from_secs(1);
+        let exchange = |frame: TransferFrame| {
+            TransferFrame::decode(&client.send_and_receive(receiver_addr, &frame.encode(1), timeout).unwrap()).unwrap()
+        };
+        let offer = TransferFrame::Offer {
+            transfer_id: 9,
+            size: 3,
+            chunk_size: 16,
+            name: "bad".to_string(),
+        };
+        assert!(matches!(exchange(offer), TransferFrame::Ack { next: 0, .. }));
+        let chunk = TransferFrame::Chunk {
+            transfer_id: 9,
+            index: 0,
+            data: b"abc".to_vec(),
+        };
+        assert!(matches!(exchange(chunk), TransferFrame::Ack { next: 1, .. }));
+        let finish = TransferFrame::Finish {
+            transfer_id: 9,
+            sha256: [0; DIGEST_LENGTH],
+        };
+        assert_eq!(exchange(finish), TransferFrame::Complete { transfer_id: 9, verified: false });
+
+        let err = receiving.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,278 @@
+// transport.rs
+// 数据报传输抽象：UdpClient / UdpServer 通过该 trait 收发，除 UDP 外还可以使用
+// 内存通道（memory 模块，用于测试）和 Unix 数据报 socket。
+use crate::batch::{self, RecvBatch};
+use crate::error::TruncatedDatagram;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// 面向数据报的传输
+///
+/// 读超时到期时 `recv_from` 返回 `WouldBlock` 或 `TimedOut`，非阻塞模式下没有数据时返回 `WouldBlock`。
+pub trait DatagramTransport: Send + Sync + Sized + 'static {
+    /// 对端地址类型
+    type Addr: Clone + Eq + Hash + fmt::Debug + Send + Sync + 'static;
+
+    fn send_to(&self, buf: &[u8], addr: &Self::Addr) -> io::Result<usize>;
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, Self::Addr)>;
+
+    fn local_addr(&self) -> io::Result<Self::Addr>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    /// 创建共享同一底层端点的新句柄，用于在其他线程回包
+    fn try_clone(&self) -> io::Result<Self>;
+
+    /// 接收单个数据报，超过缓冲区时返回 TruncatedDatagram 错误。
+    /// 默认实现无法得到实际长度，每次借一个多一字节的临时缓冲区判断是否超过 buf，
+    /// 频繁调用的传输应当自己实现
+    fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, Self::Addr)> {
+        let mut spare = vec![0; buf.len() + 1];
+        let (num_bytes, src_addr) = self.recv_from(&mut spare)?;
+        if num_bytes > buf.len() {
+            return Err(TruncatedDatagram { len: None, max: buf.len() }.into());
+        }
+        buf[..num_bytes].copy_from_slice(&spare[..num_bytes]);
+        Ok((num_bytes, src_addr))
+    }
+
+    /// 批量接收，默认实现每次只接收一个数据报，批量缓冲区多留一个字节判断截断
+    fn recv_batch(&self, batch: &mut RecvBatch<Self::Addr>) -> io::Result<usize> {
+        batch.clear();
+        let (num_bytes, src_addr) = self.recv_from(batch.spare_buf_mut(0))?;
+        if num_bytes > batch.max_len() {
+            batch.push_truncated(src_addr, 0, None);
+        } else {
+            batch.push(src_addr, 0, num_bytes, 0);
+        }
+        Ok(batch.len())
+    }
+
+    /// 发送 Layer1 帧时按帧头优先级设置 DSCP，不支持的传输直接发送
+    fn send_prioritized(&self, buf: &[u8], addr: &Self::Addr) -> io::Result<usize> {
+        self.send_to(buf, addr)
+    }
+}
+
+impl DatagramTransport for UdpSocket {
+    type Addr = SocketAddr;
+
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UdpSocket::set_read_timeout(self, timeout)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UdpSocket::set_nonblocking(self, nonblocking)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        UdpSocket::try_clone(self)
+    }
+
+    // 使用 MSG_TRUNC 得到实际长度
+    fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        batch::recv_from_checked(self, buf)
+    }
+
+    // 使用 recvmmsg，开启 GRO 时拆分合并的数据报
+    fn recv_batch(&self, batch: &mut RecvBatch) -> io::Result<usize> {
+        batch::recv_batch(self, batch)
+    }
+
+    fn send_prioritized(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        crate::sockopt::send_to(self, buf, *addr, true)
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::DatagramTransport;
+    #[cfg(target_os = "linux")]
+    use crate::{batch::RecvBatch, error::TruncatedDatagram};
+    use std::io;
+    use std::os::unix::net::UnixDatagram;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    fn path_of(addr: Option<&Path>) -> io::Result<PathBuf> {
+        addr.map(PathBuf::from)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unix datagram socket is not bound to a path"))
+    }
+
+    // 带 MSG_TRUNC 接收，返回数据报的实际长度，超过 buf 的部分被丢弃
+    #[cfg(target_os = "linux")]
+    fn recv_truncating(socket: &UnixDatagram, buf: &mut [u8]) -> io::Result<(usize, PathBuf)> {
+        use socket2::{SockAddr, SockAddrStorage};
+        use std::os::fd::AsRawFd;
+
+        let mut addr = SockAddrStorage::zeroed();
+        let mut addr_len = addr.size_of();
+        // SAFETY: buf 和 addr 在调用期间有效，传入的长度与它们的大小一致
+        let received = unsafe {
+            libc::recvfrom(
+                socket.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                libc::MSG_TRUNC,
+                &mut addr as *mut SockAddrStorage as *mut libc::sockaddr,
+                &mut addr_len,
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: 内核已经写入了 addr_len 字节的地址
+        let addr = unsafe { SockAddr::new(addr, addr_len) };
+        Ok((received as usize, path_of(addr.as_pathname())?))
+    }
+
+    /// Unix 数据报 socket，地址为绑定的路径，收发双方都需要绑定路径才能互相回复
+    impl DatagramTransport for UnixDatagram {
+        type Addr = PathBuf;
+
+        fn send_to(&self, buf: &[u8], addr: &PathBuf) -> io::Result<usize> {
+            UnixDatagram::send_to(self, buf, addr)
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, PathBuf)> {
+            let (num_bytes, addr) = UnixDatagram::recv_from(self, buf)?;
+            Ok((num_bytes, path_of(addr.as_pathname())?))
+        }
+
+        fn local_addr(&self) -> io::Result<PathBuf> {
+            path_of(UnixDatagram::local_addr(self)?.as_pathname())
+        }
+
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            UnixDatagram::set_read_timeout(self, timeout)
+        }
+
+        fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+            UnixDatagram::set_nonblocking(self, nonblocking)
+        }
+
+        fn try_clone(&self) -> io::Result<Self> {
+            UnixDatagram::try_clone(self)
+        }
+
+        // 和 UDP 一样使用 MSG_TRUNC 得到实际长度，不需要额外的缓冲区
+        #[cfg(target_os = "linux")]
+        fn recv_from_checked(&self, buf: &mut [u8]) -> io::Result<(usize, PathBuf)> {
+            let (num_bytes, src_addr) = recv_truncating(self, buf)?;
+            if num_bytes > buf.len() {
+                return Err(TruncatedDatagram {
+                    len: Some(num_bytes),
+                    max: buf.len(),
+                }
+                .into());
+            }
+            Ok((num_bytes, src_addr))
+        }
+
+        #[cfg(target_os = "linux")]
+        fn recv_batch(&self, batch: &mut RecvBatch<PathBuf>) -> io::Result<usize> {
+            batch.clear();
+            // 实际长度超过最大长度时 push 会标记截断
+            let (num_bytes, src_addr) = recv_truncating(self, batch.buf_mut(0))?;
+            batch.push(src_addr, 0, num_bytes, 0);
+            Ok(batch.len())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{UdpClient, UdpServer};
+    use std::time::Duration;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_datagram_echo() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = std::env::temp_dir().join(format!("udp-core-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let server_path = dir.join("server.sock");
+        let client_path = dir.join("client.sock");
+        let _ = std::fs::remove_file(&server_path);
+        let _ = std::fs::remove_file(&client_path);
+
+        let server = UdpServer::from_socket(UnixDatagram::bind(&server_path).unwrap());
+        let handle = server
+            .start_pool(1, |_src_addr, data, reply| {
+                reply.send(data).unwrap();
+            })
+            .unwrap();
+        assert_eq!(handle.local_addr(), server_path);
+
+        let client = UdpClient::from_socket(UnixDatagram::bind(&client_path).unwrap());
+        let response = client.send_and_receive(server_path.clone(), b"over unix", Duration::from_secs(1)).unwrap();
+        assert_eq!(response, b"over unix");
+
+        handle.shutdown().unwrap();
+        drop(client);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_recv_from_checked_exact_length() {
+        use super::DatagramTransport;
+        use crate::batch::RecvBatch;
+        use crate::error::{TruncatedDatagram, is_truncated};
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = std::env::temp_dir().join(format!("udp-core-unix-exact-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let receiver_path = dir.join("receiver.sock");
+        let sender_path = dir.join("sender.sock");
+        let _ = std::fs::remove_file(&receiver_path);
+        let _ = std::fs::remove_file(&sender_path);
+        let receiver = UnixDatagram::bind(&receiver_path).unwrap();
+        let sender = UnixDatagram::bind(&sender_path).unwrap();
+
+        // 正好填满缓冲区的数据报完整返回，多出一个字节才算截断
+        let mut buf = [0u8; 4];
+        DatagramTransport::send_to(&sender, &[1, 2, 3, 4], &receiver_path).unwrap();
+        assert_eq!(receiver.recv_from_checked(&mut buf).unwrap(), (4, sender_path.clone()));
+        assert_eq!(buf, [1, 2, 3, 4]);
+        DatagramTransport::send_to(&sender, &[5, 6, 7, 8, 9], &receiver_path).unwrap();
+        let err = receiver.recv_from_checked(&mut buf).unwrap_err();
+        assert!(is_truncated(&err));
+        // Linux 上通过 MSG_TRUNC 得到实际长度
+        if cfg!(target_os = "linux") {
+            let truncated = err.get_ref().unwrap().downcast_ref::<TruncatedDatagram>().unwrap();
+            assert_eq!(*truncated, TruncatedDatagram { len: Some(5), max: 4 });
+        }
+
+        let mut batch = RecvBatch::new(1, 4);
+        DatagramTransport::send_to(&sender, &[1, 2, 3, 4], &receiver_path).unwrap();
+        assert_eq!(receiver.recv_batch(&mut batch).unwrap(), 1);
+        assert_eq!(batch.get(0), Some((sender_path.clone(), &[1, 2, 3, 4][..])));
+        assert_eq!(batch.truncation(0), None);
+        DatagramTransport::send_to(&sender, &[5, 6, 7, 8, 9], &receiver_path).unwrap();
+        assert_eq!(receiver.recv_batch(&mut batch).unwrap(), 1);
+        assert_eq!(batch.get(0), Some((sender_path.clone(), &[5, 6, 7, 8][..])));
+        assert!(batch.truncation(0).is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
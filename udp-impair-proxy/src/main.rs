@@ -0,0 +1,142 @@
+use clap::{Parser, ValueEnum};
+use std::net::SocketAddr;
+use std::time::Duration;
+use udp_core::{GilbertElliott, ImpairmentConfig, ImpairmentProxy, LogFormat};
+
+/// 施加损伤的方向
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Direction {
+    /// 客户端到上游
+    Up,
+    /// 上游到客户端
+    Down,
+    Both,
+}
+
+/// 命令行参数解析
+#[derive(Parser, Debug)]
+#[command(name = "udp-impair-proxy")]
+#[command(about = "UDP 链路损伤代理，模拟丢包、延迟、乱序、重复、误码和带宽限制", long_about = None)]
+struct Args {
+    /// 监听端口
+    #[arg(short, long)]
+    port: u16,
+
+    /// 上游地址 (如 127.0.0.1:12345)
+    #[arg(short, long)]
+    upstream: SocketAddr,
+
+    /// 施加损伤的方向
+    #[arg(long, value_enum, default_value_t = Direction::Both)]
+    direction: Direction,
+
+    /// 随机丢包率 (0.0 ~ 1.0)
+    #[arg(long, default_value_t = 0.0)]
+    loss: f64,
+
+    /// 突发丢包：每个数据报从好状态进入坏状态的概率，坏状态下全部丢包
+    #[arg(long, requires = "burst_exit")]
+    burst_enter: Option<f64>,
+
+    /// 突发丢包：每个数据报从坏状态恢复的概率
+    #[arg(long, requires = "burst_enter")]
+    burst_exit: Option<f64>,
+
+    /// 固定延迟（毫秒）
+    #[arg(long, default_value_t = 0)]
+    delay_ms: u64,
+
+    /// 延迟抖动（毫秒），实际延迟在 delay ± jitter 内均匀分布
+    #[arg(long, default_value_t = 0)]
+    jitter_ms: u64,
+
+    /// 乱序概率：数据报被额外滞留 reorder-gap-ms
+    #[arg(long, default_value_t = 0.0)]
+    reorder: f64,
+
+    /// 乱序数据报的额外滞留时间（毫秒）
+    #[arg(long, default_value_t = 10)]
+    reorder_gap_ms: u64,
+
+    /// 重复发送概率
+    #[arg(long, default_value_t = 0.0)]
+    duplicate: f64,
+
+    /// 翻转一个随机比特的概率
+    #[arg(long, default_value_t = 0.0)]
+    corrupt: f64,
+
+    /// 带宽限制（千比特/秒）
+    #[arg(long)]
+    rate_kbps: Option<u64>,
+
+    /// 随机数种子，相同种子和相同流量可复现同样的损伤
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// 客户端会话空闲超时（秒）
+    #[arg(long, default_value_t = 60)]
+    session_timeout: u64,
+
+    /// 日志输出格式（text 或 json），级别由 RUST_LOG 控制
+    #[arg(long, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+fn probability(name: &str, value: f64) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("--{} 必须在 0.0 到 1.0 之间", name))
+    }
+}
+
+fn impairment(args: &Args) -> Result<ImpairmentConfig, String> {
+    let mut config = ImpairmentConfig::new()
+        .with_loss(probability("loss", args.loss)?)
+        .with_delay(Duration::from_millis(args.delay_ms), Duration::from_millis(args.jitter_ms))
+        .with_reorder(probability("reorder", args.reorder)?, Duration::from_millis(args.reorder_gap_ms))
+        .with_duplicate(probability("duplicate", args.duplicate)?)
+        .with_corrupt(probability("corrupt", args.corrupt)?);
+    if let (Some(enter), Some(exit)) = (args.burst_enter, args.burst_exit) {
+        config = config.with_burst_loss(GilbertElliott::new(
+            probability("burst-enter", enter)?,
+            probability("burst-exit", exit)?,
+        ));
+    }
+    if let Some(rate) = args.rate_kbps {
+        config = config.with_bandwidth(rate * 1000 / 8);
+    }
+    if let Some(seed) = args.seed {
+        config = config.with_seed(seed);
+    }
+    Ok(config)
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    udp_core::logging::init(args.log_format);
+    let config = impairment(&args).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // 未施加损伤的方向直接转发
+    let (uplink, downlink) = match args.direction {
+        Direction::Up => (config, ImpairmentConfig::new()),
+        Direction::Down => (ImpairmentConfig::new(), config),
+        Direction::Both => (config.clone(), config),
+    };
+    let handle = ImpairmentProxy::bind(args.port, args.upstream)?
+        .with_uplink(uplink)
+        .with_downlink(downlink)
+        .with_session_timeout(Duration::from_secs(args.session_timeout))
+        .start()?;
+    tracing::info!(
+        port = args.port,
+        upstream = %args.upstream,
+        uplink_seed = handle.uplink_seed(),
+        downlink_seed = handle.downlink_seed(),
+        "impairment proxy started"
+    );
+
+    handle.join().expect("代理线程异常退出");
+    Ok(())
+}
@@ -1,10 +1,12 @@
-use udp_core::UdpClient;
+use udp_core::pacer::{parse_rate_mbps, parse_rate_pps};
+use udp_core::{LogFormat, Pacer, UdpClient};
 use clap::Parser;
 use std::net::SocketAddr;
 use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
 use std::thread;
 use std::time::{Duration, Instant};
 use rand::{RngCore, rngs::OsRng};
+use tracing::{debug, error, info};
 
 #[derive(Parser, Clone, Debug)]
 #[command(name = "udp-loop")]
@@ -19,10 +21,26 @@ struct Args {
     /// 并发线程数
     #[arg(short = 'n', long, default_value_t = 4)]
     threads: usize,
+    /// 限制所有线程的总发送速率（包/秒）
+    #[arg(long, conflicts_with = "rate_mbps", value_parser = parse_rate_pps)]
+    rate_pps: Option<f64>,
+    /// 限制所有线程的总发送速率（兆比特/秒）
+    #[arg(long, value_parser = parse_rate_mbps)]
+    rate_mbps: Option<f64>,
+    /// 限速时允许的突发包数
+    #[arg(long, default_value_t = 1)]
+    burst: u32,
+    /// 日志输出格式（text 或 json），级别由 RUST_LOG 控制
+    #[arg(long, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 }
 
+// 数据包大小
+const PACKET_SIZE: usize = 4096;
+
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
+    udp_core::logging::init(args.log_format);
     let target: SocketAddr = args.addr.parse().expect("Invalid address format");
 
     let start_time = Instant::now();
@@ -30,19 +48,28 @@ fn main() -> std::io::Result<()> {
     let total_bytes = Arc::new(AtomicU64::new(0));
     let error_count = Arc::new(AtomicU64::new(0));
 
+    // 所有线程共享同一个限速器，限制总速率
+    let pacer = Pacer::from_rates(args.rate_pps, args.rate_mbps, args.burst, PACKET_SIZE).map(Arc::new);
     let mut handles = Vec::new();
 
-    for _ in 0..args.threads {
+    for index in 0..args.threads {
         let total_sent = total_sent.clone();
         let total_bytes = total_bytes.clone();
         let error_count = error_count.clone();
         let args = args.clone();
         let start_time = start_time.clone();
         let target = target.clone();
+        let pacer = pacer.clone();
 
         handles.push(thread::spawn(move || {
-            let client = UdpClient::new().expect("创建UDP socket失败");
-            println!("线程绑定端口: {}", client.local_addr().unwrap());
+            let mut client = UdpClient::new().expect("创建UDP socket失败");
+            if let Some(pacer) = pacer {
+                client = client.with_pacer(pacer);
+            }
+            let thread_span = tracing::info_span!("worker", thread = index);
+            let _thread = thread_span.enter();
+            info!(local_addr = %client.local_addr().unwrap(), peer = %target, "client bound");
+            let mut seq = 0u64;
             loop {
                 if let Some(duration) = args.duration {
                     if start_time.elapsed() >= Duration::from_secs(duration) {
@@ -50,25 +77,31 @@ fn main() -> std::io::Result<()> {
                     }
                 }
 
-                let mut data = vec![0u8; 4096];
+                let mut data = vec![0u8; PACKET_SIZE];
                 OsRng.fill_bytes(&mut data);
 
+                let span = tracing::debug_span!("request", peer = %target, seq);
+                let _enter = span.enter();
+                seq += 1;
+
                 match client.send_and_receive(target, &data, Duration::from_secs(1)) {
                     Ok(resp) => {
                         if resp != data {
                             error_count.fetch_add(1, Ordering::Relaxed);
                             if !args.ignore_errors {
-                                eprintln!("数据校验错误 (多端口多线程)");
+                                error!("echo payload mismatch");
                                 break;
                             }
+                            debug!("echo payload mismatch, ignored");
                         }
                     }
                     Err(e) => {
                         error_count.fetch_add(1, Ordering::Relaxed);
                         if !args.ignore_errors {
-                            eprintln!("发送/接收失败: {}", e);
+                            error!(error = %e, "send/receive failed");
                             break;
                         }
+                        debug!(error = %e, "send/receive failed, ignored");
                     }
                 }
                 total_sent.fetch_add(1, Ordering::Relaxed);
@@ -80,11 +113,15 @@ fn main() -> std::io::Result<()> {
     for h in handles { let _ = h.join(); }
 
     let elapsed = start_time.elapsed().as_secs_f64();
-    println!("=== 测试完成 (多端口多线程) ===");
-    println!("总包数: {}", total_sent.load(Ordering::Relaxed));
-    println!("错误包数: {}", error_count.load(Ordering::Relaxed));
-    println!("总发送数据: {:.2} MB", total_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0);
-    println!("平均带宽: {:.2} MB/s", total_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0 / elapsed);
+    let total_mb = total_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0;
+    info!(
+        threads = args.threads,
+        packets = total_sent.load(Ordering::Relaxed),
+        errors = error_count.load(Ordering::Relaxed),
+        total_mb = format_args!("{:.2}", total_mb),
+        avg_mb_per_sec = format_args!("{:.2}", total_mb / elapsed),
+        "test finished"
+    );
 
     Ok(())
 }
\ No newline at end of file
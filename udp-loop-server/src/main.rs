@@ -1,7 +1,9 @@
-use udp_core::UdpServer;
+use udp_core::{LogFormat, ReplySender, UdpServer};
 use clap::Parser;
+use std::net::SocketAddr;
 use std::time::Duration;
 use std::thread;
+use tracing::{info, warn};
 
 /// 命令行参数解析
 #[derive(Parser, Debug)]
@@ -15,29 +17,70 @@ struct Args {
     /// 是否启用延迟响应（用于测试 RTT）
     #[arg(long)]
     delay_ms: Option<u64>,
+
+    /// 处理线程数，同一客户端的数据始终由同一个线程处理
+    #[arg(short, long, default_value_t = 1)]
+    workers: usize,
+
+    /// 使用 SO_REUSEPORT 在同一端口打开多个 socket，每个 socket 独立接收（仅 Linux）
+    #[arg(short, long, default_value_t = 1)]
+    sockets: usize,
+
+    /// 日志输出格式（text 或 json），级别由 RUST_LOG 控制
+    #[arg(long, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 }
 
-fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+// 回显处理函数
+fn echo(delay_ms: Option<u64>, data: &[u8], reply: &ReplySender) {
+    if let Some(delay) = delay_ms {
+        thread::sleep(Duration::from_millis(delay)); // 模拟响应延迟
+    }
 
-    let server = UdpServer::bind(args.port)?;
-    println!("UDP Echo Server 已启动，监听端口 {}", args.port);
+    // 将收到的数据直接回传
+    if let Err(e) = reply.send(data) {
+        warn!(peer = %reply.peer(), error = %e, "echo reply failed");
+    }
+}
 
-    let socket = server.socket.try_clone()?; // 为线程使用 clone 一个 socket 实例
+#[cfg(target_os = "linux")]
+fn run_reuseport(args: &Args) -> std::io::Result<()> {
+    use udp_core::ReusePortServer;
 
-    server.start_async(move |src_addr, data| {
-        if let Some(delay) = args.delay_ms {
-            thread::sleep(Duration::from_millis(delay)); // 模拟响应延迟
-        }
+    let server = ReusePortServer::bind(args.port, args.sockets)?;
+    info!(port = args.port, sockets = args.sockets, "echo server started");
 
-        // 将收到的数据直接回传
-        if let Err(e) = socket.send_to(data, src_addr) {
-            eprintln!("发送回应失败: {}", e);
-        }
+    let delay_ms = args.delay_ms;
+    let handle = server.start(|_index| {
+        move |_src_addr: SocketAddr, data: &[u8], reply: &ReplySender| echo(delay_ms, data, reply)
     })?;
 
-    // 主线程保持运行
-    loop {
-        thread::sleep(Duration::from_secs(10));
+    handle.join().expect("服务器线程异常退出");
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_reuseport(_args: &Args) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "多 socket 模式仅支持 Linux"))
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    udp_core::logging::init(args.log_format);
+
+    if args.sockets > 1 {
+        return run_reuseport(&args);
     }
+
+    let server = UdpServer::bind(args.port)?;
+    info!(port = args.port, workers = args.workers, "echo server started");
+
+    let delay_ms = args.delay_ms;
+    let handle = server.start_pool(args.workers, move |_src_addr: SocketAddr, data: &[u8], reply: &ReplySender| {
+        echo(delay_ms, data, reply)
+    })?;
+
+    // 主线程等待服务器线程退出
+    handle.join().expect("服务器线程异常退出");
+    Ok(())
 }
@@ -1,8 +1,11 @@
-use udp_core::UdpClient;
+use udp_core::pacer::{parse_rate_mbps, parse_rate_pps};
+use udp_core::{LogFormat, Pacer, UdpClient};
 use clap::Parser;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use rand::{RngCore, rngs::OsRng};
+use tracing::{debug, error, info};
 // use hex::encode;
 
 /// 命令行参数解析
@@ -21,14 +24,37 @@ struct Args {
     /// 测试持续时间，单位秒（默认无限）
     #[arg(short, long)]
     duration: Option<u64>,
+
+    /// 限制发送速率（包/秒）
+    #[arg(long, conflicts_with = "rate_mbps", value_parser = parse_rate_pps)]
+    rate_pps: Option<f64>,
+
+    /// 限制发送速率（兆比特/秒）
+    #[arg(long, value_parser = parse_rate_mbps)]
+    rate_mbps: Option<f64>,
+
+    /// 限速时允许的突发包数
+    #[arg(long, default_value_t = 1)]
+    burst: u32,
+
+    /// 日志输出格式（text 或 json），级别由 RUST_LOG 控制
+    #[arg(long, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 }
 
+// 数据包大小
+const PACKET_SIZE: usize = 4096;
+
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
+    udp_core::logging::init(args.log_format);
     let target: SocketAddr = args.addr.parse().expect("Invalid address format");
 
-    let client = UdpClient::new()?;
-    println!("本地绑定端口: {}", client.local_addr()?);
+    let mut client = UdpClient::new()?;
+    if let Some(pacer) = Pacer::from_rates(args.rate_pps, args.rate_mbps, args.burst, PACKET_SIZE).map(Arc::new) {
+        client = client.with_pacer(pacer);
+    }
+    info!(local_addr = %client.local_addr()?, peer = %target, "client bound");
 
     let start_time = Instant::now();
     let mut total_sent = 0u64;
@@ -43,9 +69,12 @@ fn main() -> std::io::Result<()> {
         }
 
         // 生成 4KB 随机数据
-        let mut data = vec![0u8; 4096];
+        let mut data = vec![0u8; PACKET_SIZE];
         OsRng.fill_bytes(&mut data);
 
+        let span = tracing::debug_span!("request", peer = %target, seq = total_sent);
+        let _enter = span.enter();
+
         // 发送并等待回复
         match client.send_and_receive(target, &data, Duration::from_secs(1)) {
             Ok(resp) => {
@@ -53,17 +82,19 @@ fn main() -> std::io::Result<()> {
                     // println!("send:{} recv:{}", encode(&data), encode(&resp));
                     error_count += 1;
                     if !args.ignore_errors {
-                        eprintln!("数据校验错误");
+                        error!("echo payload mismatch");
                         break;
                     }
+                    debug!("echo payload mismatch, ignored");
                 }
             }
             Err(e) => {
                 error_count += 1;
                 if !args.ignore_errors {
-                    eprintln!("发送/接收失败: {}", e);
+                    error!(error = %e, "send/receive failed");
                     break;
                 }
+                debug!(error = %e, "send/receive failed, ignored");
             }
         }
 
@@ -72,12 +103,14 @@ fn main() -> std::io::Result<()> {
     }
 
     let elapsed = start_time.elapsed().as_secs_f64();
-    println!("=== 测试完成 ===");
-    println!("总包数: {}", total_sent);
-    println!("错误包数: {}", error_count);
-    println!("总发送数据: {:.2} MB", total_bytes as f64 / 1024.0 / 1024.0);
-    println!("平均带宽: {:.2} MB/s", total_bytes as f64 / 1024.0 / 1024.0 / elapsed);
-
+    let total_mb = total_bytes as f64 / 1024.0 / 1024.0;
+    info!(
+        packets = total_sent,
+        errors = error_count,
+        total_mb = format_args!("{:.2}", total_mb),
+        avg_mb_per_sec = format_args!("{:.2}", total_mb / elapsed),
+        "test finished"
+    );
 
     Ok(())
 }
@@ -0,0 +1,125 @@
+// control.rs
+use crate::layer1::Layer1Protocol;
+use crate::types::{CheckType, FrameType, Priority, ProtocolError, ProtocolResult};
+
+// 定义控制消息类型，位于控制帧负载的第一个字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlKind {
+    Data = 0,
+    Ack = 1,
+}
+
+// 控制消息，承载在 FrameType::Control 的第一层负载中
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlFrame {
+    /// 可靠通道的数据段，序列号使用第一层的 frame_seq_number
+    Data(Vec<u8>),
+    /// 选择确认：cumulative 之前的序列号全部已收到，
+    /// sack_bitmap 第 i 位表示 cumulative + 1 + i 已收到
+    Ack { cumulative: u16, sack_bitmap: u64 },
+}
+
+impl ControlFrame {
+    pub fn kind(&self) -> ControlKind {
+        match self {
+            ControlFrame::Data(_) => ControlKind::Data,
+            ControlFrame::Ack { .. } => ControlKind::Ack,
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![self.kind() as u8];
+        match self {
+            ControlFrame::Data(data) => buf.extend_from_slice(data),
+            ControlFrame::Ack { cumulative, sack_bitmap } => {
+                buf.extend_from_slice(&cumulative.to_le_bytes());
+                buf.extend_from_slice(&sack_bitmap.to_le_bytes());
+            }
+        }
+        buf
+    }
+
+    pub fn deserialize(buf: &[u8]) -> ProtocolResult<Self> {
+        if buf.is_empty() {
+            return Err(ProtocolError::InvalidLength);
+        }
+        match buf[0] {
+            0 => Ok(ControlFrame::Data(buf[1..].to_vec())),
+            1 => {
+                if buf.len() != 11 {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(ControlFrame::Ack {
+                    cumulative: u16::from_le_bytes(buf[1..3].try_into().unwrap()),
+                    sack_bitmap: u64::from_le_bytes(buf[3..11].try_into().unwrap()),
+                })
+            }
+            _ => Err(ProtocolError::UnknownCommandType),
+        }
+    }
+
+    /// 封装为完整的第一层控制帧
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: Priority::Medium,
+            check_type: CheckType::CheckSum,
+            frame_type: FrameType::Control,
+            frame_seq_number: seq,
+            frame_length: 0, // 序列化时计算填充
+            payload: self.serialize(),
+            checksum: 0, // 序列化时计算填充
+        }
+        .serialize()
+    }
+
+    /// 从完整的第一层帧中解析控制消息，返回帧序列号和消息
+    pub fn decode(buf: &[u8]) -> ProtocolResult<(u16, Self)> {
+        let layer1 = Layer1Protocol::deserialize(buf)?;
+        if layer1.frame_type != FrameType::Control {
+            return Err(ProtocolError::UnsupportedFrameType);
+        }
+        Ok((layer1.frame_seq_number, Self::deserialize(&layer1.payload)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_frame_round_trip() {
+        let data = ControlFrame::Data(vec![0x01, 0x02, 0x03]);
+        let (seq, decoded) = ControlFrame::decode(&data.encode(42)).unwrap();
+        assert_eq!(seq, 42);
+        assert_eq!(decoded, data);
+
+        let ack = ControlFrame::Ack { cumulative: 0xFFF0, sack_bitmap: 0b1011 };
+        let (_, decoded) = ControlFrame::decode(&ack.encode(0)).unwrap();
+        assert_eq!(decoded, ack);
+    }
+
+    #[test]
+    fn test_control_frame_errors() {
+        assert!(ControlFrame::deserialize(&[]).is_err());
+        assert!(ControlFrame::deserialize(&[0x01, 0x00]).is_err());
+        assert!(ControlFrame::deserialize(&[0x7F]).is_err());
+
+        // 非控制帧应该被拒绝
+        let layer1 = Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: Priority::Low,
+            check_type: CheckType::CheckSum,
+            frame_type: FrameType::Type0,
+            frame_seq_number: 1,
+            frame_length: 0,
+            payload: vec![0x00],
+            checksum: 0,
+        };
+        assert!(ControlFrame::decode(&layer1.serialize()).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+// discovery.rs
+// 设备发现：请求和响应都是 TLV 协议帧，命令码为 DISCOVERY_COMMAND。
+// 请求通过广播或组播发送，设备按类型和组过滤后回复自己的类型、序号、固件版本和能力。
+use crate::layer1::Layer1Protocol;
+use crate::layer2::Layer2Protocol;
+use crate::layer3::TlvProtocol;
+use crate::types::{
+    CheckType, DeviceType, FrameType, Priority, ProtocolError, ProtocolResult, ReqRsp, RequestBodyType,
+};
+use std::fmt;
+
+/// 设备发现的 TLV 命令码
+pub const DISCOVERY_COMMAND: u32 = 0x0000_F001;
+/// 请求中表示“所有设备”的设备序号
+pub const ANY_DEVICE_INDEX: u16 = 0xFFFF;
+// 请求负载中表示不按设备类型过滤
+const ANY_DEVICE_TYPE: u8 = 0xFF;
+// 响应负载长度：nonce(4) + 固件版本(4) + 能力(4)
+const RESPONSE_LENGTH: usize = 12;
+
+/// 设备能力位
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    pub const REGISTER: Capabilities = Capabilities(1 << 0);
+    pub const TLV: Capabilities = Capabilities(1 << 1);
+    pub const RELIABLE: Capabilities = Capabilities(1 << 2);
+    pub const MULTICAST: Capabilities = Capabilities(1 << 3);
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (Capabilities::REGISTER, "register"),
+            (Capabilities::TLV, "tlv"),
+            (Capabilities::RELIABLE, "reliable"),
+            (Capabilities::MULTICAST, "multicast"),
+        ];
+        let known: Vec<&str> = names
+            .iter()
+            .filter(|(cap, _)| self.contains(*cap))
+            .map(|(_, name)| *name)
+            .collect();
+        let unknown = self.0 & !names.iter().fold(0, |acc, (cap, _)| acc | cap.0);
+        match (known.is_empty(), unknown) {
+            (true, 0) => write!(f, "-"),
+            (_, 0) => write!(f, "{}", known.join(",")),
+            (true, unknown) => write!(f, "{:#x}", unknown),
+            (false, unknown) => write!(f, "{},{:#x}", known.join(","), unknown),
+        }
+    }
+}
+
+/// 固件版本
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u16,
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// 设备发现请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveryRequest {
+    /// 随机数，响应原样带回，用于区分不同的扫描
+    pub nonce: u32,
+    /// 只让指定类型的设备回复，None 表示所有类型
+    pub device_type: Option<DeviceType>,
+    /// 只让同组设备回复，全零表示所有组
+    pub group: [u8; 8],
+}
+
+/// 设备发现响应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveryResponse {
+    pub nonce: u32,
+    pub device_type: DeviceType,
+    pub device_index: u16,
+    pub group: [u8; 8],
+    pub firmware: FirmwareVersion,
+    pub capabilities: Capabilities,
+}
+
+fn device_type_from_u8(value: u8) -> ProtocolResult<DeviceType> {
+    match value {
+        0x00 => Ok(DeviceType::FPGA),
+        0x01 => Ok(DeviceType::MCU),
+        0x02 => Ok(DeviceType::NetworkPort),
+        0x03 => Ok(DeviceType::OpticalPort),
+        _ => Err(ProtocolError::UnsupportedDeviceType),
+    }
+}
+
+// 把命令封装为完整的第一层 TLV 帧，发现和心跳共用
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn encode_tlv(
+    command: u32,
+    priority: Priority,
+    seq: u16,
+    req_rsp: ReqRsp,
+    device_type: DeviceType,
+    device_index: u16,
+    group: [u8; 8],
+    data: Vec<u8>,
+) -> Vec<u8> {
+    let layer2 = Layer2Protocol {
+        req_rsp,
+        is_need_reply: req_rsp == ReqRsp::Request,
+        code: false,
+        flag: false,
+        request_body_type: RequestBodyType::TlvProtocol,
+        device_type,
+        device_index,
+        group,
+        payload: TlvProtocol::new(command, 0, data).serialize(),
+    };
+    Layer1Protocol {
+        frame_delimiter_0: 0x55,
+        frame_delimiter_1: 0xBB,
+        version: 1,
+        priority,
+        check_type: CheckType::CheckSum,
+        frame_type: FrameType::Type0,
+        frame_seq_number: seq,
+        frame_length: 0, // 序列化时计算填充
+        payload: layer2.serialize(),
+        checksum: 0, // 序列化时计算填充
+    }
+    .serialize()
+}
+
+// 解析完整的第一层帧，返回第二层和命令负载；不是指定命令时返回 UnknownCommandType
+pub(crate) fn decode_tlv(buf: &[u8], command: u32, req_rsp: ReqRsp) -> ProtocolResult<(Layer2Protocol, Vec<u8>)> {
+    let layer1 = Layer1Protocol::deserialize(buf)?;
+    let layer2 = Layer2Protocol::deserialize(&layer1.payload)?;
+    if layer2.request_body_type != RequestBodyType::TlvProtocol || layer2.req_rsp != req_rsp {
+        return Err(ProtocolError::UnknownCommandType);
+    }
+    let tlv = TlvProtocol::deserialize(&layer2.payload)?;
+    if tlv.command_code != command {
+        return Err(ProtocolError::UnknownCommandType);
+    }
+    Ok((layer2, tlv.user_data))
+}
+
+impl DiscoveryRequest {
+    pub fn new(nonce: u32) -> Self {
+        DiscoveryRequest {
+            nonce,
+            device_type: None,
+            group: [0; 8],
+        }
+    }
+
+    /// 封装为完整的第一层帧
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        let mut data = self.nonce.to_le_bytes().to_vec();
+        data.push(self.device_type.map_or(ANY_DEVICE_TYPE, |t| t as u8));
+        encode_tlv(
+            DISCOVERY_COMMAND,
+            Priority::Low,
+            seq,
+            ReqRsp::Request,
+            self.device_type.unwrap_or(DeviceType::FPGA),
+            ANY_DEVICE_INDEX,
+            self.group,
+            data,
+        )
+    }
+
+    /// 从完整的第一层帧中解析发现请求
+    pub fn decode(buf: &[u8]) -> ProtocolResult<Self> {
+        let (layer2, data) = decode_tlv(buf, DISCOVERY_COMMAND, ReqRsp::Request)?;
+        if data.len() != 5 {
+            return Err(ProtocolError::InvalidPayload);
+        }
+        let device_type = match data[4] {
+            ANY_DEVICE_TYPE => None,
+            value => Some(device_type_from_u8(value)?),
+        };
+        Ok(DiscoveryRequest {
+            nonce: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            device_type,
+            group: layer2.group,
+        })
+    }
+
+    /// 设备是否应该回复这个请求
+    pub fn matches(&self, device_type: DeviceType, group: &[u8; 8]) -> bool {
+        self.device_type.is_none_or(|t| t == device_type) && (self.group == [0; 8] || &self.group == group)
+    }
+}
+
+impl DiscoveryResponse {
+    /// 封装为完整的第一层帧
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RESPONSE_LENGTH);
+        data.extend_from_slice(&self.nonce.to_le_bytes());
+        data.push(self.firmware.major);
+        data.push(self.firmware.minor);
+        data.extend_from_slice(&self.firmware.patch.to_le_bytes());
+        data.extend_from_slice(&self.capabilities.0.to_le_bytes());
+        encode_tlv(
+            DISCOVERY_COMMAND,
+            Priority::Low,
+            seq,
+            ReqRsp::Response,
+            self.device_type,
+            self.device_index,
+            self.group,
+            data,
+        )
+    }
+
+    /// 从完整的第一层帧中解析发现响应
+    pub fn decode(buf: &[u8]) -> ProtocolResult<Self> {
+        let (layer2, data) = decode_tlv(buf, DISCOVERY_COMMAND, ReqRsp::Response)?;
+        if data.len() != RESPONSE_LENGTH {
+            return Err(ProtocolError::InvalidPayload);
+        }
+        Ok(DiscoveryResponse {
+            nonce: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            device_type: layer2.device_type,
+            device_index: layer2.device_index,
+            group: layer2.group,
+            firmware: FirmwareVersion {
+                major: data[4],
+                minor: data[5],
+                patch: u16::from_le_bytes(data[6..8].try_into().unwrap()),
+            },
+            capabilities: Capabilities(u32::from_le_bytes(data[8..12].try_into().unwrap())),
+        })
+    }
+
+    /// 对请求生成响应帧，请求不匹配本设备时返回 None
+    pub fn reply_to(&self, request: &DiscoveryRequest, seq: u16) -> Option<Vec<u8>> {
+        if !request.matches(self.device_type, &self.group) {
+            return None;
+        }
+        Some(DiscoveryResponse { nonce: request.nonce, ..*self }.encode(seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> DiscoveryResponse {
+        DiscoveryResponse {
+            nonce: 0,
+            device_type: DeviceType::MCU,
+            device_index: 3,
+            group: [1, 0, 0, 0, 0, 0, 0, 0],
+            firmware: FirmwareVersion { major: 2, minor: 1, patch: 300 },
+            capabilities: Capabilities::REGISTER | Capabilities::TLV,
+        }
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = DiscoveryRequest {
+            nonce: 0xDEADBEEF,
+            device_type: Some(DeviceType::OpticalPort),
+            group: [9; 8],
+        };
+        assert_eq!(DiscoveryRequest::decode(&request.encode(7)).unwrap(), request);
+
+        let any = DiscoveryRequest::new(1);
+        assert_eq!(DiscoveryRequest::decode(&any.encode(1)).unwrap(), any);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let response = DiscoveryResponse { nonce: 42, ..device() };
+        let decoded = DiscoveryResponse::decode(&response.encode(1)).unwrap();
+        assert_eq!(decoded, response);
+        assert_eq!(decoded.firmware.to_string(), "2.1.300");
+        assert_eq!(decoded.capabilities.to_string(), "register,tlv");
+        // 请求和响应不能互相解析
+        assert!(DiscoveryRequest::decode(&response.encode(1)).is_err());
+    }
+
+    #[test]
+    fn test_reply_filters() {
+        let device = device();
+        let mut request = DiscoveryRequest::new(5);
+        let reply = device.reply_to(&request, 1).unwrap();
+        assert_eq!(DiscoveryResponse::decode(&reply).unwrap().nonce, 5);
+
+        request.device_type = Some(DeviceType::FPGA);
+        assert!(device.reply_to(&request, 1).is_none());
+
+        request.device_type = Some(DeviceType::MCU);
+        request.group = [2; 8];
+        assert!(device.reply_to(&request, 1).is_none());
+        request.group = device.group;
+        assert!(device.reply_to(&request, 1).is_some());
+    }
+}
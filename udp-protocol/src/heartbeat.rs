@@ -0,0 +1,140 @@
+// heartbeat.rs
+// 心跳：请求和响应都是 TLV 协议帧，命令码为 HEARTBEAT_COMMAND，帧优先级为高。
+// 请求携带递增的计数，设备原样带回计数并附上自定义的状态字，监控端据此匹配回复并计算往返时间。
+use crate::discovery::{decode_tlv, encode_tlv};
+use crate::types::{DeviceType, Priority, ProtocolError, ProtocolResult, ReqRsp};
+
+/// 心跳的 TLV 命令码
+pub const HEARTBEAT_COMMAND: u32 = 0x0000_F002;
+// 响应负载长度：计数(4) + 状态字(4)
+const RESPONSE_LENGTH: usize = 8;
+
+/// 心跳请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatRequest {
+    pub device_type: DeviceType,
+    pub device_index: u16,
+    /// 请求计数，响应原样带回
+    pub counter: u32,
+}
+
+/// 心跳响应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatResponse {
+    pub device_type: DeviceType,
+    pub device_index: u16,
+    pub counter: u32,
+    /// 设备自定义的状态字，0 表示正常
+    pub status: u32,
+}
+
+impl HeartbeatRequest {
+    pub fn new(device_type: DeviceType, device_index: u16, counter: u32) -> Self {
+        HeartbeatRequest {
+            device_type,
+            device_index,
+            counter,
+        }
+    }
+
+    /// 封装为完整的第一层帧
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        encode_tlv(
+            HEARTBEAT_COMMAND,
+            Priority::High,
+            seq,
+            ReqRsp::Request,
+            self.device_type,
+            self.device_index,
+            [0; 8],
+            self.counter.to_le_bytes().to_vec(),
+        )
+    }
+
+    /// 从完整的第一层帧中解析心跳请求
+    pub fn decode(buf: &[u8]) -> ProtocolResult<Self> {
+        let (layer2, data) = decode_tlv(buf, HEARTBEAT_COMMAND, ReqRsp::Request)?;
+        let counter = data.try_into().map_err(|_| ProtocolError::InvalidPayload)?;
+        Ok(HeartbeatRequest {
+            device_type: layer2.device_type,
+            device_index: layer2.device_index,
+            counter: u32::from_le_bytes(counter),
+        })
+    }
+
+    /// 生成对这个请求的响应帧
+    pub fn reply(&self, status: u32, seq: u16) -> Vec<u8> {
+        HeartbeatResponse {
+            device_type: self.device_type,
+            device_index: self.device_index,
+            counter: self.counter,
+            status,
+        }
+        .encode(seq)
+    }
+}
+
+impl HeartbeatResponse {
+    /// 封装为完整的第一层帧
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RESPONSE_LENGTH);
+        data.extend_from_slice(&self.counter.to_le_bytes());
+        data.extend_from_slice(&self.status.to_le_bytes());
+        encode_tlv(
+            HEARTBEAT_COMMAND,
+            Priority::High,
+            seq,
+            ReqRsp::Response,
+            self.device_type,
+            self.device_index,
+            [0; 8],
+            data,
+        )
+    }
+
+    /// 从完整的第一层帧中解析心跳响应
+    pub fn decode(buf: &[u8]) -> ProtocolResult<Self> {
+        let (layer2, data) = decode_tlv(buf, HEARTBEAT_COMMAND, ReqRsp::Response)?;
+        if data.len() != RESPONSE_LENGTH {
+            return Err(ProtocolError::InvalidPayload);
+        }
+        Ok(HeartbeatResponse {
+            device_type: layer2.device_type,
+            device_index: layer2.device_index,
+            counter: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            status: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DiscoveryRequest;
+    use crate::layer1::Layer1Protocol;
+
+    #[test]
+    fn test_heartbeat_round_trip() {
+        let request = HeartbeatRequest::new(DeviceType::FPGA, 2, 0x01020304);
+        let frame = request.encode(9);
+        assert_eq!(HeartbeatRequest::decode(&frame).unwrap(), request);
+        assert_eq!(Layer1Protocol::deserialize(&frame).unwrap().priority, Priority::High);
+
+        let response = HeartbeatResponse::decode(&request.reply(7, 9)).unwrap();
+        assert_eq!(response.counter, request.counter);
+        assert_eq!(response.status, 7);
+        assert_eq!((response.device_type, response.device_index), (DeviceType::FPGA, 2));
+    }
+
+    #[test]
+    fn test_heartbeat_rejects_other_frames() {
+        let request = HeartbeatRequest::new(DeviceType::MCU, 1, 5);
+        // 请求和响应不能互相解析，发现帧也不是心跳
+        assert!(HeartbeatResponse::decode(&request.encode(1)).is_err());
+        assert!(HeartbeatRequest::decode(&request.reply(0, 1)).is_err());
+        assert!(matches!(
+            HeartbeatRequest::decode(&DiscoveryRequest::new(5).encode(1)),
+            Err(ProtocolError::UnknownCommandType)
+        ));
+    }
+}
@@ -35,9 +35,8 @@ impl Layer1Protocol {
         // 封装Payload
         buf.extend_from_slice(&self.payload);
 
-        // 计算并填充Frame Length
-        let payload_length = self.payload.len() as u16;
-        let frame_length_value = payload_length + 2;  // 加上校验和长度
+        // 计算并填充Frame Length（加上校验和长度）；负载超过 65533 字节时长度饱和，接收方会按长度不符拒绝
+        let frame_length_value = u16::try_from(self.payload.len() + 2).unwrap_or(u16::MAX);
         buf[8..10].copy_from_slice(&frame_length_value.to_le_bytes());
 
         let sum = calc_checksum(&buf);
@@ -68,13 +67,15 @@ impl Layer1Protocol {
         let frame_type = match buf[5] {
             0 => FrameType::Type0,
             1 => FrameType::Type1,
+            2 => FrameType::Control,
             _ => return Err(ProtocolError::UnsupportedFrameType),
         };
         let frame_seq_number = u16::from_le_bytes(buf[6..8].try_into().unwrap());
         let frame_length = u16::from_le_bytes(buf[8..10].try_into().unwrap());
         // 验证帧长度是否合法
-        let expected_total_length = frame_length + 10; // 帧头10字节 + Frame Length定义的长度（Payload + 校验和）
-        if buf.len() as u16 != expected_total_length {
+        // 帧头10字节 + Frame Length定义的长度（Payload + 校验和），按 usize 比较避免溢出
+        let expected_total_length = frame_length as usize + 10;
+        if frame_length < 2 || buf.len() != expected_total_length {
             return Err(ProtocolError::InvalidLength);
         }
 
@@ -139,4 +140,33 @@ mod tests {
         assert_eq!(deserialized.frame_seq_number, layer1.frame_seq_number);
         assert_eq!(deserialized.payload, layer1.payload);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_layer1_rejects_bad_length() {
+        // Frame Length 为 0xFFFF：帧头加长度超过 u16 也不能溢出
+        let frame = [0x55, 0xBB, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00];
+        assert!(matches!(Layer1Protocol::deserialize(&frame), Err(ProtocolError::InvalidLength)));
+        // Frame Length 小于校验和长度
+        let frame = [0x55, 0xBB, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00];
+        assert!(Layer1Protocol::deserialize(&frame).is_err());
+    }
+
+    #[test]
+    fn test_layer1_oversized_payload() {
+        let layer1 = Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: Priority::Low,
+            check_type: CheckType::CheckSum,
+            frame_type: FrameType::Type0,
+            frame_seq_number: 0,
+            frame_length: 0,
+            payload: vec![0; u16::MAX as usize],
+            checksum: 0,
+        };
+        // 长度字段饱和，不会 panic，接收方拒绝
+        let serialized = layer1.serialize();
+        assert!(matches!(Layer1Protocol::deserialize(&serialized), Err(ProtocolError::InvalidLength)));
+    }
+}
@@ -1,6 +1,6 @@
 // layer3.rs
 
-use crate::types::{ProtocolError, ProtocolResult};
+use crate::types::{ProtocolError, ProtocolResult, RequestBodyType};
 use std::marker::PhantomData;
 
 // 协议类型标记
@@ -166,7 +166,7 @@ impl TlvProtocol {
     }
 
     pub fn deserialize(buf: &[u8]) -> ProtocolResult<Self> {
-        if buf.len() < 6 {
+        if buf.len() < 8 {
             return Err(ProtocolError::InvalidLength);
         }
 
@@ -224,6 +224,23 @@ pub enum ProtocolBody {
     Tlv(TlvProtocol),
 }
 
+impl ProtocolBody {
+    // 消息体对应的第二层请求体类型
+    pub fn request_body_type(&self) -> RequestBodyType {
+        match self {
+            ProtocolBody::Register(_) => RequestBodyType::RegisterProtocol,
+            ProtocolBody::Tlv(_) => RequestBodyType::TlvProtocol,
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            ProtocolBody::Register(reg) => reg.serialize(),
+            ProtocolBody::Tlv(tlv) => tlv.serialize(),
+        }
+    }
+}
+
 
 // 自测试接口
 #[cfg(test)]
@@ -4,11 +4,21 @@ pub mod utils;
 pub mod layer1;
 pub mod layer2;
 pub mod layer3;
+pub mod control;
+pub mod discovery;
+pub mod heartbeat;
+pub mod transfer;
+pub mod stream;
 
 // 导出需要公开的类型和函数
 pub use crate::layer1::{Layer1Protocol, FrameType, Priority, CheckType};
 pub use crate::layer2::{Layer2Protocol, ReqRsp, DeviceType, RequestBodyType};
 pub use crate::layer3::{Layer3Payload, ProtocolBody, RegisterProtocol, TlvProtocol};
+pub use crate::control::{ControlFrame, ControlKind};
+pub use crate::discovery::{Capabilities, DiscoveryRequest, DiscoveryResponse, FirmwareVersion};
+pub use crate::heartbeat::{HeartbeatRequest, HeartbeatResponse};
+pub use crate::transfer::TransferFrame;
+pub use crate::stream::FrameDecoder;
 
 use crate::types::ProtocolResult;
 
@@ -84,39 +94,60 @@ pub fn encapsulate_data(
 
 // 解包函数，从第一层开始解析到第三层
 pub fn decapsulate_data(buf: &[u8]) -> Option<(Layer1Protocol, Layer2Protocol, ProtocolBody)> {
+    try_decapsulate_data(buf).ok()
+}
+
+// 解包函数，失败时返回具体的错误
+pub fn try_decapsulate_data(buf: &[u8]) -> ProtocolResult<(Layer1Protocol, Layer2Protocol, ProtocolBody)> {
     // 解析第一层协议
-    let layer1_result = Layer1Protocol::deserialize(buf);
-    if layer1_result.is_err() {
-        return None;
-    }
-    let layer1 = layer1_result.unwrap();
+    let layer1 = Layer1Protocol::deserialize(buf)?;
 
     // 解析第二层协议
-    let layer2_result = Layer2Protocol::deserialize(&layer1.payload);
-    if layer2_result.is_err() {
-        return None;
-    }
-    let layer2 = layer2_result.unwrap();
+    let layer2 = Layer2Protocol::deserialize(&layer1.payload)?;
 
     // 解析第三层协议
-    let layer3_result: ProtocolResult<ProtocolBody>;
     // 这里比较关键，首先调用了接口本身的解析方法返回了当前的数据结构，然后又使用map将其转化为泛型 P
-    match layer2.request_body_type {
+    let layer3 = match layer2.request_body_type {
         RequestBodyType::RegisterProtocol => {
-            layer3_result = Layer3Payload::<RegisterProtocol>::deserialize(&layer2.payload)
-                .map(|p| { ProtocolBody::Register(p.body)});
+            Layer3Payload::<RegisterProtocol>::deserialize(&layer2.payload)
+                .map(|p| { ProtocolBody::Register(p.body)})
         }
         RequestBodyType::TlvProtocol => {
-            layer3_result = Layer3Payload::<TlvProtocol>::deserialize(&layer2.payload)
-                .map(|p| { ProtocolBody::Tlv(p.body)});
+            Layer3Payload::<TlvProtocol>::deserialize(&layer2.payload)
+                .map(|p| { ProtocolBody::Tlv(p.body)})
         }
-    }
-    if layer3_result.is_err() {
-        return None;
-    }
-    let layer3 = layer3_result.unwrap();
+    }?;
 
-    Some((layer1, layer2, layer3))
+    Ok((layer1, layer2, layer3))
+}
+
+// 按请求帧封装响应：沿用请求的帧类型、优先级、序列号和设备寻址，请求体类型由 body 决定
+pub fn encapsulate_reply(request_l1: &Layer1Protocol, request_l2: &Layer2Protocol, body: &ProtocolBody) -> Vec<u8> {
+    let layer2 = Layer2Protocol {
+        req_rsp: ReqRsp::Response,
+        is_need_reply: false,
+        code: request_l2.code,
+        flag: request_l2.flag,
+        request_body_type: body.request_body_type(),
+        device_type: request_l2.device_type,
+        device_index: request_l2.device_index,
+        group: request_l2.group,
+        payload: body.serialize(),
+    };
+
+    Layer1Protocol {
+        frame_delimiter_0: 0x55,
+        frame_delimiter_1: 0xBB,
+        version: request_l1.version,
+        priority: request_l1.priority,
+        check_type: request_l1.check_type,
+        frame_type: request_l1.frame_type,
+        frame_seq_number: request_l1.frame_seq_number,
+        frame_length: 0, // 序列化时计算填充
+        payload: layer2.serialize(),
+        checksum: 0, // 序列化时计算填充
+    }
+    .serialize()
 }
 
 
@@ -242,4 +273,59 @@ mod tests {
         let result = decapsulate_data(&invalid_data);
         assert!(result.is_none(), "对无效数据解封装应该失败");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reply_mirrors_request() {
+        let request = encapsulate_data(
+            FrameType::Type1,
+            Priority::High,
+            CheckType::CheckSum,
+            ReqRsp::Request,
+            DeviceType::OpticalPort,
+            7,
+            RequestBodyType::RegisterProtocol,
+            [0x03; 8],
+            0x1000,
+            0,
+            vec![0xAA],
+        );
+        let (layer1, layer2, _) = try_decapsulate_data(&request).unwrap();
+
+        let body = ProtocolBody::Tlv(TlvProtocol::new(0x42, 0, vec![1, 2, 3]));
+        let reply = encapsulate_reply(&layer1, &layer2, &body);
+        let (reply_l1, reply_l2, reply_body) = try_decapsulate_data(&reply).unwrap();
+
+        assert_eq!(reply_l1.frame_seq_number, layer1.frame_seq_number);
+        assert_eq!(reply_l1.priority, Priority::High);
+        assert_eq!(reply_l2.req_rsp, ReqRsp::Response);
+        assert_eq!(reply_l2.device_type, DeviceType::OpticalPort);
+        assert_eq!(reply_l2.device_index, 7);
+        assert_eq!(reply_l2.request_body_type, RequestBodyType::TlvProtocol);
+        assert_eq!(reply_body, body);
+    }
+
+    #[test]
+    fn test_try_decapsulate_reports_error() {
+        let err = try_decapsulate_data(&[0x55, 0xBB, 0x01]).unwrap_err();
+        assert_eq!(err.name(), "InvalidLength");
+
+        // TLV 头不足 8 字节时返回错误而不是越界
+        let request = encapsulate_data(
+            FrameType::Type0,
+            Priority::Low,
+            CheckType::CheckSum,
+            ReqRsp::Request,
+            DeviceType::MCU,
+            1,
+            RequestBodyType::TlvProtocol,
+            [0; 8],
+            0x01,
+            0,
+            vec![],
+        );
+        let mut layer1 = Layer1Protocol::deserialize(&request).unwrap();
+        layer1.payload.truncate(12 + 6);
+        let err = try_decapsulate_data(&layer1.serialize()).unwrap_err();
+        assert_eq!(err.name(), "InvalidLength");
+    }
+}
@@ -0,0 +1,164 @@
+// stream.rs
+// 字节流分帧：串口、TCP 等流式链路没有数据报边界，按 0x55 0xBB 帧头、Frame Length 和校验和
+// 从字节流中切出完整的第一层帧。遇到噪声或损坏的帧时逐字节向后寻找下一个帧头重新同步。
+use crate::utils::verify_checksum;
+
+/// 帧分隔符
+pub const FRAME_DELIMITER: [u8; 2] = [0x55, 0xBB];
+// 帧头长度（分隔符到 Frame Length）
+const HEADER_LEN: usize = 10;
+/// 默认的最大帧长度，即 Frame Length 能表示的最大帧
+pub const DEFAULT_MAX_FRAME_LEN: usize = HEADER_LEN + u16::MAX as usize;
+
+/// 流式帧解码器
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    max_frame_len: usize,
+    discarded: u64,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        FrameDecoder {
+            buf: Vec::new(),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            discarded: 0,
+        }
+    }
+
+    // 声明长度超过 max_frame_len 的帧头视为噪声，避免错误的长度让解码器长时间等待
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len.max(HEADER_LEN + 2);
+        self
+    }
+
+    // 追加收到的字节
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    // 取出下一个完整且校验通过的帧；数据不足时返回 None，已缓存的字节保留到下次
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            self.skip_to_delimiter();
+            if self.buf.len() < HEADER_LEN {
+                return None;
+            }
+
+            let frame_length = u16::from_le_bytes([self.buf[8], self.buf[9]]) as usize;
+            let total = HEADER_LEN + frame_length;
+            if frame_length < 2 || total > self.max_frame_len {
+                self.discard(1);
+                continue;
+            }
+            if self.buf.len() < total {
+                return None;
+            }
+
+            let checksum = u16::from_le_bytes([self.buf[total - 2], self.buf[total - 1]]);
+            if !verify_checksum(&self.buf[..total - 2], checksum) {
+                self.discard(1);
+                continue;
+            }
+            return Some(self.buf.drain(..total).collect());
+        }
+    }
+
+    // 重新同步时丢弃的字节数
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    // 已缓存、尚未组成完整帧的字节数
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    // 丢弃帧头之前的字节；末尾的单个 0x55 可能是下一个帧头的开始，保留
+    fn skip_to_delimiter(&mut self) {
+        let start = self
+            .buf
+            .windows(2)
+            .position(|w| w == FRAME_DELIMITER)
+            .unwrap_or_else(|| self.buf.len() - usize::from(self.buf.last() == Some(&FRAME_DELIMITER[0])));
+        self.discard(start);
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buf.drain(..count);
+        self.discarded += count as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CheckType, FrameType, Layer1Protocol, Priority};
+
+    fn frame(seq: u16, payload: &[u8]) -> Vec<u8> {
+        Layer1Protocol {
+            frame_delimiter_0: 0x55,
+            frame_delimiter_1: 0xBB,
+            version: 1,
+            priority: Priority::Medium,
+            check_type: CheckType::CheckSum,
+            frame_type: FrameType::Type0,
+            frame_seq_number: seq,
+            frame_length: 0,
+            payload: payload.to_vec(),
+            checksum: 0,
+        }
+        .serialize()
+    }
+
+    #[test]
+    fn test_frames_split_across_chunks() {
+        let mut stream = frame(1, b"first");
+        stream.extend(frame(2, b"second"));
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for byte in &stream {
+            decoder.push(std::slice::from_ref(byte));
+            frames.extend(decoder.next_frame());
+        }
+        assert_eq!(frames, vec![frame(1, b"first"), frame(2, b"second")]);
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_resync_after_noise_and_corruption() {
+        let mut corrupted = frame(2, b"bad");
+        corrupted[11] ^= 0x01;
+        let mut stream = vec![0x00, 0x55, 0x12, 0x55];
+        stream.extend(frame(1, b"ok"));
+        stream.extend(&corrupted);
+        // 长度超出上限的假帧头
+        stream.extend([0x55, 0xBB, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF]);
+        stream.extend(frame(3, b"ok again"));
+
+        let mut decoder = FrameDecoder::new().with_max_frame_len(256);
+        decoder.push(&stream);
+        assert_eq!(decoder.next_frame(), Some(frame(1, b"ok")));
+        assert_eq!(decoder.next_frame(), Some(frame(3, b"ok again")));
+        assert_eq!(decoder.next_frame(), None);
+        assert_eq!(decoder.discarded() as usize, 4 + corrupted.len() + 10);
+    }
+
+    #[test]
+    fn test_keeps_partial_delimiter() {
+        let full = frame(7, b"tail");
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&[0x01, 0x02, 0x55]);
+        assert_eq!(decoder.next_frame(), None);
+        assert_eq!(decoder.buffered(), 1);
+        decoder.push(&full[1..]);
+        assert_eq!(decoder.next_frame(), Some(full));
+    }
+}
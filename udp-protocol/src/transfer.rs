@@ -0,0 +1,272 @@
+// transfer.rs
+// 文件传输：所有消息都是命令码为 TRANSFER_COMMAND 的 TLV 协议帧，TLV 数据第一个字节是消息类型。
+// 发送方发出 Offer/Chunk/Finish 请求，接收方以 Ack/Complete 响应；
+// 每次传输有随机的传输 ID，双方据此丢弃上一次传输遗留的帧。
+use crate::discovery::{decode_tlv, encode_tlv};
+use crate::types::{DeviceType, Priority, ProtocolError, ProtocolResult, ReqRsp};
+
+/// 文件传输的 TLV 命令码
+pub const TRANSFER_COMMAND: u32 = 0x0000_F003;
+/// 单个数据块允许的最大长度，保证整帧不超过 8KB 的接收缓冲区
+pub const MAX_CHUNK_SIZE: usize = 4096;
+/// 文件名允许的最大字节数
+pub const MAX_NAME_LENGTH: usize = 255;
+/// SHA-256 摘要长度
+pub const DIGEST_LENGTH: usize = 32;
+
+// 文件传输不针对具体设备，帧中的设备字段固定填写
+const TRANSFER_DEVICE_TYPE: DeviceType = DeviceType::NetworkPort;
+const TRANSFER_DEVICE_INDEX: u16 = 0;
+
+/// 文件传输消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferFrame {
+    /// 发起传输：文件名、总字节数和数据块大小
+    Offer {
+        transfer_id: u32,
+        size: u64,
+        chunk_size: u16,
+        name: String,
+    },
+    /// 第 index 个数据块，偏移为 index * chunk_size
+    Chunk { transfer_id: u32, index: u32, data: Vec<u8> },
+    /// 所有数据块都已确认，附上整个文件的 SHA-256
+    Finish {
+        transfer_id: u32,
+        sha256: [u8; DIGEST_LENGTH],
+    },
+    /// 确认：next 之前的数据块全部已收到，sack_bitmap 第 i 位表示 next + 1 + i 已收到。
+    /// 对 Offer 的确认 next 为 0
+    Ack { transfer_id: u32, next: u32, sack_bitmap: u64 },
+    /// 对 Finish 的响应，verified 表示接收方计算的摘要与发送方一致
+    Complete { transfer_id: u32, verified: bool },
+}
+
+impl TransferFrame {
+    pub fn transfer_id(&self) -> u32 {
+        match self {
+            TransferFrame::Offer { transfer_id, .. }
+            | TransferFrame::Chunk { transfer_id, .. }
+            | TransferFrame::Finish { transfer_id, .. }
+            | TransferFrame::Ack { transfer_id, .. }
+            | TransferFrame::Complete { transfer_id, .. } => *transfer_id,
+        }
+    }
+
+    // 发送方发出的消息是请求，接收方发出的是响应
+    pub fn req_rsp(&self) -> ReqRsp {
+        match self {
+            TransferFrame::Offer { .. } | TransferFrame::Chunk { .. } | TransferFrame::Finish { .. } => {
+                ReqRsp::Request
+            }
+            TransferFrame::Ack { .. } | TransferFrame::Complete { .. } => ReqRsp::Response,
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            TransferFrame::Offer { .. } => 0,
+            TransferFrame::Chunk { .. } => 1,
+            TransferFrame::Finish { .. } => 2,
+            TransferFrame::Ack { .. } => 3,
+            TransferFrame::Complete { .. } => 4,
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![self.kind()];
+        buf.extend_from_slice(&self.transfer_id().to_le_bytes());
+        match self {
+            TransferFrame::Offer { size, chunk_size, name, .. } => {
+                buf.extend_from_slice(&size.to_le_bytes());
+                buf.extend_from_slice(&chunk_size.to_le_bytes());
+                buf.extend_from_slice(name.as_bytes());
+            }
+            TransferFrame::Chunk { index, data, .. } => {
+                buf.extend_from_slice(&index.to_le_bytes());
+                buf.extend_from_slice(data);
+            }
+            TransferFrame::Finish { sha256, .. } => buf.extend_from_slice(sha256),
+            TransferFrame::Ack { next, sack_bitmap, .. } => {
+                buf.extend_from_slice(&next.to_le_bytes());
+                buf.extend_from_slice(&sack_bitmap.to_le_bytes());
+            }
+            TransferFrame::Complete { verified, .. } => buf.push(*verified as u8),
+        }
+        buf
+    }
+
+    pub fn deserialize(buf: &[u8]) -> ProtocolResult<Self> {
+        if buf.len() < 5 {
+            return Err(ProtocolError::InvalidLength);
+        }
+        let transfer_id = u32::from_le_bytes(buf[1..5].try_into().unwrap());
+        let body = &buf[5..];
+        match buf[0] {
+            0 => {
+                if body.len() < 10 || body.len() - 10 > MAX_NAME_LENGTH {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                let chunk_size = u16::from_le_bytes(body[8..10].try_into().unwrap());
+                if chunk_size == 0 || chunk_size as usize > MAX_CHUNK_SIZE {
+                    return Err(ProtocolError::InvalidPayload);
+                }
+                let name = String::from_utf8(body[10..].to_vec()).map_err(|_| ProtocolError::InvalidPayload)?;
+                Ok(TransferFrame::Offer {
+                    transfer_id,
+                    size: u64::from_le_bytes(body[0..8].try_into().unwrap()),
+                    chunk_size,
+                    name,
+                })
+            }
+            1 => {
+                if body.len() < 4 || body.len() - 4 > MAX_CHUNK_SIZE {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(TransferFrame::Chunk {
+                    transfer_id,
+                    index: u32::from_le_bytes(body[0..4].try_into().unwrap()),
+                    data: body[4..].to_vec(),
+                })
+            }
+            2 => Ok(TransferFrame::Finish {
+                transfer_id,
+                sha256: body.try_into().map_err(|_| ProtocolError::InvalidLength)?,
+            }),
+            3 => {
+                if body.len() != 12 {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(TransferFrame::Ack {
+                    transfer_id,
+                    next: u32::from_le_bytes(body[0..4].try_into().unwrap()),
+                    sack_bitmap: u64::from_le_bytes(body[4..12].try_into().unwrap()),
+                })
+            }
+            4 => match body {
+                [verified] => Ok(TransferFrame::Complete {
+                    transfer_id,
+                    verified: *verified != 0,
+                }),
+                _ => Err(ProtocolError::InvalidLength),
+            },
+            _ => Err(ProtocolError::UnknownCommandType),
+        }
+    }
+
+    /// 封装为完整的第一层帧
+    pub fn encode(&self, seq: u16) -> Vec<u8> {
+        encode_tlv(
+            TRANSFER_COMMAND,
+            Priority::Medium,
+            seq,
+            self.req_rsp(),
+            TRANSFER_DEVICE_TYPE,
+            TRANSFER_DEVICE_INDEX,
+            [0; 8],
+            self.serialize(),
+        )
+    }
+
+    /// 从完整的第一层帧中解析文件传输消息，消息类型必须与帧的请求/响应方向一致
+    pub fn decode(buf: &[u8]) -> ProtocolResult<Self> {
+        let (req_rsp, data) = match decode_tlv(buf, TRANSFER_COMMAND, ReqRsp::Request) {
+            Ok((_, data)) => (ReqRsp::Request, data),
+            Err(ProtocolError::UnknownCommandType) => {
+                (ReqRsp::Response, decode_tlv(buf, TRANSFER_COMMAND, ReqRsp::Response)?.1)
+            }
+            Err(e) => return Err(e),
+        };
+        let frame = Self::deserialize(&data)?;
+        if frame.req_rsp() != req_rsp {
+            return Err(ProtocolError::UnknownCommandType);
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HeartbeatRequest;
+
+    #[test]
+    fn test_transfer_frame_round_trip() {
+        let frames = [
+            TransferFrame::Offer {
+                transfer_id: 7,
+                size: 1 << 33,
+                chunk_size: 1024,
+                name: "固件.bin".to_string(),
+            },
+            TransferFrame::Chunk {
+                transfer_id: 7,
+                index: 0x0102_0304,
+                data: vec![0xAA; MAX_CHUNK_SIZE],
+            },
+            TransferFrame::Finish {
+                transfer_id: 7,
+                sha256: [0x5A; DIGEST_LENGTH],
+            },
+            TransferFrame::Ack {
+                transfer_id: 7,
+                next: 12,
+                sack_bitmap: 0b101,
+            },
+            TransferFrame::Complete {
+                transfer_id: 7,
+                verified: true,
+            },
+        ];
+        for frame in frames {
+            assert_eq!(TransferFrame::decode(&frame.encode(3)).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn test_transfer_frame_errors() {
+        // 块过大、块大小为 0、摘要长度不对
+        let mut chunk = TransferFrame::Chunk {
+            transfer_id: 1,
+            index: 0,
+            data: Vec::new(),
+        }
+        .serialize();
+        chunk.extend_from_slice(&[0; MAX_CHUNK_SIZE + 1]);
+        assert!(TransferFrame::deserialize(&chunk).is_err());
+        let mut offer = TransferFrame::Offer {
+            transfer_id: 1,
+            size: 0,
+            chunk_size: 1,
+            name: String::new(),
+        }
+        .serialize();
+        offer[13..15].copy_from_slice(&0u16.to_le_bytes());
+        assert!(matches!(TransferFrame::deserialize(&offer), Err(ProtocolError::InvalidPayload)));
+        assert!(TransferFrame::deserialize(&[2, 0, 0, 0, 0, 1]).is_err());
+        assert!(TransferFrame::deserialize(&[9, 0, 0, 0, 0]).is_err());
+
+        // 方向不对的帧和其他命令的帧
+        let offer = TransferFrame::Offer {
+            transfer_id: 1,
+            size: 0,
+            chunk_size: 1,
+            name: String::new(),
+        };
+        let forged = encode_tlv(
+            TRANSFER_COMMAND,
+            Priority::Medium,
+            0,
+            ReqRsp::Response,
+            TRANSFER_DEVICE_TYPE,
+            TRANSFER_DEVICE_INDEX,
+            [0; 8],
+            offer.serialize(),
+        );
+        assert!(matches!(TransferFrame::decode(&forged), Err(ProtocolError::UnknownCommandType)));
+        assert!(matches!(
+            TransferFrame::decode(&HeartbeatRequest::new(DeviceType::MCU, 0, 1).encode(0)),
+            Err(ProtocolError::UnknownCommandType)
+        ));
+    }
+}
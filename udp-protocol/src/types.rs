@@ -40,6 +40,27 @@ impl fmt::Display for ProtocolError {
     }
 }
 
+impl ProtocolError {
+    // 错误种类名称，用于按种类统计
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProtocolError::InvalidChecksum => "InvalidChecksum",
+            ProtocolError::InvalidHeader => "InvalidHeader",
+            ProtocolError::InvalidLength => "InvalidLength",
+            ProtocolError::UnknownCommandType => "UnknownCommandType",
+            ProtocolError::InvalidPayload => "InvalidPayload",
+            ProtocolError::ChecksumMismatch => "ChecksumMismatch",
+            ProtocolError::UnsupportedPriority => "UnsupportedPriority",
+            ProtocolError::UnsupportedCheckType => "UnsupportedCheckType",
+            ProtocolError::UnsupportedFrameType => "UnsupportedFrameType",
+            ProtocolError::InvalidFrameLength => "InvalidFrameLength",
+            ProtocolError::UnsupportedRequestBodyType => "UnsupportedRequestBodyType",
+            ProtocolError::UnsupportedDeviceType => "UnsupportedDeviceType",
+            ProtocolError::Other(_) => "Other",
+        }
+    }
+}
+
 impl std::error::Error for ProtocolError {}
 
 // 定义设备类型枚举
@@ -87,5 +108,7 @@ pub enum Priority {
 pub enum FrameType {
     Type0 = 0,
     Type1 = 1,
+    // 控制帧，负载为 control 模块定义的控制消息（确认、可靠数据段等）
+    Control = 2,
     // 可以根据实际情况扩展其他帧类型
 }
\ No newline at end of file
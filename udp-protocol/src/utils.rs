@@ -2,9 +2,9 @@
 
 /// 计算简单的校验和（所有字节相加的反码加一）
 pub fn calc_checksum(data: &[u8]) -> u16 {
-    // 创建迭代器针对迭代器中所有的属性求和
-    let sum: u16 = data.iter().map(|&b| b as u16).sum();
-    (!sum as u16).wrapping_add(1)
+    // 按字节累加，超过 16 位时回绕
+    let sum = data.iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+    (!sum).wrapping_add(1)
 }
 
 /// 验证数据和校验和是否一致
@@ -23,6 +23,15 @@ mod tests {
         assert!(verify_checksum(&data, checksum));
     }
 
+    #[test]
+    fn test_checksum_wraps() {
+        // 长负载的累加和超过 16 位
+        let data = vec![0xFF; 4096];
+        let checksum = calc_checksum(&data);
+        assert_eq!(checksum, (!((0xFFu32 * 4096) as u16)).wrapping_add(1));
+        assert!(verify_checksum(&data, checksum));
+    }
+
     #[test]
     fn test_invalid_checksum() {
         let data = [0x10, 0x20, 0x30];